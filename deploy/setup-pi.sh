@@ -6,6 +6,8 @@ set -e
 
 GITHUB_REPO="bboynton97/rx7-headunit"  # UPDATE THIS
 INSTALL_DIR="/opt/headunit"
+# Account the app service runs as, which needs the hardware groups and power-off rights
+SERVICE_USER="headunit"
 
 echo "=== RX7 Headunit Setup ==="
 echo ""
@@ -24,9 +26,29 @@ sudo apt-get install -y \
     libayatana-appindicator3-1 \
     librsvg2-2 \
     libasound2 \
+    pulseaudio-utils \
+    wlopm \
     curl \
     jq
 
+id "$SERVICE_USER" > /dev/null 2>&1 || sudo useradd --create-home "$SERVICE_USER"
+
+# Touch and knob events wake the display, GPIO, I2C and SPI for the car interface, video for the reverse camera
+sudo usermod -aG input,gpio,i2c,spi,video "$SERVICE_USER"
+
+# SPI0 for the shift light strip with no chip select or MISO, so GPIO7-9 stay
+# free for the headlights. SPI1 for the lighting strip with CE0 moved off
+# GPIO18, the backlight PWM. See src-tauri/src/commands/pins.rs.
+BOOT_CONFIG=/boot/firmware/config.txt
+[ -f "$BOOT_CONFIG" ] || BOOT_CONFIG=/boot/config.txt
+for line in "dtparam=spi=on" "dtoverlay=spi0-0cs,no_miso" "dtoverlay=spi1-1cs,cs0_pin=16"; do
+    grep -qxF "$line" "$BOOT_CONFIG" || echo "$line" | sudo tee -a "$BOOT_CONFIG" > /dev/null
+done
+# Each strip frame is sent as one SPI transfer, which spidev caps at 4 KiB by
+# default, about 450 LEDs. Raise it on the kernel command line.
+CMDLINE="$(dirname "$BOOT_CONFIG")/cmdline.txt"
+grep -q "spidev.bufsiz=" "$CMDLINE" || sudo sed -i '1 s/$/ spidev.bufsiz=65536/' "$CMDLINE"
+
 # Create install directory
 echo "[2/6] Creating install directory..."
 sudo mkdir -p "$INSTALL_DIR"
@@ -63,7 +85,8 @@ WantedBy=multi-user.target
 EOF
 
 # Headunit app service
-sudo tee /etc/systemd/system/headunit.service > /dev/null << 'EOF'
+SERVICE_HOME="$(getent passwd "$SERVICE_USER" | cut -d: -f6)"
+sudo tee /etc/systemd/system/headunit.service > /dev/null << EOF
 [Unit]
 Description=RX7 Headunit Application
 After=graphical.target
@@ -71,12 +94,12 @@ Wants=graphical.target
 
 [Service]
 Type=simple
-User=headunit
+User=$SERVICE_USER
 Environment=DISPLAY=:0
 Environment=WAYLAND_DISPLAY=wayland-0
-Environment=XDG_RUNTIME_DIR=/run/user/1000
+Environment=XDG_RUNTIME_DIR=/run/user/$(id -u "$SERVICE_USER")
 # Use installed binary if exists, otherwise fall back to local build
-ExecStart=/bin/bash -c 'test -x /usr/bin/headunit && exec /usr/bin/headunit || exec /home/headunit/rx7-headunit/src-tauri/target/release/headunit || exec /home/headunit/rx7-headunit/src-tauri/target/debug/headunit'
+ExecStart=/bin/bash -c 'test -x /usr/bin/headunit && exec /usr/bin/headunit || exec $SERVICE_HOME/rx7-headunit/src-tauri/target/release/headunit || exec $SERVICE_HOME/rx7-headunit/src-tauri/target/debug/headunit'
 Restart=always
 RestartSec=3
 
@@ -84,6 +107,31 @@ RestartSec=3
 WantedBy=graphical.target
 EOF
 
+# Let the app power the Pi off when the key is turned off
+sudo tee /etc/polkit-1/rules.d/50-headunit-poweroff.rules > /dev/null << EOF
+polkit.addRule(function(action, subject) {
+    if (action.id == "org.freedesktop.login1.power-off" && subject.user == "$SERVICE_USER") {
+        return polkit.Result.YES;
+    }
+});
+EOF
+
+# Release the power-hold latch once filesystems are unmounted, so the supply
+# is only cut after a clean shutdown. The pin is read from the app's power
+# settings at shutdown, so changing hold_pin in the app needs no setup rerun.
+sudo tee /usr/lib/systemd/system-shutdown/headunit-power-hold > /dev/null << EOF
+#!/bin/sh
+config="$SERVICE_HOME/.config/headunit/power.json"
+EOF
+sudo tee -a /usr/lib/systemd/system-shutdown/headunit-power-hold > /dev/null << 'EOF'
+[ "$1" = "poweroff" ] || exit 0
+[ -f "$config" ] && grep -q '"enabled": true' "$config" || exit 0
+pin=$(sed -n 's/.*"hold_pin": \([0-9][0-9]*\).*/\1/p' "$config")
+[ -n "$pin" ] && pinctrl set "$pin" op dl
+exit 0
+EOF
+sudo chmod +x /usr/lib/systemd/system-shutdown/headunit-power-hold
+
 # Enable services
 echo "[5/6] Enabling services..."
 sudo systemctl daemon-reload
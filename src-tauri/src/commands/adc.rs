@@ -0,0 +1,365 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, Mutex, Weak};
+
+#[cfg(target_os = "linux")]
+use rppal::{
+    i2c::I2c,
+    spi::{Bus, Mode, SlaveSelect, Spi},
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AdcModel {
+    /// 10-bit, 8 channels on SPI0. Needs SPI0's chip selects and MISO, which
+    /// the default setup gives the shift light strip instead, see `pins`.
+    Mcp3008,
+    /// 16-bit, 4 channels on I2C
+    Ads1115,
+}
+
+impl AdcModel {
+    pub fn channels(self) -> u8 {
+        match self {
+            AdcModel::Mcp3008 => 8,
+            AdcModel::Ads1115 => 4,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct AdcSettings {
+    pub model: AdcModel,
+    /// SPI0 chip enable the MCP3008 is wired to
+    pub chip_select: u8,
+    /// I2C address of the ADS1115, None for 0x48
+    pub address: Option<u16>,
+    /// MCP3008 reference voltage, usually the 3.3 V rail
+    pub vref: f32,
+    /// ADS1115 input range in volts, one of 6.144, 4.096, 2.048, 1.024, 0.512
+    /// or 0.256. The smallest that covers the senders gives the finest steps.
+    pub full_scale: f32,
+}
+
+impl Default for AdcSettings {
+    fn default() -> Self {
+        Self {
+            model: AdcModel::Ads1115,
+            chip_select: 0,
+            address: None,
+            vref: 3.3,
+            full_scale: 4.096,
+        }
+    }
+}
+
+impl AdcSettings {
+    /// Highest voltage the ADC can tell apart from the one below it
+    pub fn full_scale_volts(&self) -> f32 {
+        match self.model {
+            AdcModel::Mcp3008 => self.vref,
+            AdcModel::Ads1115 => self.full_scale,
+        }
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        if self.model == AdcModel::Ads1115 && ads1115_gain(self.full_scale).is_none() {
+            return Err(format!(
+                "The ADS1115 has no ±{} V range, use 6.144, 4.096, 2.048, 1.024, 0.512 or 0.256",
+                self.full_scale
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub trait Adc: Send {
+    fn read_volts(&mut self, channel: u8) -> Result<f32, Box<dyn Error + Send + Sync>>;
+
+    /// Input range for the reads after this, on chips with a programmable gain
+    fn set_full_scale(&mut self, _volts: f32) {}
+}
+
+#[cfg(target_os = "linux")]
+pub struct Mcp3008 {
+    spi: Spi,
+    vref: f32,
+}
+
+#[cfg(target_os = "linux")]
+impl Mcp3008 {
+    // Well under the 1.35 MHz limit at 2.7 V
+    const CLOCK_HZ: u32 = 1_000_000;
+
+    pub fn new(chip_select: u8, vref: f32) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let slave_select = match chip_select {
+            0 => SlaveSelect::Ss0,
+            1 => SlaveSelect::Ss1,
+            _ => return Err(format!("SPI0 has no chip select {}", chip_select).into()),
+        };
+        Ok(Mcp3008 {
+            spi: Spi::new(Bus::Spi0, slave_select, Self::CLOCK_HZ, Mode::Mode0)?,
+            vref,
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Adc for Mcp3008 {
+    fn read_volts(&mut self, channel: u8) -> Result<f32, Box<dyn Error + Send + Sync>> {
+        let mut response = [0u8; 3];
+        self.spi.transfer(&mut response, &mcp3008_request(channel))?;
+        Ok(mcp3008_volts(response, self.vref))
+    }
+}
+
+/// Start bit, then single-ended mode and the channel in the next byte's top nibble
+pub fn mcp3008_request(channel: u8) -> [u8; 3] {
+    [0x01, 0x80 | (channel & 0x07) << 4, 0x00]
+}
+
+pub fn mcp3008_volts(response: [u8; 3], vref: f32) -> f32 {
+    let raw = ((response[1] & 0x03) as u16) << 8 | response[2] as u16;
+    raw as f32 / 1023.0 * vref
+}
+
+#[cfg(target_os = "linux")]
+pub struct Ads1115 {
+    i2c: I2c,
+    full_scale: f32,
+}
+
+#[cfg(target_os = "linux")]
+impl Ads1115 {
+    const DEFAULT_ADDRESS: u16 = 0x48;
+    const REG_CONVERSION: u8 = 0x00;
+    const REG_CONFIG: u8 = 0x01;
+    // Conversion takes ~1.2 ms at 860 SPS
+    const MAX_POLLS: usize = 10;
+
+    pub fn new(address: Option<u16>, full_scale: f32) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut i2c = I2c::new()?;
+        i2c.set_slave_address(address.unwrap_or(Self::DEFAULT_ADDRESS))?;
+        Ok(Ads1115 { i2c, full_scale })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Adc for Ads1115 {
+    fn read_volts(&mut self, channel: u8) -> Result<f32, Box<dyn Error + Send + Sync>> {
+        let config = ads1115_config(channel, self.full_scale);
+        self.i2c.block_write(Self::REG_CONFIG, &config.to_be_bytes())?;
+
+        // The OS bit reads back as 1 once the conversion is done
+        for _ in 0..Self::MAX_POLLS {
+            std::thread::sleep(std::time::Duration::from_micros(500));
+            let mut config = [0u8; 2];
+            self.i2c.block_read(Self::REG_CONFIG, &mut config)?;
+            if u16::from_be_bytes(config) & 0x8000 != 0 {
+                let mut conversion = [0u8; 2];
+                self.i2c.block_read(Self::REG_CONVERSION, &mut conversion)?;
+                return Ok(ads1115_volts(i16::from_be_bytes(conversion), self.full_scale));
+            }
+        }
+        Err("ADS1115 conversion timed out".into())
+    }
+
+    fn set_full_scale(&mut self, volts: f32) {
+        self.full_scale = volts;
+    }
+}
+
+// Programmable gain amplifier settings, by full-scale range
+const ADS1115_RANGES: [(f32, u16); 6] = [
+    (6.144, 0x0000),
+    (4.096, 0x0200),
+    (2.048, 0x0400),
+    (1.024, 0x0600),
+    (0.512, 0x0800),
+    (0.256, 0x0A00),
+];
+
+fn ads1115_gain(full_scale: f32) -> Option<u16> {
+    ADS1115_RANGES
+        .iter()
+        .find(|(range, _)| (range - full_scale).abs() < 0.0005)
+        .map(|(_, bits)| *bits)
+}
+
+/// Single-shot read of AINx against ground, ±`full_scale` V range, 860 SPS, comparator off
+pub fn ads1115_config(channel: u8, full_scale: f32) -> u16 {
+    const START: u16 = 0x8000;
+    const SINGLE_ENDED: u16 = 0x4000;
+    const GAIN_4V096: u16 = 0x0200;
+    const SINGLE_SHOT: u16 = 0x0100;
+    const RATE_860SPS: u16 = 0x00E0;
+    const COMPARATOR_OFF: u16 = 0x0003;
+
+    let gain = ads1115_gain(full_scale).unwrap_or(GAIN_4V096);
+    START | SINGLE_ENDED | ((channel & 0x03) as u16) << 12 | gain | SINGLE_SHOT | RATE_860SPS | COMPARATOR_OFF
+}
+
+pub fn ads1115_volts(raw: i16, full_scale: f32) -> f32 {
+    // Single-ended readings can dip just below zero from offset error
+    (raw.max(0) as f32) * full_scale / 32768.0
+}
+
+/// Which chip a config points at. The MCP3008 key carries vref too, but each
+/// of its reads is a single SPI transfer so separate handles can't interleave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum AdcKey {
+    Mcp3008 { chip_select: u8, vref_bits: u32 },
+    Ads1115 { address: Option<u16> },
+}
+
+impl AdcKey {
+    fn of(config: &AdcSettings) -> Self {
+        match config.model {
+            AdcModel::Mcp3008 => AdcKey::Mcp3008 {
+                chip_select: config.chip_select,
+                vref_bits: config.vref.to_bits(),
+            },
+            AdcModel::Ads1115 => AdcKey::Ads1115 {
+                address: config.address,
+            },
+        }
+    }
+}
+
+type Device = Mutex<Box<dyn Adc>>;
+
+// Handles stay open while any module holds them, so reopening after a config
+// change gets a fresh device once the old one is dropped
+static OPEN: Lazy<Mutex<HashMap<AdcKey, Weak<Device>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// One module's handle on an ADC that others may be reading too. The device
+/// stays locked for the whole read, so an ADS1115's config write, polling and
+/// conversion read can't be split by another module's read.
+///
+/// Each module keeps its own input range, set on the device before every read.
+pub struct SharedAdc {
+    device: Arc<Device>,
+    full_scale: f32,
+}
+
+impl Adc for SharedAdc {
+    fn read_volts(&mut self, channel: u8) -> Result<f32, Box<dyn Error + Send + Sync>> {
+        let mut device = self.device.lock().map_err(|_| "ADC lock poisoned")?;
+        device.set_full_scale(self.full_scale);
+        device.read_volts(channel)
+    }
+
+    fn set_full_scale(&mut self, volts: f32) {
+        self.full_scale = volts;
+    }
+}
+
+fn shared(
+    key: AdcKey,
+    full_scale: f32,
+    open: impl FnOnce() -> Result<Box<dyn Adc>, Box<dyn Error + Send + Sync>>,
+) -> Result<SharedAdc, Box<dyn Error + Send + Sync>> {
+    let mut devices = OPEN.lock().map_err(|_| "ADC lock poisoned")?;
+    if let Some(device) = devices.get(&key).and_then(Weak::upgrade) {
+        return Ok(SharedAdc { device, full_scale });
+    }
+    let device: Arc<Device> = Arc::new(Mutex::new(open()?));
+    devices.insert(key, Arc::downgrade(&device));
+    Ok(SharedAdc { device, full_scale })
+}
+
+#[cfg(target_os = "linux")]
+fn open_device(config: &AdcSettings) -> Result<Box<dyn Adc>, Box<dyn Error + Send + Sync>> {
+    Ok(match config.model {
+        AdcModel::Mcp3008 => Box::new(Mcp3008::new(config.chip_select, config.vref)?),
+        AdcModel::Ads1115 => Box::new(Ads1115::new(config.address, config.full_scale)?),
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_device(_config: &AdcSettings) -> Result<Box<dyn Adc>, Box<dyn Error + Send + Sync>> {
+    Err("ADCs are only supported on Linux".into())
+}
+
+/// Open the configured ADC, sharing the handle with any module already using it
+pub fn open_adc(config: &AdcSettings) -> Result<Box<dyn Adc>, Box<dyn Error + Send + Sync>> {
+    config.check()?;
+    let device = shared(AdcKey::of(config), config.full_scale, || open_device(config))?;
+    Ok(Box::new(device))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mcp3008_framing() {
+        assert_eq!(mcp3008_request(0), [0x01, 0x80, 0x00]);
+        assert_eq!(mcp3008_request(5), [0x01, 0xD0, 0x00]);
+
+        // Only the low two bits of the second byte are data
+        assert_eq!(mcp3008_volts([0xFF, 0xFF, 0xFF], 3.3), 3.3);
+        assert!((mcp3008_volts([0x00, 0x02, 0x00], 3.3) - 1.6516).abs() < 0.001);
+    }
+
+    #[test]
+    fn ads1115_framing() {
+        assert_eq!(ads1115_config(0, 4.096), 0xC3E3);
+        assert_eq!(ads1115_config(3, 4.096), 0xF3E3);
+        assert_eq!(ads1115_config(0, 6.144), 0xC1E3);
+        assert_eq!(ads1115_config(0, 0.256), 0xCBE3);
+
+        assert_eq!(ads1115_volts(-5, 4.096), 0.0);
+        assert!((ads1115_volts(16000, 4.096) - 2.0).abs() < 0.001);
+        assert!((ads1115_volts(16000, 2.048) - 1.0).abs() < 0.001);
+
+        let mut config = AdcSettings::default();
+        assert_eq!(config.check(), Ok(()));
+        config.full_scale = 5.0;
+        assert!(config.check().is_err());
+        config.model = AdcModel::Mcp3008;
+        assert_eq!(config.full_scale_volts(), 3.3);
+    }
+
+    struct FakeAdc {
+        volts: f32,
+        full_scale: f32,
+    }
+
+    impl Adc for FakeAdc {
+        fn read_volts(&mut self, _channel: u8) -> Result<f32, Box<dyn Error + Send + Sync>> {
+            Ok(self.volts.min(self.full_scale))
+        }
+
+        fn set_full_scale(&mut self, volts: f32) {
+            self.full_scale = volts;
+        }
+    }
+
+    #[test]
+    fn modules_share_one_handle_per_device() {
+        let key = AdcKey::Ads1115 { address: Some(0x4B) };
+        let mut opened = 0;
+        let mut open = |volts| {
+            opened += 1;
+            Ok(Box::new(FakeAdc { volts, full_scale: 0.0 }) as Box<dyn Adc>)
+        };
+
+        let mut first = shared(key, 4.096, || open(1.0)).unwrap();
+        let mut second = shared(key, 0.512, || open(2.0)).unwrap();
+        assert!(Arc::ptr_eq(&first.device, &second.device));
+        assert_eq!(first.read_volts(0).unwrap(), 1.0);
+        // Each handle reads at its own range
+        assert_eq!(second.read_volts(0).unwrap(), 0.512);
+        assert_eq!(first.read_volts(0).unwrap(), 1.0);
+
+        // Once every handle is dropped the next open gets a fresh device
+        drop((first, second));
+        first = shared(key, 4.096, || open(3.0)).unwrap();
+        assert_eq!(first.read_volts(0).unwrap(), 3.0);
+        assert_eq!(opened, 2);
+    }
+}
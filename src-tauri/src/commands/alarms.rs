@@ -0,0 +1,747 @@
+use once_cell::sync::Lazy;
+use rodio::source::{SineWave, Source};
+use rodio::Sink;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter};
+
+use super::{audio, settings};
+
+const RULES_FILE: &str = "alarms.toml";
+const LOG_FILE: &str = "alarm_log.jsonl";
+const RAISED_EVENT: &str = "alarm://raised";
+const CLEARED_EVENT: &str = "alarm://cleared";
+// Also how often alarms on channels that stopped updating get expired
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+// Rates are measured over at least this long so sample noise doesn't look like a spike
+const RATE_WINDOW: Duration = Duration::from_millis(250);
+// A channel that stops updating can't hold a condition true
+const STALE_AFTER: Duration = Duration::from_secs(2);
+// A condition has to stay false this long before its alarm clears, so a
+// reading hovering on the line doesn't raise and clear over and over
+const CLEAR_AFTER: Duration = Duration::from_secs(2);
+// An alarm raised again this soon after the last time doesn't chime again
+const CHIME_COOLDOWN: Duration = Duration::from_secs(60);
+const LOG_LIMIT: usize = 500;
+// The log is cut back to LOG_LIMIT entries once it grows past this
+const LOG_TRIM_AT: usize = LOG_LIMIT * 2;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// Shown, no chime
+    Info,
+    Warning,
+    /// Urgent triple beep
+    Critical,
+}
+
+/// Tests against telemetry channels, named `<source>.<field>` as in `telemetry::Telemetry::channels`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Condition {
+    Above {
+        channel: String,
+        value: f32,
+    },
+    Below {
+        channel: String,
+        value: f32,
+    },
+    /// Climbing faster than `per_second` units a second
+    RisingFaster {
+        channel: String,
+        per_second: f32,
+    },
+    FallingFaster {
+        channel: String,
+        per_second: f32,
+    },
+    All {
+        conditions: Vec<Condition>,
+    },
+    Any {
+        conditions: Vec<Condition>,
+    },
+    /// `condition` has held continuously for `seconds`
+    For {
+        seconds: f32,
+        condition: Box<Condition>,
+        #[serde(skip)]
+        since: Option<Instant>,
+    },
+}
+
+impl Condition {
+    pub fn holds(&mut self, channels: &Channels, now: Instant) -> bool {
+        match self {
+            Condition::Above { channel, value } => channels.value(channel, now).is_some_and(|v| v > *value),
+            Condition::Below { channel, value } => channels.value(channel, now).is_some_and(|v| v < *value),
+            Condition::RisingFaster { channel, per_second } => {
+                channels.rate(channel, now).is_some_and(|rate| rate > *per_second)
+            }
+            Condition::FallingFaster { channel, per_second } => {
+                channels.rate(channel, now).is_some_and(|rate| -rate > *per_second)
+            }
+            // Every branch is evaluated so duration timers inside see every sample
+            Condition::All { conditions } => evaluate_all(conditions, channels, now).iter().all(|held| *held),
+            Condition::Any { conditions } => evaluate_all(conditions, channels, now).iter().any(|held| *held),
+            Condition::For {
+                seconds,
+                condition,
+                since,
+            } => {
+                if !condition.holds(channels, now) {
+                    *since = None;
+                    return false;
+                }
+                let since = *since.get_or_insert(now);
+                now.saturating_duration_since(since).as_secs_f32() >= *seconds
+            }
+        }
+    }
+}
+
+fn evaluate_all(conditions: &mut [Condition], channels: &Channels, now: Instant) -> Vec<bool> {
+    conditions
+        .iter_mut()
+        .map(|condition| condition.holds(channels, now))
+        .collect()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Rule {
+    pub name: String,
+    pub message: String,
+    pub severity: Severity,
+    pub condition: Condition,
+}
+
+/// Rules read from `alarms.toml` in the config dir, e.g.
+///
+/// ```toml
+/// [[rules]]
+/// name = "water_temp_high"
+/// message = "Water temp over 100°C"
+/// severity = "critical"
+/// condition = { type = "above", channel = "power_fc.water_temp_c", value = 100.0 }
+///
+/// [[rules]]
+/// name = "oil_pressure_under_boost"
+/// message = "Low oil pressure under boost"
+/// severity = "critical"
+/// [rules.condition]
+/// type = "for"
+/// seconds = 0.5
+/// condition = { type = "all", conditions = [
+///     { type = "below", channel = "sensors.oil_pressure", value = 30.0 },
+///     { type = "above", channel = "power_fc.boost_kpa", value = 0.0 },
+/// ] }
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct AlarmRules {
+    pub rules: Vec<Rule>,
+}
+
+struct ChannelValue {
+    value: f32,
+    at: Instant,
+    rate: Option<f32>,
+    reference: (f32, Instant),
+}
+
+/// Latest value and rate of change of every telemetry channel seen
+#[derive(Default)]
+pub struct Channels {
+    values: HashMap<String, ChannelValue>,
+}
+
+impl Channels {
+    pub fn update(&mut self, name: &str, value: f32, now: Instant) {
+        let Some(channel) = self.values.get_mut(name) else {
+            self.values.insert(
+                name.to_string(),
+                ChannelValue {
+                    value,
+                    at: now,
+                    rate: None,
+                    reference: (value, now),
+                },
+            );
+            return;
+        };
+
+        let (reference_value, reference_at) = channel.reference;
+        let elapsed = now.saturating_duration_since(reference_at);
+        if elapsed > STALE_AFTER {
+            channel.rate = None;
+            channel.reference = (value, now);
+        } else if elapsed >= RATE_WINDOW {
+            channel.rate = Some((value - reference_value) / elapsed.as_secs_f32());
+            channel.reference = (value, now);
+        }
+        channel.value = value;
+        channel.at = now;
+    }
+
+    fn fresh(&self, name: &str, now: Instant) -> Option<&ChannelValue> {
+        self.values
+            .get(name)
+            .filter(|channel| now.saturating_duration_since(channel.at) <= STALE_AFTER)
+    }
+
+    pub fn value(&self, name: &str, now: Instant) -> Option<f32> {
+        self.fresh(name, now).map(|channel| channel.value)
+    }
+
+    pub fn rate(&self, name: &str, now: Instant) -> Option<f32> {
+        self.fresh(name, now).and_then(|channel| channel.rate)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Alarm {
+    pub name: String,
+    pub message: String,
+    pub severity: Severity,
+    /// Unix time in milliseconds
+    pub raised_at: u64,
+    /// Raised again within the chime cooldown, so it's not chimed or re-shown
+    #[serde(default)]
+    pub repeat: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AlarmChange {
+    Raised(Alarm),
+    Cleared(Alarm),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LogEntry {
+    /// Unix time in milliseconds
+    pub time: u64,
+    #[serde(flatten)]
+    pub change: AlarmChange,
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Evaluates every rule against the telemetry stream and tracks which alarms are raised
+#[derive(Default)]
+pub struct AlarmEngine {
+    rules: Vec<Rule>,
+    channels: Channels,
+    active: Vec<Alarm>,
+    // When each active alarm's condition stopped holding
+    clearing: HashMap<String, Instant>,
+    last_raised: HashMap<String, Instant>,
+}
+
+impl AlarmEngine {
+    /// Swap in new rules, keeping channel history. Alarms whose rule is gone are cleared.
+    pub fn set_rules(&mut self, rules: Vec<Rule>) -> Vec<AlarmChange> {
+        let (kept, removed) = std::mem::take(&mut self.active)
+            .into_iter()
+            .partition(|alarm| rules.iter().any(|rule| rule.name == alarm.name));
+        self.active = kept;
+        self.clearing
+            .retain(|name, _| rules.iter().any(|rule| &rule.name == name));
+        self.rules = rules;
+        removed.into_iter().map(AlarmChange::Cleared).collect()
+    }
+
+    pub fn active(&self) -> &[Alarm] {
+        &self.active
+    }
+
+    /// Feed in new samples and re-check every rule. Called with no samples to
+    /// expire alarms whose channels have stopped updating.
+    pub fn observe(&mut self, samples: &[(String, f32)], now: Instant) -> Vec<AlarmChange> {
+        for (name, value) in samples {
+            self.channels.update(name, *value, now);
+        }
+
+        let mut changes = vec![];
+        for rule in self.rules.iter_mut() {
+            let held = rule.condition.holds(&self.channels, now);
+            let position = self.active.iter().position(|alarm| alarm.name == rule.name);
+
+            match (held, position) {
+                (true, None) => {
+                    let repeat = self
+                        .last_raised
+                        .get(&rule.name)
+                        .is_some_and(|at| now.saturating_duration_since(*at) < CHIME_COOLDOWN);
+                    if !repeat {
+                        self.last_raised.insert(rule.name.clone(), now);
+                    }
+                    let alarm = Alarm {
+                        name: rule.name.clone(),
+                        message: rule.message.clone(),
+                        severity: rule.severity,
+                        raised_at: unix_millis(),
+                        repeat,
+                    };
+                    self.active.push(alarm.clone());
+                    changes.push(AlarmChange::Raised(alarm));
+                }
+                (true, Some(_)) => {
+                    self.clearing.remove(&rule.name);
+                }
+                (false, Some(position)) => {
+                    let since = *self.clearing.entry(rule.name.clone()).or_insert(now);
+                    if now.saturating_duration_since(since) >= CLEAR_AFTER {
+                        self.clearing.remove(&rule.name);
+                        changes.push(AlarmChange::Cleared(self.active.remove(position)));
+                    }
+                }
+                (false, None) => {}
+            }
+        }
+        changes
+    }
+}
+
+static ENGINE: Lazy<Mutex<AlarmEngine>> = Lazy::new(|| Mutex::new(AlarmEngine::default()));
+
+fn rules_path() -> PathBuf {
+    settings::config_dir().join(RULES_FILE)
+}
+
+fn log_path() -> PathBuf {
+    settings::config_dir().join(LOG_FILE)
+}
+
+fn load_rules() -> Result<AlarmRules, Box<dyn Error + Send + Sync>> {
+    match std::fs::read_to_string(rules_path()) {
+        Ok(contents) => Ok(toml::from_str(&contents)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(AlarmRules::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn append_log(change: &AlarmChange) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let entry = LogEntry {
+        time: unix_millis(),
+        change: change.clone(),
+    };
+    let line = serde_json::to_string(&entry)?;
+    std::fs::create_dir_all(settings::config_dir())?;
+
+    let contents = match std::fs::read_to_string(log_path()) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    match trim_log(&contents, &line) {
+        // Same temp file and rename as settings::save
+        Some(trimmed) => {
+            let tmp_path = log_path().with_extension("jsonl.tmp");
+            std::fs::write(&tmp_path, trimmed)?;
+            std::fs::rename(&tmp_path, log_path())?;
+        }
+        None => {
+            let mut file = OpenOptions::new().create(true).append(true).open(log_path())?;
+            writeln!(file, "{}", line)?;
+        }
+    }
+    Ok(())
+}
+
+/// The log with `line` added and cut back to the newest LOG_LIMIT entries,
+/// or None while it's small enough to just append to
+fn trim_log(contents: &str, line: &str) -> Option<String> {
+    let lines: Vec<&str> = contents.lines().chain([line]).collect();
+    if lines.len() <= LOG_TRIM_AT {
+        return None;
+    }
+    let kept = &lines[lines.len() - LOG_LIMIT..];
+    Some(kept.iter().map(|line| format!("{}\n", line)).collect())
+}
+
+/// Play the chime for `severity` on its own sink, over whatever else is playing
+fn chime(severity: Severity) -> Result<(), Box<dyn Error + Send + Sync>> {
+    // (frequency, length) of each tone, silence between them
+    let tones: &[(f32, u64)] = match severity {
+        Severity::Info => return Ok(()),
+        Severity::Warning => &[(880.0, 150), (660.0, 250)],
+        Severity::Critical => &[(1320.0, 120), (0.0, 80), (1320.0, 120), (0.0, 80), (1320.0, 120)],
+    };
+
+    let sink = Sink::try_new(&audio::output_handle()?)?;
+    for &(frequency, millis) in tones {
+        let tone = SineWave::new(frequency)
+            .take_duration(Duration::from_millis(millis))
+            .amplify(if frequency > 0.0 { 0.4 } else { 0.0 });
+        sink.append(tone);
+    }
+    sink.detach();
+    Ok(())
+}
+
+fn announce(app: &AppHandle, changes: Vec<AlarmChange>) {
+    for change in changes {
+        if let Err(e) = append_log(&change) {
+            println!("Failed to write alarm log: {}", e);
+        }
+
+        let result = match &change {
+            AlarmChange::Raised(alarm) => {
+                println!("Alarm raised: {}", alarm.message);
+                let chimed = if alarm.repeat { Ok(()) } else { chime(alarm.severity) };
+                if let Err(e) = chimed {
+                    println!("Failed to play alarm chime: {}", e);
+                }
+                app.emit(RAISED_EVENT, alarm)
+            }
+            AlarmChange::Cleared(alarm) => app.emit(CLEARED_EVENT, alarm),
+        };
+        if let Err(e) = result {
+            println!("Failed to emit alarm: {}", e);
+        }
+    }
+}
+
+/// Run the rules over a telemetry update. Called for everything on the telemetry channel.
+pub fn observe(app: &AppHandle, samples: &[(String, f32)]) {
+    let changes = match ENGINE.lock() {
+        Ok(mut engine) => engine.observe(samples, Instant::now()),
+        Err(_) => return,
+    };
+    announce(app, changes);
+}
+
+/// Load the rules and pick them up again whenever the file changes. Called once from the app setup hook.
+///
+/// Also re-checks the rules every tick, so alarms clear even once nothing is
+/// publishing telemetry at all.
+pub fn start(app: AppHandle) {
+    std::thread::spawn(move || {
+        let mut loaded = None;
+        loop {
+            observe(&app, &[]);
+
+            let modified = std::fs::metadata(rules_path()).and_then(|meta| meta.modified()).ok();
+            if loaded != Some(modified) {
+                loaded = Some(modified);
+                match load_rules() {
+                    Ok(config) => {
+                        println!("Loaded {} alarm rules", config.rules.len());
+                        let changes = match ENGINE.lock() {
+                            Ok(mut engine) => engine.set_rules(config.rules),
+                            Err(_) => return,
+                        };
+                        announce(&app, changes);
+                    }
+                    Err(e) => println!("Invalid {}: {}", RULES_FILE, e),
+                }
+            }
+            std::thread::sleep(TICK_INTERVAL);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_active_alarms() -> Result<Vec<Alarm>, String> {
+    let engine = ENGINE.lock().map_err(|e| e.to_string())?;
+    Ok(engine.active().to_vec())
+}
+
+/// Most recent log entries, oldest first
+#[tauri::command]
+pub fn get_alarm_log() -> Result<Vec<LogEntry>, String> {
+    let contents = match std::fs::read_to_string(log_path()) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let entries: Vec<LogEntry> = contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    Ok(entries[entries.len().saturating_sub(LOG_LIMIT)..].to_vec())
+}
+
+#[tauri::command]
+pub fn clear_alarm_log() -> Result<(), String> {
+    match std::fs::remove_file(log_path()) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.to_string()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(name: &str, condition: Condition) -> Rule {
+        Rule {
+            name: name.to_string(),
+            message: name.to_string(),
+            severity: Severity::Warning,
+            condition,
+        }
+    }
+
+    fn engine(rules: Vec<Rule>) -> AlarmEngine {
+        let mut engine = AlarmEngine::default();
+        engine.set_rules(rules);
+        engine
+    }
+
+    fn sample(channel: &str, value: f32) -> Vec<(String, f32)> {
+        vec![(channel.to_string(), value)]
+    }
+
+    fn raised(changes: &[AlarmChange]) -> Vec<&str> {
+        changes
+            .iter()
+            .filter_map(|change| match change {
+                AlarmChange::Raised(alarm) => Some(alarm.name.as_str()),
+                AlarmChange::Cleared(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn threshold_raises_and_clears() {
+        let mut engine = engine(vec![rule(
+            "hot",
+            Condition::Above {
+                channel: "power_fc.water_temp_c".to_string(),
+                value: 100.0,
+            },
+        )]);
+        let start = Instant::now();
+
+        let mut raised_count = 0;
+        for (i, temp) in [95.0, 99.0, 101.0, 103.0, 104.0].into_iter().enumerate() {
+            let changes = engine.observe(
+                &sample("power_fc.water_temp_c", temp),
+                start + Duration::from_secs(i as u64),
+            );
+            raised_count += raised(&changes).len();
+        }
+        // Raised once, not on every sample above the line
+        assert_eq!(raised_count, 1);
+        assert_eq!(engine.active().len(), 1);
+
+        // Cleared once it's stayed under the line for CLEAR_AFTER
+        assert!(engine
+            .observe(&sample("power_fc.water_temp_c", 98.0), start + Duration::from_secs(5))
+            .is_empty());
+        let changes = engine.observe(&sample("power_fc.water_temp_c", 97.0), start + Duration::from_secs(7));
+        assert!(matches!(&changes[..], [AlarmChange::Cleared(alarm)] if alarm.name == "hot"));
+        assert!(engine.active().is_empty());
+    }
+
+    #[test]
+    fn hovering_on_the_line_doesnt_chatter() {
+        let mut engine = engine(vec![rule(
+            "hot",
+            Condition::Above {
+                channel: "power_fc.water_temp_c".to_string(),
+                value: 100.0,
+            },
+        )]);
+        let start = Instant::now();
+
+        let mut changes = vec![];
+        for i in 0..20 {
+            let temp = if i % 2 == 0 { 100.5 } else { 99.5 };
+            changes.extend(engine.observe(
+                &sample("power_fc.water_temp_c", temp),
+                start + Duration::from_millis(500 * i),
+            ));
+        }
+        assert_eq!(changes.len(), 1);
+        assert_eq!(raised(&changes), vec!["hot"]);
+    }
+
+    #[test]
+    fn raising_again_soon_doesnt_rechime() {
+        let mut engine = engine(vec![rule(
+            "hot",
+            Condition::Above {
+                channel: "power_fc.water_temp_c".to_string(),
+                value: 100.0,
+            },
+        )]);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut raise_and_clear = |secs| {
+            let changes = engine.observe(&sample("power_fc.water_temp_c", 105.0), at(secs));
+            engine.observe(&sample("power_fc.water_temp_c", 95.0), at(secs + 1));
+            engine.observe(&sample("power_fc.water_temp_c", 95.0), at(secs + 3));
+            assert!(engine.active().is_empty());
+            match &changes[..] {
+                [AlarmChange::Raised(alarm)] => alarm.repeat,
+                other => panic!("expected a raise, got {:?}", other),
+            }
+        };
+
+        assert!(!raise_and_clear(0));
+        assert!(raise_and_clear(10));
+        assert!(raise_and_clear(50));
+        // The cooldown runs from the last chime, not the last raise
+        assert!(!raise_and_clear(70));
+    }
+
+    #[test]
+    fn and_with_duration() {
+        let mut engine = engine(vec![rule(
+            "oil",
+            Condition::For {
+                seconds: 0.5,
+                since: None,
+                condition: Box::new(Condition::All {
+                    conditions: vec![
+                        Condition::Below {
+                            channel: "sensors.oil_pressure".to_string(),
+                            value: 30.0,
+                        },
+                        Condition::Above {
+                            channel: "power_fc.boost_kpa".to_string(),
+                            value: 0.0,
+                        },
+                    ],
+                }),
+            },
+        )]);
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+
+        // Low oil pressure at idle, no boost
+        engine.observe(&sample("sensors.oil_pressure", 20.0), at(0));
+        assert!(raised(&engine.observe(&sample("power_fc.boost_kpa", -40.0), at(0))).is_empty());
+
+        // A brief dip under boost isn't enough
+        assert!(raised(&engine.observe(&sample("power_fc.boost_kpa", 50.0), at(100))).is_empty());
+        assert!(raised(&engine.observe(&sample("sensors.oil_pressure", 40.0), at(300))).is_empty());
+        assert!(raised(&engine.observe(&sample("sensors.oil_pressure", 20.0), at(400))).is_empty());
+
+        // Held for half a second
+        assert!(raised(&engine.observe(&sample("sensors.oil_pressure", 20.0), at(700))).is_empty());
+        assert_eq!(
+            raised(&engine.observe(&sample("sensors.oil_pressure", 20.0), at(900))),
+            vec!["oil"]
+        );
+    }
+
+    #[test]
+    fn rate_of_change() {
+        let mut engine = engine(vec![rule(
+            "climbing",
+            Condition::RisingFaster {
+                channel: "sensors.oil_temp".to_string(),
+                per_second: 5.0,
+            },
+        )]);
+        let start = Instant::now();
+
+        // 2 degrees a second
+        let mut raised_names = vec![];
+        for i in 0..10 {
+            let changes = engine.observe(
+                &sample("sensors.oil_temp", 90.0 + i as f32 * 0.2),
+                start + Duration::from_millis(100 * i),
+            );
+            raised_names.extend(raised(&changes).into_iter().map(String::from));
+        }
+        assert!(raised_names.is_empty());
+
+        // 10 degrees a second
+        for i in 10..20 {
+            let changes = engine.observe(
+                &sample("sensors.oil_temp", 92.0 + (i - 10) as f32),
+                start + Duration::from_millis(100 * i),
+            );
+            raised_names.extend(raised(&changes).into_iter().map(String::from));
+        }
+        assert_eq!(raised_names, vec!["climbing"]);
+    }
+
+    #[test]
+    fn stale_channel_clears() {
+        let mut engine = engine(vec![rule(
+            "hot",
+            Condition::Above {
+                channel: "tach.rpm".to_string(),
+                value: 8000.0,
+            },
+        )]);
+        let start = Instant::now();
+
+        assert_eq!(raised(&engine.observe(&sample("tach.rpm", 8500.0), start)), vec!["hot"]);
+        assert!(engine
+            .observe(&sample("power_fc.rpm", 1000.0), start + Duration::from_secs(3))
+            .is_empty());
+
+        // Expired by the tick even with nothing publishing at all
+        let changes = engine.observe(&[], start + Duration::from_secs(5));
+        assert!(matches!(&changes[..], [AlarmChange::Cleared(_)]));
+    }
+
+    #[test]
+    fn log_is_trimmed_to_the_newest_entries() {
+        let full: String = (0..LOG_TRIM_AT - 1).map(|i| format!("{}\n", i)).collect();
+        assert_eq!(trim_log(&full, "new"), None);
+
+        let over = format!("{}{}\n", full, LOG_TRIM_AT - 1);
+        let trimmed = trim_log(&over, "new").unwrap();
+        let lines: Vec<&str> = trimmed.lines().collect();
+        assert_eq!(lines.len(), LOG_LIMIT);
+        assert_eq!(lines[0], (LOG_TRIM_AT + 1 - LOG_LIMIT).to_string());
+        assert_eq!(lines[LOG_LIMIT - 1], "new");
+    }
+
+    #[test]
+    fn parses_rules_and_log() {
+        let config: AlarmRules = toml::from_str(
+            r#"
+            [[rules]]
+            name = "oil"
+            message = "Low oil pressure under boost"
+            severity = "critical"
+            [rules.condition]
+            type = "for"
+            seconds = 0.5
+            condition = { type = "all", conditions = [
+                { type = "below", channel = "sensors.oil_pressure", value = 30.0 },
+                { type = "above", channel = "power_fc.boost_kpa", value = 0.0 },
+            ] }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.rules[0].severity, Severity::Critical);
+        assert!(matches!(&config.rules[0].condition, Condition::For { seconds, .. } if *seconds == 0.5));
+
+        let entry = LogEntry {
+            time: 1,
+            change: AlarmChange::Cleared(Alarm {
+                name: "oil".to_string(),
+                message: "Low oil pressure".to_string(),
+                severity: Severity::Critical,
+                raised_at: 0,
+                repeat: false,
+            }),
+        };
+        let line = serde_json::to_string(&entry).unwrap();
+        assert_eq!(serde_json::from_str::<LogEntry>(&line).unwrap(), entry);
+    }
+}
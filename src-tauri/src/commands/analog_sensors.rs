@@ -0,0 +1,531 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tauri::AppHandle;
+
+use super::adc::{self, Adc, AdcSettings};
+use super::settings;
+use super::telemetry::{self, Telemetry};
+
+const CONFIG_FILE: &str = "analog_sensors.toml";
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Unit {
+    Volts,
+    Kpa,
+    Psi,
+    Bar,
+    Celsius,
+    Fahrenheit,
+}
+
+impl Unit {
+    /// Convert between units of the same quantity, None if they measure different things
+    pub fn convert(self, value: f32, to: Unit) -> Option<f32> {
+        use Unit::*;
+
+        let kpa = |unit: Unit, value: f32| match unit {
+            Kpa => Some(value),
+            Psi => Some(value * 6.894_757),
+            Bar => Some(value * 100.0),
+            _ => None,
+        };
+        let celsius = |unit: Unit, value: f32| match unit {
+            Celsius => Some(value),
+            Fahrenheit => Some((value - 32.0) / 1.8),
+            _ => None,
+        };
+
+        match (self, to) {
+            (from, to) if from == to => Some(value),
+            (from, Kpa) => kpa(from, value),
+            (from, Psi) => kpa(from, value).map(|kpa| kpa / 6.894_757),
+            (from, Bar) => kpa(from, value).map(|kpa| kpa / 100.0),
+            (from, Celsius) => celsius(from, value),
+            (from, Fahrenheit) => celsius(from, value).map(|c| c * 1.8 + 32.0),
+            (_, Volts) => None,
+        }
+    }
+}
+
+/// Sender wired to ground with a pull-up resistor to the supply, the ADC reading the junction
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Divider {
+    pub pullup_ohms: f32,
+    pub supply_volts: f32,
+}
+
+impl Divider {
+    /// Sender resistance, None when the reading is pinned to a rail (open or shorted sender)
+    pub fn resistance(&self, volts: f32) -> Option<f32> {
+        if volts <= 0.0 || volts >= self.supply_volts {
+            return None;
+        }
+        Some(self.pullup_ohms * volts / (self.supply_volts - volts))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Curve {
+    /// Straight line through two points, e.g. a 0.5-4.5 V pressure sender
+    Linear { volts: [f32; 2], values: [f32; 2] },
+    /// NTC thermistor, gives Celsius
+    SteinhartHart { a: f64, b: f64, c: f64, divider: Divider },
+    /// Interpolated table of (input, value) pairs. The input is ohms with a divider, volts without.
+    Lookup {
+        divider: Option<Divider>,
+        table: Vec<[f32; 2]>,
+    },
+}
+
+impl Curve {
+    /// Highest voltage the curve expects to read, None if it can't tell
+    pub fn max_volts(&self) -> Option<f32> {
+        match self {
+            Curve::Linear { volts, .. } => Some(volts[0].max(volts[1])),
+            Curve::SteinhartHart { divider, .. }
+            | Curve::Lookup {
+                divider: Some(divider), ..
+            } => Some(divider.supply_volts),
+            Curve::Lookup { divider: None, table } => table.iter().map(|[volts, _]| *volts).reduce(f32::max),
+        }
+    }
+
+    pub fn value(&self, volts: f32) -> Option<f32> {
+        match self {
+            Curve::Linear { volts: v, values } => {
+                if v[1] == v[0] {
+                    return None;
+                }
+                Some(values[0] + (volts - v[0]) * (values[1] - values[0]) / (v[1] - v[0]))
+            }
+            Curve::SteinhartHart { a, b, c, divider } => {
+                let ln_r = (divider.resistance(volts)? as f64).ln();
+                let kelvin = 1.0 / (a + b * ln_r + c * ln_r.powi(3));
+                Some((kelvin - 273.15) as f32)
+            }
+            Curve::Lookup { divider, table } => {
+                let input = match divider {
+                    Some(divider) => divider.resistance(volts)?,
+                    None => volts,
+                };
+                interpolate(table, input)
+            }
+        }
+    }
+}
+
+/// Linear interpolation over a table in either input order, clamped at the ends
+pub fn interpolate(table: &[[f32; 2]], input: f32) -> Option<f32> {
+    let mut points = table.to_vec();
+    points.sort_by(|a, b| a[0].total_cmp(&b[0]));
+
+    let first = points.first()?;
+    let last = points.last()?;
+    if input <= first[0] {
+        return Some(first[1]);
+    }
+    if input >= last[0] {
+        return Some(last[1]);
+    }
+
+    points.windows(2).find(|pair| input <= pair[1][0]).map(|pair| {
+        let [x0, y0] = pair[0];
+        let [x1, y1] = pair[1];
+        y0 + (input - x0) * (y1 - y0) / (x1 - x0)
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SensorConfig {
+    pub name: String,
+    pub channel: u8,
+    pub curve: Curve,
+    /// What the curve gives, Celsius for Steinhart-Hart
+    pub unit: Unit,
+    /// Unit to report in, None for the curve's own
+    #[serde(default)]
+    pub display_unit: Option<Unit>,
+    /// Exponential smoothing factor, 1 for none, smaller is smoother
+    #[serde(default = "default_smoothing")]
+    pub smoothing: f32,
+}
+
+fn default_smoothing() -> f32 {
+    1.0
+}
+
+impl SensorConfig {
+    pub fn display_unit(&self) -> Unit {
+        self.display_unit.unwrap_or(self.unit)
+    }
+}
+
+/// Read from `analog_sensors.toml` in the config dir, e.g.
+///
+/// ```toml
+/// poll_ms = 50
+///
+/// [adc]
+/// model = "ads1115"
+///
+/// [[sensors]]
+/// name = "oil_pressure"
+/// channel = 0
+/// unit = "kpa"
+/// display_unit = "psi"
+/// smoothing = 0.3
+/// curve = { type = "linear", volts = [0.5, 4.5], values = [0.0, 689.5] }
+///
+/// [[sensors]]
+/// name = "water_temp"
+/// channel = 1
+/// unit = "celsius"
+/// smoothing = 0.1
+/// [sensors.curve]
+/// type = "lookup"
+/// divider = { pullup_ohms = 1000.0, supply_volts = 3.3 }
+/// table = [[1743.0, 20.0], [467.0, 50.0], [158.0, 80.0], [68.0, 110.0], [34.0, 140.0]]
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct AnalogSensorsConfig {
+    pub adc: AdcSettings,
+    pub poll_ms: u64,
+    pub sensors: Vec<SensorConfig>,
+}
+
+impl Default for AnalogSensorsConfig {
+    fn default() -> Self {
+        Self {
+            adc: AdcSettings::default(),
+            poll_ms: 50,
+            sensors: vec![],
+        }
+    }
+}
+
+impl AnalogSensorsConfig {
+    pub fn parse(contents: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let config: AnalogSensorsConfig = toml::from_str(contents)?;
+
+        for sensor in &config.sensors {
+            if sensor.channel >= config.adc.model.channels() {
+                return Err(format!("{}: the ADC has no channel {}", sensor.name, sensor.channel).into());
+            }
+            if sensor.unit.convert(0.0, sensor.display_unit()).is_none() {
+                return Err(format!(
+                    "{}: can't show {:?} as {:?}",
+                    sensor.name,
+                    sensor.unit,
+                    sensor.display_unit()
+                )
+                .into());
+            }
+            if !(sensor.smoothing > 0.0 && sensor.smoothing <= 1.0) {
+                return Err(format!("{}: smoothing must be above 0 and at most 1", sensor.name).into());
+            }
+        }
+        config.adc.check()?;
+
+        // Still usable, the top of the curve just reads as full scale
+        let full_scale = config.adc.full_scale_volts();
+        for sensor in &config.sensors {
+            if let Some(max) = sensor.curve.max_volts().filter(|max| *max > full_scale) {
+                println!(
+                    "{}: the curve goes up to {} V but the ADC only reads up to {} V",
+                    sensor.name, max, full_scale
+                );
+            }
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct SensorReading {
+    pub name: String,
+    /// None when the sender looks open or shorted
+    pub value: Option<f32>,
+    pub unit: Unit,
+    pub volts: f32,
+}
+
+/// Turns raw ADC voltages into smoothed readings for one sensor
+pub struct Sensor {
+    config: SensorConfig,
+    filtered: Option<f32>,
+}
+
+impl Sensor {
+    pub fn new(config: SensorConfig) -> Self {
+        Sensor { config, filtered: None }
+    }
+
+    pub fn update(&mut self, volts: f32) -> SensorReading {
+        let value = self.config.curve.value(volts).filter(|value| value.is_finite());
+
+        // A fault resets the filter so the gauge doesn't crawl back from a bogus value
+        self.filtered = value.map(|value| match self.filtered {
+            Some(previous) => previous + self.config.smoothing * (value - previous),
+            None => value,
+        });
+
+        let unit = self.config.display_unit();
+        SensorReading {
+            name: self.config.name.clone(),
+            value: self.filtered.and_then(|value| self.config.unit.convert(value, unit)),
+            unit,
+            volts,
+        }
+    }
+}
+
+static LATEST: Mutex<Vec<SensorReading>> = Mutex::new(Vec::new());
+
+fn set_latest(readings: Vec<SensorReading>) {
+    if let Ok(mut latest) = LATEST.lock() {
+        *latest = readings;
+    }
+}
+
+fn config_path() -> PathBuf {
+    settings::config_dir().join(CONFIG_FILE)
+}
+
+fn modified() -> Option<SystemTime> {
+    std::fs::metadata(config_path()).and_then(|meta| meta.modified()).ok()
+}
+
+fn load_config() -> Result<AnalogSensorsConfig, Box<dyn Error + Send + Sync>> {
+    match std::fs::read_to_string(config_path()) {
+        Ok(contents) => AnalogSensorsConfig::parse(&contents),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(AnalogSensorsConfig::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Poll until a read fails or the config file changes
+fn poll_sensors(
+    app: &AppHandle,
+    config: &AnalogSensorsConfig,
+    loaded: Option<SystemTime>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut adc: Box<dyn Adc> = adc::open_adc(&config.adc)?;
+    let mut sensors: Vec<Sensor> = config.sensors.iter().cloned().map(Sensor::new).collect();
+    let interval = Duration::from_millis(config.poll_ms.max(1));
+
+    while modified() == loaded {
+        let mut readings = Vec::with_capacity(sensors.len());
+        for sensor in sensors.iter_mut() {
+            let volts = adc.read_volts(sensor.config.channel)?;
+            readings.push(sensor.update(volts));
+        }
+
+        set_latest(readings.clone());
+        telemetry::publish(app, Telemetry::Sensors { readings });
+        std::thread::sleep(interval);
+    }
+    Ok(())
+}
+
+/// Start polling the analog senders. Called once from the app setup hook.
+///
+/// The config file is picked up again whenever it changes.
+pub fn start(app: AppHandle) {
+    std::thread::spawn(move || loop {
+        let loaded = modified();
+        let config = match load_config() {
+            Ok(config) => config,
+            Err(e) => {
+                println!("Invalid {}: {}", CONFIG_FILE, e);
+                std::thread::sleep(RETRY_DELAY);
+                continue;
+            }
+        };
+
+        if config.sensors.is_empty() {
+            std::thread::sleep(RETRY_DELAY);
+            continue;
+        }
+
+        if let Err(e) = poll_sensors(&app, &config, loaded) {
+            println!("Analog sensors failed: {}", e);
+            set_latest(vec![]);
+            std::thread::sleep(RETRY_DELAY);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_sensor_readings() -> Result<Vec<SensorReading>, String> {
+    LATEST.lock().map(|latest| latest.clone()).map_err(|e| e.to_string())
+}
+
+/// Sensor definitions, so gauges know what to expect before the first reading
+#[tauri::command]
+pub fn get_analog_sensors_config() -> Result<AnalogSensorsConfig, String> {
+    load_config().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 0.05
+    }
+
+    #[test]
+    fn linear_sender() {
+        let curve = Curve::Linear {
+            volts: [0.5, 4.5],
+            values: [0.0, 100.0],
+        };
+        assert_eq!(curve.value(0.5), Some(0.0));
+        assert_eq!(curve.value(2.5), Some(50.0));
+        // Not clamped, so an over-range sender is visible
+        assert_eq!(curve.value(4.9), Some(110.0));
+    }
+
+    #[test]
+    fn steinhart_hart_thermistor() {
+        // Coefficients for a common 10k NTC
+        let curve = Curve::SteinhartHart {
+            a: 1.009_249_522e-3,
+            b: 2.378_405_444e-4,
+            c: 2.019_202_697e-7,
+            divider: Divider {
+                pullup_ohms: 10_000.0,
+                supply_volts: 3.3,
+            },
+        };
+
+        // Equal resistances put the junction at half supply
+        assert!((curve.value(1.65).unwrap() - 25.0).abs() < 0.5);
+        assert!(curve.value(1.0).unwrap() > 25.0);
+        assert_eq!(curve.value(3.3), None);
+        assert_eq!(curve.value(0.0), None);
+    }
+
+    #[test]
+    fn lookup_interpolates_resistance() {
+        let curve = Curve::Lookup {
+            divider: Some(Divider {
+                pullup_ohms: 1000.0,
+                supply_volts: 5.0,
+            }),
+            table: vec![[1743.0, 20.0], [467.0, 50.0], [158.0, 80.0]],
+        };
+
+        // 2.5 V is 1000 ohms, between the 20 and 50 degree points
+        assert!(close(
+            curve.value(2.5).unwrap(),
+            20.0 + (1743.0 - 1000.0) * 30.0 / (1743.0 - 467.0)
+        ));
+        assert_eq!(interpolate(&[[1.0, 10.0], [2.0, 20.0]], 5.0), Some(20.0));
+        assert_eq!(interpolate(&[], 1.0), None);
+    }
+
+    #[test]
+    fn units() {
+        assert!(close(Unit::Kpa.convert(689.476, Unit::Psi).unwrap(), 100.0));
+        assert!(close(Unit::Bar.convert(1.0, Unit::Psi).unwrap(), 14.504));
+        assert_eq!(Unit::Celsius.convert(100.0, Unit::Fahrenheit), Some(212.0));
+        assert_eq!(Unit::Celsius.convert(1.0, Unit::Psi), None);
+    }
+
+    #[test]
+    fn smoothing_and_faults() {
+        let mut sensor = Sensor::new(SensorConfig {
+            name: "boost".to_string(),
+            channel: 0,
+            curve: Curve::Linear {
+                volts: [0.0, 5.0],
+                values: [0.0, 1.0],
+            },
+            unit: Unit::Bar,
+            display_unit: Some(Unit::Kpa),
+            smoothing: 0.5,
+        });
+
+        assert_eq!(sensor.update(0.0).value, Some(0.0));
+        assert_eq!(sensor.update(5.0).value, Some(50.0));
+        assert_eq!(sensor.update(5.0).value, Some(75.0));
+
+        let mut sensor = Sensor::new(SensorConfig {
+            name: "coolant".to_string(),
+            channel: 1,
+            curve: Curve::Lookup {
+                divider: Some(Divider {
+                    pullup_ohms: 100.0,
+                    supply_volts: 3.3,
+                }),
+                table: vec![[10.0, 100.0], [100.0, 0.0]],
+            },
+            unit: Unit::Celsius,
+            display_unit: None,
+            smoothing: 0.5,
+        });
+
+        // 100 ohms against the 100 ohm pullup
+        assert_eq!(sensor.update(1.65).value, Some(0.0));
+        // Open sender pins the input to the supply, shorted to ground
+        assert_eq!(sensor.update(3.3).value, None);
+        assert_eq!(sensor.update(0.0).value, None);
+        // The filter starts over rather than easing back from before the fault
+        let ten_ohms = 3.3 * 10.0 / 110.0;
+        assert!(close(sensor.update(ten_ohms).value.unwrap(), 100.0));
+    }
+
+    #[test]
+    fn curve_range() {
+        let linear = Curve::Linear {
+            volts: [4.5, 0.5],
+            values: [0.0, 1.0],
+        };
+        assert_eq!(linear.max_volts(), Some(4.5));
+        let lookup = Curve::Lookup {
+            divider: None,
+            table: vec![[0.2, 0.0], [2.5, 1.0], [1.0, 0.5]],
+        };
+        assert_eq!(lookup.max_volts(), Some(2.5));
+
+        // A 5 V sender on the default ±4.096 V range still parses
+        let config = AnalogSensorsConfig::parse(
+            "[[sensors]]\nname = \"x\"\nchannel = 0\nunit = \"volts\"\ncurve = { type = \"linear\", volts = [0.0, 5.0], values = [0.0, 5.0] }",
+        )
+        .unwrap();
+        assert_eq!(config.adc.full_scale_volts(), 4.096);
+        assert!(AnalogSensorsConfig::parse("[adc]\nfull_scale = 3.3").is_err());
+    }
+
+    #[test]
+    fn parses_toml() {
+        let config = AnalogSensorsConfig::parse(
+            r#"
+            [adc]
+            model = "ads1115"
+
+            [[sensors]]
+            name = "oil_pressure"
+            channel = 0
+            unit = "kpa"
+            display_unit = "psi"
+            curve = { type = "linear", volts = [0.5, 4.5], values = [0.0, 689.5] }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.adc.model, adc::AdcModel::Ads1115);
+        assert_eq!(config.poll_ms, 50);
+        assert_eq!(config.sensors[0].smoothing, 1.0);
+
+        let bad_channel = "[adc]\nmodel = \"ads1115\"\n[[sensors]]\nname = \"x\"\nchannel = 5\nunit = \"volts\"\ncurve = { type = \"linear\", volts = [0.0, 1.0], values = [0.0, 1.0] }";
+        assert!(AnalogSensorsConfig::parse(bad_channel).is_err());
+    }
+}
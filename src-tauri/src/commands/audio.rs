@@ -1,6 +1,38 @@
 // use std::io::BufReader;
 // use rodio::PlayError::DecoderError;
-use std::process::Command;
+use once_cell::sync::Lazy;
+use rodio::{OutputStream, OutputStreamHandle};
+use std::sync::Mutex;
+use tokio::process::Command;
+
+// Shared rodio output. The OutputStream itself isn't Send, so it lives on its
+// own thread for the life of the app and only the handle is passed around.
+static OUTPUT_HANDLE: Lazy<Mutex<Option<OutputStreamHandle>>> = Lazy::new(|| Mutex::new(None));
+
+pub fn output_handle() -> Result<OutputStreamHandle, String> {
+    let mut output = OUTPUT_HANDLE.lock().map_err(|e| e.to_string())?;
+    if let Some(handle) = output.as_ref() {
+        return Ok(handle.clone());
+    }
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || match OutputStream::try_default() {
+        Ok((_stream, handle)) => {
+            let _ = tx.send(Ok(handle));
+            // Dropping the stream stops playback, so park here forever
+            loop {
+                std::thread::park();
+            }
+        }
+        Err(e) => {
+            let _ = tx.send(Err(e.to_string()));
+        }
+    });
+
+    let handle = rx.recv().map_err(|e| e.to_string())??;
+    *output = Some(handle.clone());
+    Ok(handle)
+}
 
 // #[tauri::command]
 // pub fn play_synth() {
@@ -18,16 +50,35 @@ use std::process::Command;
 //     }
 // }
 
-#[tauri::command]
-pub fn set_system_volume(volume: u8) -> Result<(), String> {
-    println!("Setting system volume to {volume}");
-    if volume > 100 {
-        return Err("Volume must be between 0 and 100".to_string());
-    }
+// Last volume the user picked, before any speed compensation is added
+static USER_VOLUME: Mutex<Option<u8>> = Mutex::new(None);
+// Extra gain from speed compensation, in dB
+static SPEED_BOOST_DB: Mutex<f32> = Mutex::new(0.0);
+
+/// Volume the user last picked, if they've touched it yet
+pub fn user_volume() -> Option<u8> {
+    USER_VOLUME.lock().ok().and_then(|volume| *volume)
+}
+
+/// Set the speed compensation gain and re-apply the system volume
+pub async fn set_speed_boost(db: f32) -> Result<(), String> {
+    *SPEED_BOOST_DB.lock().map_err(|e| e.to_string())? = db;
+    apply_system_volume().await
+}
+
+/// Push the user volume plus any speed boost out to the mixer
+async fn apply_system_volume() -> Result<(), String> {
+    // Nothing to compensate until the user has set a volume
+    let Some(user_volume) = *USER_VOLUME.lock().map_err(|e| e.to_string())? else {
+        return Ok(());
+    };
+    let boost_db = *SPEED_BOOST_DB.lock().map_err(|e| e.to_string())?;
+    let volume = (user_volume as f32 * 10f32.powf(boost_db / 20.0)).round().min(100.0) as u8;
 
     let output = Command::new("amixer")
         .args(["set", "Master", &format!("{}%", volume)])
         .output()
+        .await
         .map_err(|e| e.to_string())?;
 
     if !output.status.success() {
@@ -36,4 +87,15 @@ pub fn set_system_volume(volume: u8) -> Result<(), String> {
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[tauri::command]
+pub async fn set_system_volume(volume: u8) -> Result<(), String> {
+    println!("Setting system volume to {volume}");
+    if volume > 100 {
+        return Err("Volume must be between 0 and 100".to_string());
+    }
+
+    *USER_VOLUME.lock().map_err(|e| e.to_string())? = Some(volume);
+    apply_system_volume().await
+}
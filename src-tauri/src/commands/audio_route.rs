@@ -0,0 +1,625 @@
+use once_cell::sync::Lazy;
+use rodio::{Sink, Source};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::process::{Child, ChildStdout, Stdio};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+use super::dsp::DspSource;
+use super::pulse::{self, Connection};
+use super::{audio, settings};
+
+const SETTINGS_NAME: &str = "audio_route";
+// Property set on our loopback's stream so it can be found again after a restart
+const LOOPBACK_TAG: &str = "headunit.route=loopback";
+// What parec is asked to record in, 32-bit float little endian
+const CAPTURE_RATE: u32 = 48_000;
+const CAPTURE_CHANNELS: u16 = 2;
+// Chunks queued between parec and the output before new ones are dropped
+const CAPTURE_QUEUE: usize = 8;
+
+// Percentage of its level the Bluetooth or AUX stream plays at, None while not ducked
+static DUCK_PERCENT: std::sync::Mutex<Option<u8>> = std::sync::Mutex::new(None);
+
+fn duck_percent() -> u8 {
+    DUCK_PERCENT.lock().ok().and_then(|percent| *percent).unwrap_or(100)
+}
+
+/// What the user is listening to
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioInput {
+    /// A2DP stream from a connected phone
+    Bluetooth,
+    Soundboard,
+    LocalFiles,
+    /// Line-in on a capture device
+    Aux,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AudioDevice {
+    pub name: String,
+    pub description: String,
+    pub bluetooth: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AudioSource {
+    pub input: AudioInput,
+    /// Sound server source behind this input, for Bluetooth and AUX
+    pub device: Option<String>,
+    pub available: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AudioRoute {
+    pub active_input: AudioInput,
+    pub output: Option<String>,
+    pub bluetooth_source: Option<String>,
+    /// A2DP codec of the Bluetooth stream, e.g. "SBC", "AAC", "LDAC"
+    pub codec: Option<String>,
+    pub sources: Vec<AudioDevice>,
+    pub sinks: Vec<AudioDevice>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AudioRouteSettings {
+    pub active_input: AudioInput,
+    /// Sink to play everything through, None for the server default
+    pub output: Option<String>,
+    /// Capture source used for AUX, None to pick the first non-Bluetooth input
+    pub aux_source: Option<String>,
+    pub loopback_latency_ms: u32,
+    /// Play Bluetooth and AUX through the app so they get the EQ, loudness and
+    /// limiter. Off links them straight to the output, with a little less latency.
+    pub process_streams: bool,
+}
+
+impl Default for AudioRouteSettings {
+    fn default() -> Self {
+        Self {
+            active_input: AudioInput::Bluetooth,
+            output: None,
+            aux_source: None,
+            loopback_latency_ms: 60,
+            process_streams: true,
+        }
+    }
+}
+
+/// Live audio from a sound server source, recorded by `parec` and played
+/// through the DSP chain to the output
+struct Capture {
+    parec: Child,
+    sink: Sink,
+}
+
+impl Capture {
+    fn start(source: &str, latency_ms: u32) -> Result<Self, String> {
+        let mut parec = std::process::Command::new("parec")
+            .args([
+                format!("--device={}", source),
+                "--format=float32le".to_string(),
+                format!("--rate={}", CAPTURE_RATE),
+                format!("--channels={}", CAPTURE_CHANNELS),
+                format!("--latency-msec={}", latency_ms),
+                "--raw".to_string(),
+            ])
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to run parec: {}", e))?;
+        let Some(stdout) = parec.stdout.take() else {
+            let _ = parec.kill();
+            return Err("parec has no output".to_string());
+        };
+
+        let (tx, rx) = mpsc::sync_channel(CAPTURE_QUEUE);
+        std::thread::spawn(move || read_capture(stdout, tx));
+
+        let sink = audio::output_handle()
+            .and_then(|handle| Sink::try_new(&handle).map_err(|e| e.to_string()))
+            .inspect_err(|_| {
+                let _ = parec.kill();
+            })?;
+        sink.set_volume(duck_percent() as f32 / 100.0);
+        sink.append(DspSource::new(CaptureSource::new(rx)));
+        Ok(Self { parec, sink })
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        self.sink.stop();
+        let _ = self.parec.kill();
+        let _ = self.parec.wait();
+    }
+}
+
+/// Turn parec's bytes into samples, holding back a partial frame until the rest arrives
+fn decode_frames(pending: &mut Vec<u8>, bytes: &[u8]) -> Vec<f32> {
+    const FRAME_BYTES: usize = 4 * CAPTURE_CHANNELS as usize;
+    pending.extend_from_slice(bytes);
+    let whole = pending.len() / FRAME_BYTES * FRAME_BYTES;
+    let samples = pending[..whole]
+        .chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect();
+    pending.drain(..whole);
+    samples
+}
+
+fn read_capture(mut stdout: ChildStdout, tx: SyncSender<Vec<f32>>) {
+    let mut buffer = [0u8; 4096];
+    let mut pending = vec![];
+    loop {
+        let read = match stdout.read(&mut buffer) {
+            Ok(0) | Err(_) => return,
+            Ok(read) => read,
+        };
+        let samples = decode_frames(&mut pending, &buffer[..read]);
+        if samples.is_empty() {
+            continue;
+        }
+        // try_send so a stalled output drops audio instead of backing up parec
+        if let Err(mpsc::TrySendError::Disconnected(_)) = tx.try_send(samples) {
+            return;
+        }
+    }
+}
+
+/// Samples from `read_capture`, filled with silent frames whenever parec falls behind
+struct CaptureSource {
+    rx: Receiver<Vec<f32>>,
+    chunk: std::vec::IntoIter<f32>,
+    // Samples left in the silent frame being played
+    silence: usize,
+}
+
+impl CaptureSource {
+    fn new(rx: Receiver<Vec<f32>>) -> Self {
+        Self {
+            rx,
+            chunk: Vec::new().into_iter(),
+            silence: 0,
+        }
+    }
+}
+
+impl Iterator for CaptureSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.silence > 0 {
+            self.silence -= 1;
+            return Some(0.0);
+        }
+        if let Some(sample) = self.chunk.next() {
+            return Some(sample);
+        }
+        // Chunks are whole frames, so this is always a frame boundary
+        match self.rx.try_recv() {
+            Ok(chunk) => {
+                self.chunk = chunk.into_iter();
+                self.chunk.next()
+            }
+            Err(TryRecvError::Empty) => {
+                self.silence = CAPTURE_CHANNELS as usize - 1;
+                Some(0.0)
+            }
+            Err(TryRecvError::Disconnected) => None,
+        }
+    }
+}
+
+impl Source for CaptureSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        CAPTURE_CHANNELS
+    }
+
+    fn sample_rate(&self) -> u32 {
+        CAPTURE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+struct AudioRouter {
+    settings: AudioRouteSettings,
+    // Index of the loopback module linking the active input to the output
+    loopback_module: Option<u32>,
+    // Or the capture playing it through the DSP chain
+    capture: Option<Capture>,
+}
+
+fn is_bluetooth(name: &str) -> bool {
+    // PulseAudio names them bluez_source/bluez_sink, PipeWire bluez_input/bluez_output
+    name.starts_with("bluez_")
+}
+
+fn to_device(device: &pulse::Device) -> AudioDevice {
+    AudioDevice {
+        name: device.name.clone(),
+        description: device.description.clone().unwrap_or_else(|| device.name.clone()),
+        bluetooth: is_bluetooth(&device.name),
+    }
+}
+
+/// Capture sources, leaving out the monitors of sinks
+async fn list_sources(server: &mut Connection) -> Result<Vec<AudioDevice>, String> {
+    Ok(server
+        .sources()
+        .await?
+        .iter()
+        .filter(|source| source.monitor_of_sink.is_none())
+        .map(to_device)
+        .collect())
+}
+
+async fn list_sinks(server: &mut Connection) -> Result<Vec<AudioDevice>, String> {
+    Ok(server.sinks().await?.iter().map(to_device).collect())
+}
+
+/// Loopback modules a previous run of the app left loaded
+fn tagged_loopbacks(modules: &[pulse::Module]) -> Vec<u32> {
+    modules
+        .iter()
+        .filter(|module| module.name == "module-loopback" && module.argument.contains(LOOPBACK_TAG))
+        .map(|module| module.index)
+        .collect()
+}
+
+/// Streams of the loopback this router loads, found by the property it tags them with
+fn tagged_sink_inputs(sink_inputs: &[pulse::SinkInput]) -> Vec<&pulse::SinkInput> {
+    let (key, value) = LOOPBACK_TAG.split_once('=').unwrap_or_default();
+    sink_inputs
+        .iter()
+        .filter(|input| input.properties.get(key).map(String::as_str) == Some(value))
+        .collect()
+}
+
+/// Codec as reported by the sound server, PipeWire sets `api.bluez5.codec`
+async fn server_codec(server: &mut Connection, source: &str) -> Option<String> {
+    let sources = server.sources().await.ok()?;
+    sources
+        .iter()
+        .find(|device| device.name == source)?
+        .properties
+        .get("api.bluez5.codec")
+        .map(|codec| codec.to_uppercase())
+}
+
+#[cfg(target_os = "linux")]
+async fn bluez_codec() -> Option<String> {
+    let address = super::bluetooth::connected_device_address().await?;
+    super::media_player::get_codec(&address).await.ok().flatten()
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn bluez_codec() -> Option<String> {
+    None
+}
+
+impl AudioRouter {
+    fn new() -> Self {
+        Self {
+            settings: settings::load(SETTINGS_NAME),
+            loopback_module: None,
+            capture: None,
+        }
+    }
+
+    fn source_device(&self, input: AudioInput, sources: &[AudioDevice]) -> Option<String> {
+        match input {
+            AudioInput::Bluetooth => sources.iter().find(|s| s.bluetooth).map(|s| s.name.clone()),
+            AudioInput::Aux => match &self.settings.aux_source {
+                Some(name) => sources.iter().find(|s| &s.name == name).map(|s| s.name.clone()),
+                None => sources.iter().find(|s| !s.bluetooth).map(|s| s.name.clone()),
+            },
+            // Played by the app itself straight to the output
+            AudioInput::Soundboard | AudioInput::LocalFiles => None,
+        }
+    }
+
+    async fn unlink(&mut self, server: &mut Connection) -> Result<(), String> {
+        self.capture = None;
+        if let Some(module) = self.loopback_module.take() {
+            server.unload_module(module).await?;
+        }
+        Ok(())
+    }
+
+    /// Unload loopbacks this router didn't load itself, left behind when the app
+    /// last exited without unlinking
+    async fn unlink_stale(&mut self, server: &mut Connection) -> Result<(), String> {
+        for module in tagged_loopbacks(&server.modules().await?) {
+            if Some(module) != self.loopback_module {
+                server.unload_module(module).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Route `input` to the configured output, replacing whatever was linked before
+    async fn apply(&mut self, server: &mut Connection, input: AudioInput) -> Result<(), String> {
+        let sources = list_sources(server).await?;
+        let device = self.source_device(input, &sources);
+        if matches!(input, AudioInput::Bluetooth | AudioInput::Aux) && device.is_none() {
+            return Err(format!("No {:?} source available", input));
+        }
+
+        self.unlink(server).await?;
+
+        if let Some(output) = &self.settings.output {
+            server.set_default_sink(output).await?;
+        }
+
+        if let Some(source) = device.as_deref().filter(|_| self.settings.process_streams) {
+            self.capture = Some(Capture::start(source, self.settings.loopback_latency_ms)?);
+        } else if let Some(source) = device {
+            let mut args = vec![
+                format!("source={}", source),
+                format!("latency_msec={}", self.settings.loopback_latency_ms),
+                "source_dont_move=true".to_string(),
+                format!("sink_input_properties={}", LOOPBACK_TAG),
+            ];
+            if let Some(output) = &self.settings.output {
+                args.push(format!("sink={}", output));
+            }
+
+            self.loopback_module = Some(server.load_module("module-loopback", &args.join(" ")).await?);
+            self.apply_duck(server).await?;
+        }
+
+        self.settings.active_input = input;
+        Ok(())
+    }
+
+    /// Set the linked stream's volume to the current duck level. Only the music
+    /// stream is turned down, so alarms and the soundboard still play in full.
+    async fn apply_duck(&self, server: &mut Connection) -> Result<(), String> {
+        let percent = duck_percent();
+        if let Some(capture) = &self.capture {
+            capture.sink.set_volume(percent as f32 / 100.0);
+        }
+        if self.loopback_module.is_some() {
+            for input in tagged_sink_inputs(&server.sink_inputs().await?) {
+                server.set_sink_input_volume(input, percent).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Turn the Bluetooth or AUX stream down to `percent` of its level, 0 to mute, None to restore it
+pub fn set_duck(percent: Option<u8>) -> Result<(), String> {
+    *DUCK_PERCENT.lock().map_err(|e| e.to_string())? = percent.map(|percent| percent.min(100));
+    tauri::async_runtime::block_on(async {
+        let router = AUDIO_ROUTER.read().await;
+        match &router.capture {
+            // Nothing to ask the sound server, and it may not be running
+            Some(capture) => {
+                capture.sink.set_volume(duck_percent() as f32 / 100.0);
+                Ok(())
+            }
+            None if router.loopback_module.is_some() => router.apply_duck(&mut Connection::connect().await?).await,
+            None => Ok(()),
+        }
+    })
+}
+
+// Global audio router instance
+static AUDIO_ROUTER: Lazy<Arc<RwLock<AudioRouter>>> = Lazy::new(|| Arc::new(RwLock::new(AudioRouter::new())));
+
+/// Restore the last selected input. Called once from the app setup hook.
+pub fn start() {
+    tauri::async_runtime::spawn(async {
+        let mut server = match Connection::connect().await {
+            Ok(server) => server,
+            Err(e) => {
+                println!("Failed to restore audio route: {}", e);
+                return;
+            }
+        };
+        let mut router = AUDIO_ROUTER.write().await;
+        if let Err(e) = router.unlink_stale(&mut server).await {
+            println!("Failed to unload old audio loopback: {}", e);
+        }
+        let input = router.settings.active_input;
+        if let Err(e) = router.apply(&mut server, input).await {
+            println!("Failed to restore audio route: {}", e);
+        }
+    });
+}
+
+#[tauri::command]
+pub async fn list_audio_sources() -> Result<Vec<AudioSource>, String> {
+    let router = AUDIO_ROUTER.read().await;
+    let sources = list_sources(&mut Connection::connect().await?).await?;
+
+    Ok([AudioInput::Bluetooth, AudioInput::Soundboard, AudioInput::LocalFiles, AudioInput::Aux]
+        .into_iter()
+        .map(|input| {
+            let device = router.source_device(input, &sources);
+            let available = match input {
+                AudioInput::Bluetooth | AudioInput::Aux => device.is_some(),
+                AudioInput::Soundboard | AudioInput::LocalFiles => true,
+            };
+            AudioSource { input, device, available }
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub async fn select_audio_source(input: AudioInput, output: Option<String>) -> Result<(), String> {
+    let mut router = AUDIO_ROUTER.write().await;
+    if output.is_some() {
+        router.settings.output = output;
+    }
+
+    router.apply(&mut Connection::connect().await?, input).await?;
+    settings::save(SETTINGS_NAME, &router.settings)
+}
+
+#[tauri::command]
+pub async fn get_audio_route() -> Result<AudioRoute, String> {
+    let router = AUDIO_ROUTER.read().await;
+    let mut server = Connection::connect().await?;
+    let sources = list_sources(&mut server).await?;
+    let sinks = list_sinks(&mut server).await?;
+
+    let bluetooth_source = router.source_device(AudioInput::Bluetooth, &sources);
+    let codec = match &bluetooth_source {
+        Some(source) => match server_codec(&mut server, source).await {
+            Some(codec) => Some(codec),
+            None => bluez_codec().await,
+        },
+        None => None,
+    };
+
+    let output = match &router.settings.output {
+        Some(output) => Some(output.clone()),
+        None => server.default_sink().await.ok().flatten(),
+    };
+
+    Ok(AudioRoute {
+        active_input: router.settings.active_input,
+        output,
+        bluetooth_source,
+        codec,
+        sources,
+        sinks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn router(aux_source: Option<&str>) -> AudioRouter {
+        AudioRouter {
+            settings: AudioRouteSettings {
+                aux_source: aux_source.map(String::from),
+                ..Default::default()
+            },
+            loopback_module: None,
+            capture: None,
+        }
+    }
+
+    fn device(name: &str) -> AudioDevice {
+        AudioDevice {
+            name: name.to_string(),
+            description: name.to_string(),
+            bluetooth: is_bluetooth(name),
+        }
+    }
+
+    #[test]
+    fn route_selection() {
+        let sources = vec![
+            device("alsa_input.usb-line-in"),
+            device("bluez_input.AA_BB_CC_DD_EE_FF"),
+            device("alsa_input.platform-mic"),
+        ];
+
+        let auto = router(None);
+        assert_eq!(
+            auto.source_device(AudioInput::Bluetooth, &sources).as_deref(),
+            Some("bluez_input.AA_BB_CC_DD_EE_FF")
+        );
+        assert_eq!(
+            auto.source_device(AudioInput::Aux, &sources).as_deref(),
+            Some("alsa_input.usb-line-in")
+        );
+        assert_eq!(auto.source_device(AudioInput::Soundboard, &sources), None);
+        assert_eq!(auto.source_device(AudioInput::LocalFiles, &sources), None);
+
+        let pinned = router(Some("alsa_input.platform-mic"));
+        assert_eq!(
+            pinned.source_device(AudioInput::Aux, &sources).as_deref(),
+            Some("alsa_input.platform-mic")
+        );
+
+        // A configured AUX source that's unplugged isn't swapped for another
+        let missing = router(Some("alsa_input.unplugged"));
+        assert_eq!(missing.source_device(AudioInput::Aux, &sources), None);
+
+        // No phone connected
+        assert_eq!(auto.source_device(AudioInput::Bluetooth, &sources[..1]), None);
+    }
+
+    #[test]
+    fn capture_keeps_frames_whole() {
+        let bytes: Vec<u8> = [0.5f32, -0.5, 0.25, -0.25]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+
+        // A frame split across reads comes out once it's complete
+        let mut pending = vec![];
+        assert!(decode_frames(&mut pending, &bytes[..6]).is_empty());
+        assert_eq!(decode_frames(&mut pending, &bytes[6..11]), vec![0.5, -0.5]);
+        assert_eq!(decode_frames(&mut pending, &bytes[11..]), vec![0.25, -0.25]);
+        assert!(pending.is_empty());
+
+        // Gaps are filled with whole silent frames so left and right don't swap
+        let (tx, rx) = mpsc::sync_channel(CAPTURE_QUEUE);
+        let mut source = CaptureSource::new(rx);
+        assert_eq!(source.next(), Some(0.0));
+        tx.send(vec![0.5, -0.5]).unwrap();
+        assert_eq!(source.next(), Some(0.0));
+        assert_eq!(source.next(), Some(0.5));
+        assert_eq!(source.next(), Some(-0.5));
+
+        drop(tx);
+        assert_eq!(source.next(), None);
+    }
+
+    #[test]
+    fn finds_loopbacks_left_by_a_previous_run() {
+        let module = |index: u32, name: &str, argument: &str| pulse::Module {
+            index,
+            name: name.to_string(),
+            argument: argument.to_string(),
+        };
+        let ours = format!("source=bluez_input.x sink_input_properties={}", LOOPBACK_TAG);
+        let modules = vec![
+            module(7, "module-loopback", "source=mic sink=speakers"),
+            module(12, "module-loopback", &ours),
+            module(3, "module-null-sink", LOOPBACK_TAG),
+        ];
+        assert_eq!(tagged_loopbacks(&modules), vec![12]);
+    }
+
+    #[test]
+    fn finds_the_loopback_stream_to_duck() {
+        let sink_input = |index: u32, properties: &[(&str, &str)]| pulse::SinkInput {
+            index,
+            channels: 2,
+            properties: properties
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<BTreeMap<_, _>>(),
+        };
+        let sink_inputs = vec![
+            sink_input(40, &[("application.name", "headunit")]),
+            sink_input(41, &[("media.name", "Loopback"), ("headunit.route", "loopback")]),
+            sink_input(42, &[]),
+        ];
+        let tagged = tagged_sink_inputs(&sink_inputs);
+        assert_eq!(tagged.len(), 1);
+        assert_eq!(tagged[0].index, 41);
+    }
+}
@@ -0,0 +1,237 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::Notify;
+
+use super::display::BacklightService;
+use super::vehicle::{self, Position};
+use super::{illumination, light_sensor, settings, solar};
+
+const SETTINGS_NAME: &str = "auto_brightness";
+const NIGHT_MODE_EVENT: &str = "theme://night-mode";
+const UPDATE_INTERVAL: Duration = Duration::from_secs(30);
+// Slow enough that a step at dusk isn't noticeable
+const AUTO_FADE_MS: u32 = 5000;
+// Sun elevation at sunset, allowing for refraction and the size of the disc
+const SUNSET_ELEVATION: f64 = -0.833;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AutoBrightnessSettings {
+    pub enabled: bool,
+    /// Fixed location, used when there's no GPS fix
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Prefer the gpsd position over the fixed location
+    pub use_gps: bool,
+    pub day_brightness: u8,
+    pub night_brightness: u8,
+    /// Sun elevation (degrees) at and above which the day level is used
+    pub day_elevation: f64,
+    /// Sun elevation at and below which the night level is used
+    pub night_elevation: f64,
+}
+
+impl Default for AutoBrightnessSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            latitude: None,
+            longitude: None,
+            use_gps: true,
+            day_brightness: 80,
+            night_brightness: 20,
+            day_elevation: 6.0,
+            // Civil twilight
+            night_elevation: -6.0,
+        }
+    }
+}
+
+impl AutoBrightnessSettings {
+    fn location(&self) -> Option<Position> {
+        let gps = if self.use_gps { vehicle::position() } else { None };
+        gps.or(match (self.latitude, self.longitude) {
+            (Some(latitude), Some(longitude)) => Some(Position { latitude, longitude }),
+            _ => None,
+        })
+    }
+
+    /// Interpolate between the night and day levels through twilight
+    fn brightness_for(&self, elevation: f64) -> u8 {
+        let span = self.day_elevation - self.night_elevation;
+        let day_fraction = if span <= 0.0 {
+            if elevation >= self.day_elevation { 1.0 } else { 0.0 }
+        } else {
+            ((elevation - self.night_elevation) / span).clamp(0.0, 1.0)
+        };
+
+        let night = self.night_brightness.min(100) as f64;
+        let day = self.day_brightness.min(100) as f64;
+        (night + (day - night) * day_fraction).round() as u8
+    }
+}
+
+static SETTINGS: Lazy<Mutex<AutoBrightnessSettings>> = Lazy::new(|| Mutex::new(settings::load(SETTINGS_NAME)));
+// Whether the sun is down, None until a location is known
+static SUN_DOWN: Mutex<Option<bool>> = Mutex::new(None);
+// Last state sent to the UI
+static NIGHT_MODE: Mutex<Option<bool>> = Mutex::new(None);
+// Re-evaluate straight away after a settings change
+static WAKE: Lazy<Notify> = Lazy::new(Notify::new);
+
+fn now_unix_secs() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.0)
+}
+
+/// Whether the night palette is in use, for anything else that dims along with the UI
+pub fn night_mode() -> bool {
+    NIGHT_MODE.lock().ok().and_then(|night_mode| *night_mode).unwrap_or(false)
+}
+
+pub fn enabled() -> bool {
+    SETTINGS.lock().map(|config| config.enabled).unwrap_or(false)
+}
+
+/// Night mode is on after sunset or while the headlights are on
+fn publish_night_mode(app: &AppHandle) {
+    let sun_down = SUN_DOWN.lock().ok().and_then(|sun_down| *sun_down).unwrap_or(false);
+    let night = sun_down || illumination::headlights_on();
+
+    if let Ok(mut night_mode) = NIGHT_MODE.lock() {
+        if *night_mode != Some(night) {
+            *night_mode = Some(night);
+            if let Err(e) = app.emit(NIGHT_MODE_EVENT, night) {
+                println!("Failed to emit night mode: {}", e);
+            }
+        }
+    }
+}
+
+/// Re-check night mode and brightness after something else touched them
+pub fn refresh(app: &AppHandle) {
+    publish_night_mode(app);
+    WAKE.notify_one();
+}
+
+fn update(app: &AppHandle, last_level: &mut Option<u8>) {
+    let config = match SETTINGS.lock() {
+        Ok(config) => config.clone(),
+        Err(_) => return,
+    };
+    let Some(position) = config.location() else { return };
+
+    let elevation = solar::solar_elevation(now_unix_secs(), position.latitude, position.longitude);
+
+    if let Ok(mut sun_down) = SUN_DOWN.lock() {
+        *sun_down = Some(elevation < SUNSET_ELEVATION);
+    }
+    publish_night_mode(app);
+
+    // The headlight profile and the light sensor take over while they're active
+    if illumination::headlights_on() || light_sensor::active() {
+        *last_level = None;
+        return;
+    }
+    let backlight = app.state::<BacklightService>();
+    if !config.enabled {
+        // Back to the level the user picked
+        if last_level.take().is_some() {
+            if let Err(e) = backlight.set_auto(None, Some(AUTO_FADE_MS)) {
+                println!("Failed to restore brightness: {}", e);
+            }
+        }
+        return;
+    }
+
+    let level = config.brightness_for(elevation);
+    if *last_level != Some(level) {
+        *last_level = Some(level);
+        if let Err(e) = backlight.set_auto(Some(level), Some(AUTO_FADE_MS)) {
+            println!("Failed to apply auto brightness: {}", e);
+        }
+    }
+}
+
+/// Start following the sun. Called once from the app setup hook, after the backlight is managed.
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut last_level = None;
+        loop {
+            update(&app, &mut last_level);
+
+            tokio::select! {
+                _ = tokio::time::sleep(UPDATE_INTERVAL) => {}
+                _ = WAKE.notified() => {}
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_auto_brightness_settings() -> Result<AutoBrightnessSettings, String> {
+    let config = SETTINGS.lock().map_err(|e| e.to_string())?;
+    Ok(config.clone())
+}
+
+#[tauri::command]
+pub fn set_auto_brightness_settings(config: AutoBrightnessSettings) -> Result<(), String> {
+    settings::save(SETTINGS_NAME, &config)?;
+    *SETTINGS.lock().map_err(|e| e.to_string())? = config;
+    WAKE.notify_one();
+    Ok(())
+}
+
+/// Whether the UI should use its night palette
+#[tauri::command]
+pub fn get_night_mode() -> Result<bool, String> {
+    let night_mode = NIGHT_MODE.lock().map_err(|e| e.to_string())?;
+    Ok(night_mode.unwrap_or(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brightness_follows_twilight() {
+        let config = AutoBrightnessSettings::default();
+        assert_eq!(config.brightness_for(45.0), 80);
+        assert_eq!(config.brightness_for(6.0), 80);
+        assert_eq!(config.brightness_for(0.0), 50);
+        assert_eq!(config.brightness_for(-6.0), 20);
+        assert_eq!(config.brightness_for(-40.0), 20);
+    }
+
+    #[test]
+    fn inverted_thresholds_switch_hard() {
+        let config = AutoBrightnessSettings {
+            day_elevation: 0.0,
+            night_elevation: 0.0,
+            ..AutoBrightnessSettings::default()
+        };
+        assert_eq!(config.brightness_for(0.1), 80);
+        assert_eq!(config.brightness_for(-0.1), 20);
+    }
+
+    #[test]
+    fn fixed_location_needs_both_coordinates() {
+        let config = AutoBrightnessSettings {
+            use_gps: false,
+            latitude: Some(35.0),
+            ..AutoBrightnessSettings::default()
+        };
+        assert_eq!(config.location(), None);
+
+        let config = AutoBrightnessSettings {
+            longitude: Some(139.0),
+            ..config
+        };
+        assert_eq!(config.location(), Some(Position { latitude: 35.0, longitude: 139.0 }));
+    }
+}
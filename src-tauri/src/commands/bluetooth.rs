@@ -213,6 +213,18 @@ impl BluetoothManager {
 static BLUETOOTH_MANAGER: once_cell::sync::Lazy<Arc<RwLock<BluetoothManager>>> = 
     once_cell::sync::Lazy::new(|| Arc::new(RwLock::new(BluetoothManager::new())));
 
+/// Address of the currently connected device, if any
+pub async fn connected_device_address() -> Option<String> {
+    let manager = BLUETOOTH_MANAGER.read().await;
+    manager.refresh_devices().await.ok()?;
+    manager
+        .get_devices()
+        .await
+        .into_iter()
+        .find(|device| device.connected)
+        .map(|device| device.address)
+}
+
 #[tauri::command]
 pub async fn initialize_bluetooth() -> Result<(), String> {
     let mut manager = BLUETOOTH_MANAGER.write().await;
@@ -0,0 +1,312 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::http::{header, Request, Response, StatusCode};
+use tauri::UriSchemeResponder;
+use tokio::sync::watch;
+
+#[cfg(all(target_os = "linux", feature = "camera"))]
+use v4l::{buffer::Type, io::mmap::Stream, io::traits::CaptureStream, video::Capture, Device, Format, FourCC};
+
+use super::settings;
+
+const SETTINGS_NAME: &str = "camera";
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+// How long a frame request waits for the next frame before giving up
+const FRAME_WAIT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CameraSource {
+    /// V4L2 capture device, a USB camera or a v4l2loopback device
+    Device { path: String },
+    /// Concatenated JPEG frames, e.g. from `ffmpeg -i in.mp4 -c:v mjpeg -f mjpeg out.mjpeg`
+    Replay { path: String },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct CameraSettings {
+    pub source: CameraSource,
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            source: CameraSource::Device {
+                path: "/dev/video0".to_string(),
+            },
+            width: 640,
+            height: 480,
+            fps: 30,
+        }
+    }
+}
+
+pub trait FrameSource: Send {
+    /// Block until the next JPEG frame is ready
+    fn next_frame(&mut self) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>>;
+}
+
+#[cfg(all(target_os = "linux", feature = "camera"))]
+pub struct V4lSource {
+    stream: Stream<'static>,
+}
+
+#[cfg(all(target_os = "linux", feature = "camera"))]
+impl V4lSource {
+    const BUFFERS: u32 = 4;
+
+    pub fn open(path: &str, config: &CameraSettings) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let device = Device::with_path(path)?;
+        let mjpeg = FourCC::new(b"MJPG");
+        let format = device.set_format(&Format::new(config.width, config.height, mjpeg))?;
+        if format.fourcc != mjpeg {
+            return Err(format!("{} can't capture MJPEG, offered {}", path, format.fourcc).into());
+        }
+        device.set_params(&v4l::video::capture::Parameters::with_fps(config.fps))?;
+
+        Ok(V4lSource {
+            stream: Stream::with_buffers(&device, Type::VideoCapture, Self::BUFFERS)?,
+        })
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "camera"))]
+impl FrameSource for V4lSource {
+    fn next_frame(&mut self) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let (buffer, meta) = self.stream.next()?;
+        // The mapped buffer is sized for the worst case, the JPEG is only the used part
+        let used = (meta.bytesused as usize).min(buffer.len());
+        Ok(buffer[..used].to_vec())
+    }
+}
+
+/// Plays an MJPEG file on a loop at the configured frame rate
+pub struct ReplaySource {
+    data: Vec<u8>,
+    frames: Vec<Range<usize>>,
+    index: usize,
+    interval: Duration,
+    next_due: Instant,
+}
+
+impl ReplaySource {
+    pub fn open(path: &str, fps: u32) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let data = std::fs::read(path)?;
+        let frames = split_jpeg_frames(&data);
+        if frames.is_empty() {
+            return Err(format!("No JPEG frames in {}", path).into());
+        }
+
+        Ok(ReplaySource {
+            data,
+            frames,
+            index: 0,
+            interval: Duration::from_secs(1) / fps.max(1),
+            next_due: Instant::now(),
+        })
+    }
+}
+
+impl FrameSource for ReplaySource {
+    fn next_frame(&mut self) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let now = Instant::now();
+        if self.next_due > now {
+            std::thread::sleep(self.next_due - now);
+        }
+        self.next_due = self.next_due.max(now) + self.interval;
+
+        let frame = self.data[self.frames[self.index].clone()].to_vec();
+        self.index = (self.index + 1) % self.frames.len();
+        Ok(frame)
+    }
+}
+
+/// Byte ranges of each complete JPEG (SOI to EOI) in an MJPEG stream
+pub fn split_jpeg_frames(data: &[u8]) -> Vec<Range<usize>> {
+    const SOI: [u8; 2] = [0xFF, 0xD8];
+    const EOI: [u8; 2] = [0xFF, 0xD9];
+
+    let mut frames = vec![];
+    let mut start = None;
+    let mut i = 0;
+    while i + 1 < data.len() {
+        let marker = [data[i], data[i + 1]];
+        if marker == SOI && start.is_none() {
+            start = Some(i);
+            i += 2;
+        } else if marker == EOI && start.is_some() {
+            if let Some(start) = start.take() {
+                frames.push(start..i + 2);
+            }
+            i += 2;
+        } else {
+            i += 1;
+        }
+    }
+    frames
+}
+
+fn open_source(config: &CameraSettings) -> Result<Box<dyn FrameSource>, Box<dyn Error + Send + Sync>> {
+    match &config.source {
+        CameraSource::Replay { path } => Ok(Box::new(ReplaySource::open(path, config.fps)?)),
+        #[cfg(all(target_os = "linux", feature = "camera"))]
+        CameraSource::Device { path } => Ok(Box::new(V4lSource::open(path, config)?)),
+        #[cfg(not(all(target_os = "linux", feature = "camera")))]
+        CameraSource::Device { .. } => Err("Built without the camera feature".into()),
+    }
+}
+
+static SETTINGS: Lazy<Mutex<CameraSettings>> = Lazy::new(|| Mutex::new(settings::load(SETTINGS_NAME)));
+static FRAME: Lazy<watch::Sender<Option<Arc<Vec<u8>>>>> = Lazy::new(|| watch::channel(None).0);
+// Bumped on every start and stop, so a capture thread knows when it's been replaced
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Start capturing into the frame buffer, restarting with the current settings if already running
+pub fn start() {
+    let generation = GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    let current = move || GENERATION.load(Ordering::SeqCst) == generation;
+
+    std::thread::spawn(move || {
+        let config = match SETTINGS.lock() {
+            Ok(config) => config.clone(),
+            Err(_) => return,
+        };
+
+        while current() {
+            let mut source = match open_source(&config) {
+                Ok(source) => source,
+                Err(e) => {
+                    println!("Failed to open camera: {}", e);
+                    std::thread::sleep(RETRY_DELAY);
+                    continue;
+                }
+            };
+
+            while current() {
+                match source.next_frame() {
+                    Ok(frame) => {
+                        FRAME.send_replace(Some(Arc::new(frame)));
+                    }
+                    Err(e) => {
+                        println!("Camera capture failed: {}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// Stop capturing and drop the last frame, so a stale image is never shown
+pub fn stop() {
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+    FRAME.send_replace(None);
+}
+
+/// Serves `camera://localhost/frame`. Each request waits for the next frame,
+/// so the UI can fetch in a loop and get the camera's frame rate.
+pub fn handle_frame_request(request: Request<Vec<u8>>, responder: UriSchemeResponder) {
+    if request.uri().path() != "/frame" {
+        responder.respond(
+            Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Vec::new())
+                .unwrap_or_default(),
+        );
+        return;
+    }
+
+    let mut frames = FRAME.subscribe();
+    tauri::async_runtime::spawn(async move {
+        let _ = tokio::time::timeout(FRAME_WAIT, frames.changed()).await;
+        let frame = frames.borrow_and_update().clone();
+
+        let response = match frame {
+            Some(frame) => Response::builder()
+                .header(header::CONTENT_TYPE, "image/jpeg")
+                .header(header::CACHE_CONTROL, "no-store")
+                // The dev server serves the UI from a different origin
+                .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+                .body(frame.to_vec()),
+            None => Response::builder()
+                .status(StatusCode::NO_CONTENT)
+                .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+                .body(Vec::new()),
+        };
+        responder.respond(response.unwrap_or_default());
+    });
+}
+
+#[tauri::command]
+pub fn get_camera_settings() -> Result<CameraSettings, String> {
+    let config = SETTINGS.lock().map_err(|e| e.to_string())?;
+    Ok(config.clone())
+}
+
+#[tauri::command]
+pub fn set_camera_settings(config: CameraSettings) -> Result<(), String> {
+    settings::save(SETTINGS_NAME, &config)?;
+    *SETTINGS.lock().map_err(|e| e.to_string())? = config;
+    Ok(())
+}
+
+/// Show the camera without reverse engaged, for aiming it
+#[tauri::command]
+pub fn start_camera_preview() -> Result<(), String> {
+    start();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn stop_camera_preview() -> Result<(), String> {
+    stop();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jpeg(body: &[u8]) -> Vec<u8> {
+        [&[0xFF, 0xD8][..], body, &[0xFF, 0xD9]].concat()
+    }
+
+    #[test]
+    fn splits_concatenated_jpegs() {
+        let first = jpeg(&[1, 2, 3]);
+        let second = jpeg(&[4, 0xFF, 5]);
+        let data = [first.clone(), second.clone()].concat();
+
+        let frames = split_jpeg_frames(&data);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(&data[frames[0].clone()], &first[..]);
+        assert_eq!(&data[frames[1].clone()], &second[..]);
+    }
+
+    #[test]
+    fn truncated_frame_is_dropped() {
+        let data = [jpeg(&[1, 2]), vec![0xFF, 0xD8, 9, 9]].concat();
+        assert_eq!(split_jpeg_frames(&data), vec![0..6]);
+    }
+
+    #[test]
+    fn replay_loops() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reverse.mjpeg");
+        std::fs::write(&path, [jpeg(&[1]), jpeg(&[2])].concat()).unwrap();
+
+        let mut source = ReplaySource::open(path.to_str().unwrap(), 1000).unwrap();
+        assert_eq!(source.next_frame().unwrap(), jpeg(&[1]));
+        assert_eq!(source.next_frame().unwrap(), jpeg(&[2]));
+        assert_eq!(source.next_frame().unwrap(), jpeg(&[1]));
+    }
+}
@@ -1,25 +1,93 @@
+use serde::{Deserialize, Serialize};
 use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, State};
+
+#[cfg(target_os = "linux")]
 use rppal::pwm::{Channel, Polarity, Pwm};
 
-pub struct DisplayBacklight {
+use super::settings;
+
+const SETTINGS_NAME: &str = "display";
+const FADE_STEP: Duration = Duration::from_millis(10);
+const SYSFS_BACKLIGHT_ROOT: &str = "/sys/class/backlight";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BacklightBackend {
+    /// Use a sysfs backlight if the kernel exposes one, otherwise PWM
+    Auto,
+    /// GPIO18 driven by the PWM0 channel
+    Pwm,
+    /// `/sys/class/backlight/<device>`, used by the official DSI display
+    Sysfs,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct DisplaySettings {
+    /// Last brightness the user picked, restored at startup
+    pub brightness: u8,
+    /// How long a brightness change takes to fade in
+    pub fade_ms: u32,
+    pub backend: BacklightBackend,
+    /// Device under /sys/class/backlight, None to use the first one found
+    pub sysfs_device: Option<String>,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        Self {
+            brightness: 60,
+            fade_ms: 300,
+            backend: BacklightBackend::Auto,
+            sysfs_device: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BacklightInfo {
+    pub backend: BacklightBackend,
+    pub device: Option<String>,
+    /// Number of hardware steps behind the 0-100% range
+    pub max_brightness: u32,
+}
+
+pub trait Backlight {
+    /// Set the level as a percentage of `max_brightness`
+    fn set_brightness(&self, percentage: u8) -> Result<(), Box<dyn Error>>;
+
+    fn info(&self) -> BacklightInfo;
+}
+
+#[cfg(target_os = "linux")]
+pub struct PwmBacklight {
     pwm: Pwm,
 }
 
-impl DisplayBacklight {
-    pub fn new() -> Result<Self, Box<dyn Error>> {
+#[cfg(target_os = "linux")]
+impl PwmBacklight {
+    pub fn new(percentage: u8) -> Result<Self, Box<dyn Error>> {
         // SunFounder 7" display typically uses GPIO18 for backlight control
         let pwm = Pwm::with_frequency(
             Channel::Pwm0,  // GPIO18 is on PWM0
             100.0,          // 100 Hz frequency
-            0.0,            // Start with 0% duty cycle
+            percentage.min(100) as f64 / 100.0, // Start at the requested level so the screen doesn't flash dark
             Polarity::Normal,
             true,           // Enable PWM
         )?;
 
-        Ok(DisplayBacklight { pwm })
+        Ok(PwmBacklight { pwm })
     }
+}
 
-    pub fn set_brightness(&self, percentage: u8) -> Result<(), Box<dyn Error>> {
+#[cfg(target_os = "linux")]
+impl Backlight for PwmBacklight {
+    fn set_brightness(&self, percentage: u8) -> Result<(), Box<dyn Error>> {
         if percentage > 100 {
             return Err("Brightness percentage must be 0-100".into());
         }
@@ -27,30 +95,441 @@ impl DisplayBacklight {
         // Convert percentage to duty cycle (0.0 to 1.0)
         let duty_cycle = percentage as f64 / 100.0;
         self.pwm.set_duty_cycle(duty_cycle)?;
-        println!("Set PWM duty cycle to {}%", percentage);
         Ok(())
     }
+
+    fn info(&self) -> BacklightInfo {
+        BacklightInfo {
+            backend: BacklightBackend::Pwm,
+            device: Some("pwm0".to_string()),
+            max_brightness: 100,
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub struct PwmBacklight;
+
+#[cfg(not(target_os = "linux"))]
+impl PwmBacklight {
+    pub fn new(_percentage: u8) -> Result<Self, Box<dyn Error>> {
+        Err("PWM backlight not available on this platform".into())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl Backlight for PwmBacklight {
+    fn set_brightness(&self, _percentage: u8) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn info(&self) -> BacklightInfo {
+        BacklightInfo {
+            backend: BacklightBackend::Pwm,
+            device: None,
+            max_brightness: 100,
+        }
+    }
 }
 
-#[tauri::command]
-pub fn set_brightness(value: u8) -> Result<(), String> {
-    println!("Attempting to set brightness to {}", value);
-    let backlight = match DisplayBacklight::new() {
-        Ok(b) => b,
-        Err(e) => {
-            println!("Failed to initialize backlight: {}", e);
-            return Err(format!("Failed to initialize backlight: {}", e));
+/// Backlight class device, driven through its `brightness` attribute
+pub struct SysfsBacklight {
+    dir: PathBuf,
+    max_brightness: u32,
+}
+
+impl SysfsBacklight {
+    pub fn open(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let max_brightness: u32 = fs::read_to_string(dir.join("max_brightness"))
+            .map_err(|e| format!("Failed to read max_brightness from {}: {}", dir.display(), e))?
+            .trim()
+            .parse()?;
+        if max_brightness == 0 {
+            return Err(format!("{} reports a max_brightness of 0", dir.display()).into());
+        }
+
+        Ok(SysfsBacklight {
+            dir: dir.to_path_buf(),
+            max_brightness,
+        })
+    }
+
+    /// First backlight device under `root` (normally /sys/class/backlight)
+    pub fn detect(root: &Path) -> Option<PathBuf> {
+        let mut devices: Vec<PathBuf> = fs::read_dir(root)
+            .ok()?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.join("max_brightness").exists())
+            .collect();
+        devices.sort();
+        devices.into_iter().next()
+    }
+}
+
+impl Backlight for SysfsBacklight {
+    fn set_brightness(&self, percentage: u8) -> Result<(), Box<dyn Error>> {
+        if percentage > 100 {
+            return Err("Brightness percentage must be 0-100".into());
+        }
+
+        let raw = (percentage as f64 / 100.0 * self.max_brightness as f64).round() as u32;
+        fs::write(self.dir.join("brightness"), raw.to_string())?;
+        Ok(())
+    }
+
+    fn info(&self) -> BacklightInfo {
+        BacklightInfo {
+            backend: BacklightBackend::Sysfs,
+            device: self.dir.file_name().map(|name| name.to_string_lossy().to_string()),
+            max_brightness: self.max_brightness,
         }
+    }
+}
+
+/// Open the backlight picked in `config`, looking for sysfs devices under `sysfs_root`
+pub fn open_backlight(config: &DisplaySettings, sysfs_root: &Path, percentage: u8) -> Result<Box<dyn Backlight>, Box<dyn Error>> {
+    let sysfs_dir = match &config.sysfs_device {
+        Some(device) => Some(sysfs_root.join(device)),
+        None => SysfsBacklight::detect(sysfs_root),
+    };
+
+    let backlight: Box<dyn Backlight> = match (config.backend, sysfs_dir) {
+        (BacklightBackend::Pwm, _) | (BacklightBackend::Auto, None) => Box::new(PwmBacklight::new(percentage)?),
+        (BacklightBackend::Sysfs | BacklightBackend::Auto, Some(dir)) => Box::new(SysfsBacklight::open(&dir)?),
+        (BacklightBackend::Sysfs, None) => return Err(format!("No backlight found in {}", sysfs_root.display()).into()),
     };
-    
-    match backlight.set_brightness(value) {
-        Ok(_) => {
-            println!("Successfully set brightness to {}", value);
-            Ok(())
-        },
+    backlight.set_brightness(percentage)?;
+
+    Ok(backlight)
+}
+
+struct FadeState {
+    current: f32,
+    from: f32,
+    target: u8,
+    // Level picked by auto-brightness or the headlight profile, used over the target but never saved
+    auto: Option<u8>,
+    // Cap applied on top of the target while the display is dimmed or off
+    limit: Option<u8>,
+    started: Instant,
+    // Length of the fade in progress
+    duration_ms: u32,
+    // Configured fade length, used when a caller doesn't pick one
+    fade_ms: u32,
+    // Last level written to the settings file
+    saved: u8,
+    info: Option<BacklightInfo>,
+    error: Option<String>,
+}
+
+impl FadeState {
+    /// Level the backlight is heading to
+    fn goal(&self) -> u8 {
+        let level = self.auto.unwrap_or(self.target);
+        match self.limit {
+            Some(limit) => level.min(limit),
+            None => level,
+        }
+    }
+
+    fn restart(&mut self, fade_ms: Option<u32>) {
+        self.from = self.current;
+        self.started = Instant::now();
+        self.duration_ms = fade_ms.unwrap_or(self.fade_ms);
+    }
+}
+
+/// Owns the backlight for the life of the app and fades it between levels
+/// on a worker thread, so slider ticks never re-open the PWM channel.
+pub struct BacklightService {
+    state: Arc<(Mutex<FadeState>, Condvar)>,
+}
+
+impl BacklightService {
+    pub fn start(config: DisplaySettings) -> Self {
+        let state = Arc::new((
+            Mutex::new(FadeState {
+                current: config.brightness as f32,
+                from: config.brightness as f32,
+                target: config.brightness,
+                auto: None,
+                limit: None,
+                started: Instant::now(),
+                duration_ms: config.fade_ms,
+                fade_ms: config.fade_ms,
+                saved: config.brightness,
+                info: None,
+                error: None,
+            }),
+            Condvar::new(),
+        ));
+
+        let worker_state = state.clone();
+        std::thread::spawn(move || run_fades(worker_state, config));
+
+        Self { state }
+    }
+
+    /// Start fading toward `level`, from wherever the backlight is right now.
+    /// This is the user's level, so it's saved and takes over from any automatic level.
+    pub fn set_target(&self, level: u8, fade_ms: Option<u32>) -> Result<(), String> {
+        let (lock, wake) = &*self.state;
+        let mut state = lock.lock().map_err(|e| e.to_string())?;
+
+        state.target = level;
+        state.auto = None;
+        state.restart(fade_ms);
+        wake.notify_one();
+
+        match &state.error {
+            Some(e) => Err(e.clone()),
+            None => Ok(()),
+        }
+    }
+
+    /// Fade to an automatic level without touching the saved one, None to go back to the user's level
+    pub fn set_auto(&self, level: Option<u8>, fade_ms: Option<u32>) -> Result<(), String> {
+        let (lock, wake) = &*self.state;
+        let mut state = lock.lock().map_err(|e| e.to_string())?;
+        if state.auto == level {
+            return Ok(());
+        }
+
+        state.auto = level;
+        state.restart(fade_ms);
+        wake.notify_one();
+
+        match &state.error {
+            Some(e) => Err(e.clone()),
+            None => Ok(()),
+        }
+    }
+
+    /// Cap the output below the target without losing it, None to lift the cap
+    pub fn set_limit(&self, limit: Option<u8>, fade_ms: Option<u32>) -> Result<(), String> {
+        let (lock, wake) = &*self.state;
+        let mut state = lock.lock().map_err(|e| e.to_string())?;
+
+        state.limit = limit;
+        state.restart(fade_ms);
+        wake.notify_one();
+        Ok(())
+    }
+
+    pub fn target(&self) -> Result<u8, String> {
+        let (lock, _) = &*self.state;
+        Ok(lock.lock().map_err(|e| e.to_string())?.target)
+    }
+
+    pub fn info(&self) -> Result<BacklightInfo, String> {
+        let (lock, _) = &*self.state;
+        let state = lock.lock().map_err(|e| e.to_string())?;
+        match (&state.info, &state.error) {
+            (Some(info), _) => Ok(info.clone()),
+            (None, Some(e)) => Err(e.clone()),
+            (None, None) => Err("Backlight not available".to_string()),
+        }
+    }
+}
+
+fn open(lock: &Mutex<FadeState>, config: &DisplaySettings, level: u8) -> Option<Box<dyn Backlight>> {
+    let result = open_backlight(config, Path::new(SYSFS_BACKLIGHT_ROOT), level);
+    let Ok(mut state) = lock.lock() else { return None };
+
+    match result {
+        Ok(backlight) => {
+            let info = backlight.info();
+            println!("Using {:?} backlight {:?} (max brightness {})", info.backend, info.device, info.max_brightness);
+            state.info = Some(info);
+            state.error = None;
+            Some(backlight)
+        }
         Err(e) => {
-            println!("Failed to set brightness: {}", e);
-            Err(format!("Failed to set brightness: {}", e))
+            if state.error.is_none() {
+                println!("Failed to initialize backlight: {}", e);
+            }
+            state.error = Some(format!("Failed to initialize backlight: {}", e));
+            None
+        }
+    }
+}
+
+fn run_fades(state: Arc<(Mutex<FadeState>, Condvar)>, mut config: DisplaySettings) {
+    let (lock, wake) = &*state;
+    let mut backlight = open(lock, &config, config.brightness);
+
+    loop {
+        let Ok(mut guard) = lock.lock() else { return };
+
+        // Sleep until there's somewhere to fade to, saving the level once we've settled
+        while guard.current == guard.goal() as f32 {
+            if guard.saved != guard.target {
+                guard.saved = guard.target;
+                config.brightness = guard.target;
+                if let Err(e) = settings::save(SETTINGS_NAME, &config) {
+                    println!("Failed to save display settings: {}", e);
+                }
+            }
+
+            guard = match wake.wait(guard) {
+                Ok(guard) => guard,
+                Err(_) => return,
+            };
+        }
+
+        let progress = if guard.duration_ms == 0 {
+            1.0
+        } else {
+            (guard.started.elapsed().as_secs_f32() * 1000.0 / guard.duration_ms as f32).min(1.0)
+        };
+        guard.current = guard.from + (guard.goal() as f32 - guard.from) * progress;
+        let level = guard.current.round() as u8;
+        drop(guard);
+
+        // PWM0 may have been busy at startup, try again when asked for a new level
+        if backlight.is_none() {
+            backlight = open(lock, &config, level);
         }
+
+        if let Some(result) = backlight.as_ref().map(|b| b.set_brightness(level)) {
+            if let Ok(mut state) = lock.lock() {
+                state.error = result.err().map(|e| format!("Failed to set brightness: {}", e));
+            }
+        }
+
+        std::thread::sleep(FADE_STEP);
+    }
+}
+
+/// Create the backlight service with the last saved level. Called once from the app setup hook.
+pub fn init(app: &AppHandle) {
+    app.manage(BacklightService::start(settings::load(SETTINGS_NAME)));
+}
+
+#[tauri::command]
+pub fn set_brightness(value: u8, fade_ms: Option<u32>, backlight: State<'_, BacklightService>) -> Result<(), String> {
+    if value > 100 {
+        return Err("Brightness percentage must be 0-100".to_string());
+    }
+
+    println!("Setting brightness to {}", value);
+    backlight.set_target(value, fade_ms)
+}
+
+#[tauri::command]
+pub fn get_brightness(backlight: State<'_, BacklightService>) -> Result<u8, String> {
+    backlight.target()
+}
+
+#[tauri::command]
+pub fn get_backlight_info(backlight: State<'_, BacklightService>) -> Result<BacklightInfo, String> {
+    backlight.info()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_device(root: &Path, name: &str, max: u32) -> PathBuf {
+        let dir = root.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("max_brightness"), format!("{}\n", max)).unwrap();
+        fs::write(dir.join("brightness"), "0\n").unwrap();
+        dir
+    }
+
+    fn raw_brightness(dir: &Path) -> String {
+        fs::read_to_string(dir.join("brightness")).unwrap()
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn sysfs_scales_percentage_to_max_brightness() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = fake_device(root.path(), "rpi_backlight", 255);
+        let backlight = SysfsBacklight::open(&dir).unwrap();
+
+        backlight.set_brightness(50).unwrap();
+        assert_eq!(raw_brightness(&dir), "128");
+        backlight.set_brightness(100).unwrap();
+        assert_eq!(raw_brightness(&dir), "255");
+        backlight.set_brightness(0).unwrap();
+        assert_eq!(raw_brightness(&dir), "0");
+
+        assert!(backlight.set_brightness(101).is_err());
+        assert_eq!(backlight.info().max_brightness, 255);
+        assert_eq!(backlight.info().device.as_deref(), Some("rpi_backlight"));
+    }
+
+    #[test]
+    fn sysfs_rejects_missing_or_zero_max() {
+        let root = tempfile::tempdir().unwrap();
+        assert!(SysfsBacklight::open(&root.path().join("missing")).is_err());
+
+        let dir = fake_device(root.path(), "broken", 0);
+        assert!(SysfsBacklight::open(&dir).is_err());
+    }
+
+    #[test]
+    fn auto_prefers_sysfs_and_applies_initial_level() {
+        let root = tempfile::tempdir().unwrap();
+        fake_device(root.path(), "b_backlight", 100);
+        let first = fake_device(root.path(), "a_backlight", 31);
+
+        let backlight = open_backlight(&DisplaySettings::default(), root.path(), 60).unwrap();
+        let info = backlight.info();
+        assert_eq!(info.backend, BacklightBackend::Sysfs);
+        assert_eq!(info.device.as_deref(), Some("a_backlight"));
+        assert_eq!(info.max_brightness, 31);
+        assert_eq!(raw_brightness(&first), "19");
+    }
+
+    #[test]
+    fn configured_sysfs_device_is_used() {
+        let root = tempfile::tempdir().unwrap();
+        fake_device(root.path(), "a_backlight", 100);
+        let chosen = fake_device(root.path(), "10-0045", 255);
+
+        let config = DisplaySettings {
+            backend: BacklightBackend::Sysfs,
+            sysfs_device: Some("10-0045".to_string()),
+            ..DisplaySettings::default()
+        };
+        let backlight = open_backlight(&config, root.path(), 100).unwrap();
+        assert_eq!(backlight.info().device.as_deref(), Some("10-0045"));
+        assert_eq!(raw_brightness(&chosen), "255");
+    }
+
+    #[test]
+    fn sysfs_without_device_fails() {
+        let root = tempfile::tempdir().unwrap();
+        let config = DisplaySettings {
+            backend: BacklightBackend::Sysfs,
+            ..DisplaySettings::default()
+        };
+        assert!(open_backlight(&config, root.path(), 50).is_err());
+    }
+
+    #[test]
+    fn automatic_level_overrides_target_under_the_limit() {
+        let mut state = FadeState {
+            current: 80.0,
+            from: 80.0,
+            target: 80,
+            auto: None,
+            limit: None,
+            started: Instant::now(),
+            duration_ms: 0,
+            fade_ms: 0,
+            saved: 80,
+            info: None,
+            error: None,
+        };
+        state.auto = Some(30);
+        assert_eq!(state.goal(), 30);
+        state.limit = Some(10);
+        assert_eq!(state.goal(), 10);
+        state.auto = None;
+        state.limit = None;
+        assert_eq!(state.goal(), 80);
+    }
+}
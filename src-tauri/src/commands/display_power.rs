@@ -0,0 +1,338 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::process::Command;
+use tokio::sync::Notify;
+
+use super::display::BacklightService;
+use super::settings;
+
+const SETTINGS_NAME: &str = "display_power";
+const POWER_STATE_EVENT: &str = "display://power-state";
+const TICK_INTERVAL: Duration = Duration::from_millis(500);
+const INPUT_SCAN_INTERVAL: Duration = Duration::from_secs(10);
+const INPUT_DEVICE_DIR: &str = "/dev/input";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DisplayPowerState {
+    Active,
+    Dimmed,
+    Screensaver,
+    Off,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OffMethod {
+    /// Drop the backlight to 0, works with any panel
+    Backlight,
+    /// Power the output down through the compositor (wlopm) or X server (xset)
+    Dpms,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct DisplayPowerSettings {
+    /// Idle seconds before each stage, 0 to skip that stage
+    pub dim_after_secs: u64,
+    pub screensaver_after_secs: u64,
+    pub off_after_secs: u64,
+    /// Backlight cap while dimmed or showing the screensaver
+    pub dimmed_brightness: u8,
+    pub off_method: OffMethod,
+}
+
+impl Default for DisplayPowerSettings {
+    fn default() -> Self {
+        Self {
+            dim_after_secs: 60,
+            screensaver_after_secs: 120,
+            off_after_secs: 900,
+            dimmed_brightness: 20,
+            off_method: OffMethod::Backlight,
+        }
+    }
+}
+
+impl DisplayPowerSettings {
+    /// Deepest stage whose timeout has passed
+    fn state_after(&self, idle: Duration) -> DisplayPowerState {
+        let passed = |secs: u64| secs > 0 && idle >= Duration::from_secs(secs);
+
+        if passed(self.off_after_secs) {
+            DisplayPowerState::Off
+        } else if passed(self.screensaver_after_secs) {
+            DisplayPowerState::Screensaver
+        } else if passed(self.dim_after_secs) {
+            DisplayPowerState::Dimmed
+        } else {
+            DisplayPowerState::Active
+        }
+    }
+}
+
+pub struct DisplayPower {
+    state: DisplayPowerState,
+    last_activity: Instant,
+}
+
+impl DisplayPower {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            state: DisplayPowerState::Active,
+            last_activity: now,
+        }
+    }
+
+    pub fn state(&self) -> DisplayPowerState {
+        self.state
+    }
+
+    /// Touch, key press or any other input, restarts the idle timeout
+    pub fn activity(&mut self, now: Instant) {
+        self.last_activity = now;
+    }
+
+    /// Returns the previous and new state when the display should change
+    pub fn tick(&mut self, config: &DisplayPowerSettings, now: Instant) -> Option<(DisplayPowerState, DisplayPowerState)> {
+        let next = config.state_after(now.saturating_duration_since(self.last_activity));
+        if next == self.state {
+            return None;
+        }
+
+        let previous = self.state;
+        self.state = next;
+        Some((previous, next))
+    }
+}
+
+static SETTINGS: Lazy<Mutex<DisplayPowerSettings>> = Lazy::new(|| Mutex::new(settings::load(SETTINGS_NAME)));
+static POWER: Lazy<Mutex<DisplayPower>> = Lazy::new(|| Mutex::new(DisplayPower::new(Instant::now())));
+static WAKE: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// Wake the display and restart the idle timeout
+pub fn wake() {
+    if let Ok(mut power) = POWER.lock() {
+        power.activity(Instant::now());
+    }
+    WAKE.notify_one();
+}
+
+async fn set_dpms(on: bool) -> Result<(), String> {
+    // wlr-output-power-management on Wayland compositors, DPMS on X11
+    let mut command = if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        let mut command = Command::new("wlopm");
+        command.args([if on { "--on" } else { "--off" }, "*"]);
+        command
+    } else {
+        let mut command = Command::new("xset");
+        command.args(["dpms", "force", if on { "on" } else { "off" }]);
+        command
+    };
+
+    let output = command.output().await.map_err(|e| format!("Failed to run DPMS command: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(())
+}
+
+async fn apply(app: &AppHandle, config: &DisplayPowerSettings, previous: DisplayPowerState, state: DisplayPowerState) {
+    println!("Display power {:?} -> {:?}", previous, state);
+
+    if previous == DisplayPowerState::Off && config.off_method == OffMethod::Dpms {
+        if let Err(e) = set_dpms(true).await {
+            println!("Failed to turn the display on: {}", e);
+        }
+    }
+
+    let limit = match state {
+        DisplayPowerState::Active => None,
+        DisplayPowerState::Dimmed | DisplayPowerState::Screensaver => Some(config.dimmed_brightness),
+        DisplayPowerState::Off => match config.off_method {
+            OffMethod::Backlight => Some(0),
+            OffMethod::Dpms => Some(config.dimmed_brightness),
+        },
+    };
+    // Come back instantly on touch, dim gently
+    let fade_ms = if state == DisplayPowerState::Active { Some(0) } else { None };
+    if let Err(e) = app.state::<BacklightService>().set_limit(limit, fade_ms) {
+        println!("Failed to limit backlight: {}", e);
+    }
+
+    if state == DisplayPowerState::Off && config.off_method == OffMethod::Dpms {
+        if let Err(e) = set_dpms(false).await {
+            println!("Failed to turn the display off: {}", e);
+        }
+    }
+
+    if let Err(e) = app.emit(POWER_STATE_EVENT, state) {
+        println!("Failed to emit display power state: {}", e);
+    }
+}
+
+/// Treat any evdev event (touch, keys, knobs) as activity, so the display
+/// wakes even while the webview isn't showing anything
+fn watch_input_devices() {
+    let watched: Arc<Mutex<HashSet<PathBuf>>> = Arc::new(Mutex::new(HashSet::new()));
+
+    std::thread::spawn(move || loop {
+        let devices = std::fs::read_dir(INPUT_DEVICE_DIR)
+            .map(|entries| entries.filter_map(|entry| entry.ok().map(|e| e.path())).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        for path in devices {
+            let is_event_device = path
+                .file_name()
+                .map(|name| name.to_string_lossy().starts_with("event"))
+                .unwrap_or(false);
+            if !is_event_device {
+                continue;
+            }
+
+            if watched.lock().map(|watching| watching.contains(&path)).unwrap_or(true) {
+                continue;
+            }
+            let Ok(mut file) = File::open(&path) else { continue };
+            if let Ok(mut watching) = watched.lock() {
+                watching.insert(path.clone());
+            }
+
+            let watched = watched.clone();
+            std::thread::spawn(move || {
+                let mut buffer = [0u8; 256];
+                while matches!(file.read(&mut buffer), Ok(n) if n > 0) {
+                    wake();
+                }
+                // Unplugged, pick it up again if it comes back
+                if let Ok(mut watching) = watched.lock() {
+                    watching.remove(&path);
+                }
+            });
+        }
+
+        std::thread::sleep(INPUT_SCAN_INTERVAL);
+    });
+}
+
+/// Start the idle timeouts. Called once from the app setup hook, after the backlight is managed.
+pub fn start(app: AppHandle) {
+    watch_input_devices();
+
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let config = match SETTINGS.lock() {
+                Ok(config) => config.clone(),
+                Err(_) => return,
+            };
+            let change = match POWER.lock() {
+                Ok(mut power) => power.tick(&config, Instant::now()),
+                Err(_) => return,
+            };
+            if let Some((previous, state)) = change {
+                apply(&app, &config, previous, state).await;
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(TICK_INTERVAL) => {}
+                _ = WAKE.notified() => {}
+            }
+        }
+    });
+}
+
+/// Called by the UI on touches and clicks it sees
+#[tauri::command]
+pub fn report_user_activity() -> Result<(), String> {
+    wake();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_display_power_state() -> Result<DisplayPowerState, String> {
+    let power = POWER.lock().map_err(|e| e.to_string())?;
+    Ok(power.state())
+}
+
+#[tauri::command]
+pub fn get_display_power_settings() -> Result<DisplayPowerSettings, String> {
+    let config = SETTINGS.lock().map_err(|e| e.to_string())?;
+    Ok(config.clone())
+}
+
+#[tauri::command]
+pub fn set_display_power_settings(config: DisplayPowerSettings) -> Result<(), String> {
+    settings::save(SETTINGS_NAME, &config)?;
+    *SETTINGS.lock().map_err(|e| e.to_string())? = config;
+    WAKE.notify_one();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(start: Instant, secs: u64) -> Instant {
+        start + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn walks_through_each_stage() {
+        let config = DisplayPowerSettings::default();
+        let start = Instant::now();
+        let mut power = DisplayPower::new(start);
+
+        assert_eq!(power.tick(&config, at(start, 59)), None);
+        assert_eq!(power.tick(&config, at(start, 60)), Some((DisplayPowerState::Active, DisplayPowerState::Dimmed)));
+        assert_eq!(power.tick(&config, at(start, 90)), None);
+        assert_eq!(
+            power.tick(&config, at(start, 120)),
+            Some((DisplayPowerState::Dimmed, DisplayPowerState::Screensaver))
+        );
+        assert_eq!(power.tick(&config, at(start, 900)), Some((DisplayPowerState::Screensaver, DisplayPowerState::Off)));
+        assert_eq!(power.state(), DisplayPowerState::Off);
+    }
+
+    #[test]
+    fn activity_wakes_from_any_stage() {
+        let config = DisplayPowerSettings::default();
+        let start = Instant::now();
+        let mut power = DisplayPower::new(start);
+        power.tick(&config, at(start, 1000));
+
+        power.activity(at(start, 1001));
+        assert_eq!(power.tick(&config, at(start, 1001)), Some((DisplayPowerState::Off, DisplayPowerState::Active)));
+        assert_eq!(power.tick(&config, at(start, 1050)), None);
+    }
+
+    #[test]
+    fn disabled_stages_are_skipped() {
+        let config = DisplayPowerSettings {
+            dim_after_secs: 0,
+            screensaver_after_secs: 0,
+            off_after_secs: 300,
+            ..DisplayPowerSettings::default()
+        };
+        let start = Instant::now();
+        let mut power = DisplayPower::new(start);
+
+        assert_eq!(power.tick(&config, at(start, 299)), None);
+        assert_eq!(power.tick(&config, at(start, 300)), Some((DisplayPowerState::Active, DisplayPowerState::Off)));
+    }
+
+    #[test]
+    fn long_idle_jumps_straight_to_deepest_stage() {
+        let config = DisplayPowerSettings::default();
+        let start = Instant::now();
+        let mut power = DisplayPower::new(start);
+
+        assert_eq!(power.tick(&config, at(start, 3600)), Some((DisplayPowerState::Active, DisplayPowerState::Off)));
+    }
+}
@@ -0,0 +1,698 @@
+use once_cell::sync::Lazy;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::Duration;
+
+use super::{audio, settings};
+
+const SETTINGS_NAME: &str = "audio";
+
+/// Center frequencies of the EQ bands, matching the audio settings page
+pub const EQ_FREQUENCIES: [f32; 9] = [32.0, 64.0, 125.0, 250.0, 500.0, 1000.0, 4000.0, 8000.0, 16000.0];
+const EQ_Q: f32 = 1.41;
+
+// The volume slider is treated as this much attenuation from 100% to 0%
+const VOLUME_RANGE_DB: f32 = 60.0;
+const MAX_LOUDNESS_BOOST_DB: f32 = 15.0;
+const LOUDNESS_BASS_HZ: f32 = 120.0;
+const LOUDNESS_TREBLE_HZ: f32 = 8000.0;
+
+// How often (in frames) a playing source picks up settings and volume changes
+const REFRESH_FRAMES: usize = 1024;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct EqSettings {
+    /// Gain per band in dB (-12 to +12), in `EQ_FREQUENCIES` order
+    pub bands: Vec<f32>,
+    pub preset: String,
+}
+
+impl Default for EqSettings {
+    fn default() -> Self {
+        Self {
+            bands: vec![0.0; EQ_FREQUENCIES.len()],
+            preset: "FLAT".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct LoudnessSettings {
+    pub enabled: bool,
+    /// Listening level (phon) the music is assumed to be mixed for, reached at 100% volume
+    pub reference_phon: f32,
+    /// Fraction of the ISO 226 contour difference to apply (0.0-1.0)
+    pub strength: f32,
+}
+
+impl Default for LoudnessSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            reference_phon: 80.0,
+            strength: 0.7,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct LimiterSettings {
+    pub enabled: bool,
+    /// Output peak ceiling in dBFS
+    pub ceiling_db: f32,
+    pub lookahead_ms: f32,
+    pub release_ms: f32,
+}
+
+impl Default for LimiterSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ceiling_db: -1.0,
+            lookahead_ms: 5.0,
+            release_ms: 150.0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub eq: EqSettings,
+    pub loudness: LoudnessSettings,
+    pub limiter: LimiterSettings,
+}
+
+/// RBJ cookbook biquad, transposed direct form II
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    fn new(b0: f32, b1: f32, b2: f32, a0: f32, a1: f32, a2: f32) -> Self {
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
+    fn peaking(sample_rate: u32, freq: f32, q: f32, gain_db: f32) -> Self {
+        let a = 10f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * freq / sample_rate as f32;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+
+        Self::new(1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a, 1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a)
+    }
+
+    fn low_shelf(sample_rate: u32, freq: f32, gain_db: f32) -> Self {
+        let a = 10f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * freq / sample_rate as f32;
+        let cos = w0.cos();
+        // Shelf slope S = 1
+        let beta = 2.0 * a.sqrt() * w0.sin() / 2.0 * 2f32.sqrt();
+
+        Self::new(
+            a * ((a + 1.0) - (a - 1.0) * cos + beta),
+            2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+            a * ((a + 1.0) - (a - 1.0) * cos - beta),
+            (a + 1.0) + (a - 1.0) * cos + beta,
+            -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+            (a + 1.0) + (a - 1.0) * cos - beta,
+        )
+    }
+
+    fn high_shelf(sample_rate: u32, freq: f32, gain_db: f32) -> Self {
+        let a = 10f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * freq / sample_rate as f32;
+        let cos = w0.cos();
+        let beta = 2.0 * a.sqrt() * w0.sin() / 2.0 * 2f32.sqrt();
+
+        Self::new(
+            a * ((a + 1.0) + (a - 1.0) * cos + beta),
+            -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+            a * ((a + 1.0) + (a - 1.0) * cos - beta),
+            (a + 1.0) - (a - 1.0) * cos + beta,
+            2.0 * ((a - 1.0) - (a + 1.0) * cos),
+            (a + 1.0) - (a - 1.0) * cos - beta,
+        )
+    }
+
+    /// Swap in new coefficients without resetting the filter state
+    fn retune(&mut self, other: Biquad) {
+        *self = Biquad {
+            z1: self.z1,
+            z2: self.z2,
+            ..other
+        };
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+}
+
+// ISO 226:2003 equal-loudness contour parameters
+const ISO226_FREQ: [f32; 29] = [
+    20.0, 25.0, 31.5, 40.0, 50.0, 63.0, 80.0, 100.0, 125.0, 160.0, 200.0, 250.0, 315.0, 400.0, 500.0, 630.0, 800.0,
+    1000.0, 1250.0, 1600.0, 2000.0, 2500.0, 3150.0, 4000.0, 5000.0, 6300.0, 8000.0, 10000.0, 12500.0,
+];
+const ISO226_AF: [f32; 29] = [
+    0.532, 0.506, 0.480, 0.455, 0.432, 0.409, 0.387, 0.367, 0.349, 0.330, 0.315, 0.301, 0.288, 0.276, 0.267, 0.259,
+    0.253, 0.250, 0.246, 0.244, 0.243, 0.243, 0.243, 0.242, 0.242, 0.245, 0.254, 0.271, 0.301,
+];
+const ISO226_LU: [f32; 29] = [
+    -31.6, -27.2, -23.0, -19.1, -15.9, -13.0, -10.3, -8.1, -6.2, -4.5, -3.1, -2.0, -1.1, -0.4, 0.0, 0.3, 0.5, 0.0,
+    -2.7, -4.1, -1.0, 1.7, 2.5, 1.2, -2.1, -7.1, -11.2, -10.7, -3.1,
+];
+const ISO226_TF: [f32; 29] = [
+    78.5, 68.7, 59.5, 51.1, 44.0, 37.5, 31.5, 26.5, 22.1, 17.9, 14.4, 11.4, 8.6, 6.2, 4.4, 3.0, 2.2, 2.4, 3.5, 1.7,
+    -1.3, -4.2, -6.0, -5.4, -1.5, 6.0, 12.6, 13.9, 12.3,
+];
+
+/// Sound pressure level (dB SPL) at ISO 226 table index `index` that sounds as loud as `phon`
+fn equal_loudness_spl(index: usize, phon: f32) -> f32 {
+    let (af, lu, tf) = (ISO226_AF[index], ISO226_LU[index], ISO226_TF[index]);
+    let a = 4.47e-3 * (10f32.powf(0.025 * phon) - 1.15) + (0.4 * 10f32.powf((tf + lu) / 10.0 - 9.0)).powf(af);
+    (10.0 / af) * a.log10() - lu + 94.0
+}
+
+fn iso226_index(freq: f32) -> usize {
+    ISO226_FREQ
+        .iter()
+        .enumerate()
+        .min_by(|a, b| (a.1 - freq).abs().total_cmp(&(b.1 - freq).abs()))
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+/// Bass and treble shelf gains (dB) that keep the tonal balance of the
+/// reference level when listening at `volume` percent
+pub fn loudness_gains(config: &LoudnessSettings, volume: u8) -> (f32, f32) {
+    if !config.enabled {
+        return (0.0, 0.0);
+    }
+
+    let attenuation = VOLUME_RANGE_DB * (1.0 - volume.min(100) as f32 / 100.0);
+    let listening_phon = (config.reference_phon - attenuation).max(20.0);
+    let reference_1k = iso226_index(1000.0);
+
+    // How much more the contour rises at `freq` at the listening level than at the reference level
+    let boost = |freq: f32| {
+        let index = iso226_index(freq);
+        let listening = equal_loudness_spl(index, listening_phon) - equal_loudness_spl(reference_1k, listening_phon);
+        let reference =
+            equal_loudness_spl(index, config.reference_phon) - equal_loudness_spl(reference_1k, config.reference_phon);
+        ((listening - reference) * config.strength).clamp(0.0, MAX_LOUDNESS_BOOST_DB)
+    };
+
+    (boost(63.0), boost(10000.0))
+}
+
+/// Look-ahead peak limiter.
+///
+/// The gain for each frame is the minimum required gain over the look-ahead
+/// window, released slowly and then averaged over the same window. Every
+/// averaged gain that touches a frame is at or below the gain that frame
+/// needs, so the delayed output can't exceed the ceiling.
+pub struct Limiter {
+    ceiling: f32,
+    lookahead: usize,
+    release_coef: f32,
+    channels: usize,
+    delay: VecDeque<f32>,
+    // Monotonic queue of (frame, required gain) for the sliding minimum
+    minima: VecDeque<(u64, f32)>,
+    released: f32,
+    averaging: VecDeque<f32>,
+    sum: f64,
+    frame: u64,
+}
+
+impl Limiter {
+    pub fn new(config: &LimiterSettings, sample_rate: u32, channels: usize) -> Self {
+        let lookahead = ((config.lookahead_ms / 1000.0 * sample_rate as f32) as usize).max(1);
+        let release_frames = (config.release_ms / 1000.0 * sample_rate as f32).max(1.0);
+
+        Self {
+            ceiling: 10f32.powf(config.ceiling_db / 20.0),
+            lookahead,
+            release_coef: 1.0 - (-1.0 / release_frames).exp(),
+            channels,
+            delay: std::iter::repeat_n(0.0, (lookahead - 1) * channels).collect(),
+            minima: VecDeque::with_capacity(lookahead),
+            released: 1.0,
+            averaging: std::iter::repeat_n(1.0, lookahead).collect(),
+            sum: lookahead as f64,
+            frame: 0,
+        }
+    }
+
+    /// Frames of delay the limiter adds
+    pub fn latency(&self) -> usize {
+        self.lookahead - 1
+    }
+
+    /// Push one interleaved frame in, get the frame from `latency()` frames ago out
+    pub fn process_frame(&mut self, frame: &mut [f32]) {
+        let peak = frame.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        let required = if peak > self.ceiling { self.ceiling / peak } else { 1.0 };
+
+        // Sliding minimum of the required gain over the look-ahead window
+        while self.minima.back().map(|&(_, gain)| gain >= required).unwrap_or(false) {
+            self.minima.pop_back();
+        }
+        self.minima.push_back((self.frame, required));
+        while self.minima.front().map(|&(index, _)| index + self.lookahead as u64 <= self.frame).unwrap_or(false) {
+            self.minima.pop_front();
+        }
+        let minimum = self.minima.front().map(|&(_, gain)| gain).unwrap_or(1.0);
+
+        // Gain drops immediately but only recovers at the release rate
+        self.released = minimum.min(self.released + (1.0 - self.released) * self.release_coef);
+
+        self.averaging.push_back(self.released);
+        self.sum += self.released as f64;
+        if let Some(oldest) = self.averaging.pop_front() {
+            self.sum -= oldest as f64;
+        }
+        let gain = (self.sum / self.lookahead as f64) as f32;
+
+        self.delay.extend(frame.iter().copied());
+        for sample in frame.iter_mut().take(self.channels) {
+            *sample = self.delay.pop_front().unwrap_or(0.0) * gain;
+        }
+        self.frame += 1;
+    }
+}
+
+/// EQ, loudness contour and limiter for one interleaved stream
+pub struct DspChain {
+    sample_rate: u32,
+    channels: usize,
+    config: AudioSettings,
+    volume: u8,
+    eq: Vec<Vec<Biquad>>,
+    loudness: Vec<[Biquad; 2]>,
+    limiter: Option<Limiter>,
+}
+
+impl DspChain {
+    pub fn new(sample_rate: u32, channels: usize, config: &AudioSettings, volume: u8) -> Self {
+        let mut chain = Self {
+            sample_rate,
+            channels,
+            config: config.clone(),
+            volume,
+            eq: Vec::new(),
+            loudness: Vec::new(),
+            limiter: None,
+        };
+        chain.eq = (0..channels).map(|_| chain.eq_filters()).collect();
+        chain.loudness = (0..channels).map(|_| chain.loudness_filters()).collect();
+        chain.limiter = chain.new_limiter();
+        chain
+    }
+
+    fn eq_filters(&self) -> Vec<Biquad> {
+        EQ_FREQUENCIES
+            .iter()
+            .zip(self.config.eq.bands.iter())
+            // Leave out bands too close to Nyquist to filter sensibly
+            .filter(|(freq, _)| **freq < self.sample_rate as f32 * 0.45)
+            .map(|(freq, gain)| Biquad::peaking(self.sample_rate, *freq, EQ_Q, *gain))
+            .collect()
+    }
+
+    fn loudness_filters(&self) -> [Biquad; 2] {
+        let (bass, treble) = loudness_gains(&self.config.loudness, self.volume);
+        [
+            Biquad::low_shelf(self.sample_rate, LOUDNESS_BASS_HZ, bass),
+            Biquad::high_shelf(self.sample_rate, LOUDNESS_TREBLE_HZ.min(self.sample_rate as f32 * 0.4), treble),
+        ]
+    }
+
+    fn new_limiter(&self) -> Option<Limiter> {
+        self.config
+            .limiter
+            .enabled
+            .then(|| Limiter::new(&self.config.limiter, self.sample_rate, self.channels))
+    }
+
+    /// Frames of delay the chain adds
+    pub fn latency(&self) -> usize {
+        self.limiter.as_ref().map(|limiter| limiter.latency()).unwrap_or(0)
+    }
+
+    /// Pick up new settings or a volume change while keeping filter state
+    pub fn configure(&mut self, config: &AudioSettings, volume: u8) {
+        let limiter_changed = config.limiter != self.config.limiter;
+        self.config = config.clone();
+        self.volume = volume;
+
+        let eq = self.eq_filters();
+        for filters in self.eq.iter_mut() {
+            if filters.len() == eq.len() {
+                filters.iter_mut().zip(eq.iter()).for_each(|(filter, new)| filter.retune(*new));
+            } else {
+                *filters = eq.clone();
+            }
+        }
+
+        let loudness = self.loudness_filters();
+        for filters in self.loudness.iter_mut() {
+            filters.iter_mut().zip(loudness.iter()).for_each(|(filter, new)| filter.retune(*new));
+        }
+
+        if limiter_changed {
+            self.limiter = self.new_limiter();
+        }
+    }
+
+    pub fn process_frame(&mut self, frame: &mut [f32]) {
+        for (channel, sample) in frame.iter_mut().enumerate().take(self.channels) {
+            let mut value = *sample;
+            for filter in self.eq[channel].iter_mut() {
+                value = filter.process(value);
+            }
+            for filter in self.loudness[channel].iter_mut() {
+                value = filter.process(value);
+            }
+            *sample = value;
+        }
+
+        if let Some(limiter) = self.limiter.as_mut() {
+            limiter.process_frame(frame);
+        }
+    }
+}
+
+static SETTINGS: Lazy<RwLock<AudioSettings>> = Lazy::new(|| RwLock::new(settings::load(SETTINGS_NAME)));
+// Bumped on every settings change so playing sources know to reconfigure
+static SETTINGS_VERSION: AtomicU64 = AtomicU64::new(0);
+
+fn current_settings() -> AudioSettings {
+    SETTINGS.read().map(|config| config.clone()).unwrap_or_default()
+}
+
+fn current_volume() -> u8 {
+    // Until the user touches the volume assume full level, i.e. no loudness boost
+    audio::user_volume().unwrap_or(100)
+}
+
+/// Runs a rodio source through the shared DSP settings.
+///
+/// Assumes the channel count and sample rate don't change mid-stream.
+pub struct DspSource<S> {
+    inner: S,
+    chain: DspChain,
+    frame: Vec<f32>,
+    position: usize,
+    version: u64,
+    volume: u8,
+    frames_since_refresh: usize,
+    // Silent frames still to push through to empty the limiter once the source ends
+    flush_frames: Option<usize>,
+}
+
+impl<S: Source<Item = f32>> DspSource<S> {
+    pub fn new(inner: S) -> Self {
+        Self::with_settings(inner, &current_settings())
+    }
+
+    fn with_settings(inner: S, config: &AudioSettings) -> Self {
+        let channels = inner.channels() as usize;
+        let volume = current_volume();
+        let chain = DspChain::new(inner.sample_rate(), channels, config, volume);
+
+        Self {
+            inner,
+            chain,
+            frame: vec![0.0; channels],
+            position: channels,
+            version: SETTINGS_VERSION.load(Ordering::Relaxed),
+            volume,
+            frames_since_refresh: 0,
+            flush_frames: None,
+        }
+    }
+
+    fn refresh(&mut self) {
+        let version = SETTINGS_VERSION.load(Ordering::Relaxed);
+        let volume = current_volume();
+        if version != self.version || volume != self.volume {
+            self.version = version;
+            self.volume = volume;
+            self.chain.configure(&current_settings(), volume);
+        }
+    }
+
+    fn fill_frame(&mut self) -> bool {
+        self.frames_since_refresh += 1;
+        if self.frames_since_refresh >= REFRESH_FRAMES {
+            self.frames_since_refresh = 0;
+            self.refresh();
+        }
+
+        if self.flush_frames.is_none() {
+            match self.inner.next() {
+                Some(sample) => {
+                    self.frame[0] = sample;
+                    for channel in 1..self.frame.len() {
+                        self.frame[channel] = self.inner.next().unwrap_or(0.0);
+                    }
+                }
+                None => self.flush_frames = Some(self.chain.latency()),
+            }
+        }
+
+        if let Some(remaining) = self.flush_frames.as_mut() {
+            if *remaining == 0 {
+                return false;
+            }
+            *remaining -= 1;
+            self.frame.iter_mut().for_each(|sample| *sample = 0.0);
+        }
+
+        self.chain.process_frame(&mut self.frame);
+        true
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for DspSource<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position >= self.frame.len() {
+            if !self.fill_frame() {
+                return None;
+            }
+            self.position = 0;
+        }
+
+        let sample = self.frame[self.position];
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for DspSource<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.frame.len() as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.chain.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
+
+#[tauri::command]
+pub fn get_audio_settings() -> Result<AudioSettings, String> {
+    Ok(current_settings())
+}
+
+#[tauri::command]
+pub fn set_audio_settings(config: AudioSettings) -> Result<(), String> {
+    if config.eq.bands.len() != EQ_FREQUENCIES.len() {
+        return Err(format!("Expected {} EQ bands", EQ_FREQUENCIES.len()));
+    }
+
+    settings::save(SETTINGS_NAME, &config)?;
+    *SETTINGS.write().map_err(|e| e.to_string())? = config;
+    SETTINGS_VERSION.fetch_add(1, Ordering::Relaxed);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    const SAMPLE_RATE: u32 = 48_000;
+
+    /// Stereo logarithmic sine sweep from 20 Hz to 20 kHz
+    fn sweep(seconds: f32, amplitude: f32) -> Vec<f32> {
+        let total = (seconds * SAMPLE_RATE as f32) as usize;
+        let (start, end) = (20.0f32, 20_000.0f32);
+        let k = (end / start).ln();
+        let mut samples = Vec::with_capacity(total * 2);
+        for i in 0..total {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            let phase = 2.0 * PI * start * seconds / k * ((t / seconds * k).exp() - 1.0);
+            let sample = amplitude * phase.sin();
+            samples.push(sample);
+            samples.push(sample);
+        }
+        samples
+    }
+
+    fn run_chain(chain: &mut DspChain, input: &[f32]) -> Vec<f32> {
+        let mut output = Vec::with_capacity(input.len());
+        for frame in input.chunks(2) {
+            let mut frame = [frame[0], frame[1]];
+            chain.process_frame(&mut frame);
+            output.extend_from_slice(&frame);
+        }
+        output
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()))
+    }
+
+    fn boosted_settings(ceiling_db: f32) -> AudioSettings {
+        AudioSettings {
+            eq: EqSettings {
+                bands: vec![12.0; EQ_FREQUENCIES.len()],
+                preset: "CUSTOM".to_string(),
+            },
+            loudness: LoudnessSettings {
+                enabled: true,
+                reference_phon: 80.0,
+                strength: 1.0,
+            },
+            limiter: LimiterSettings {
+                ceiling_db,
+                ..LimiterSettings::default()
+            },
+        }
+    }
+
+    #[test]
+    fn iso226_matches_phon_at_1khz() {
+        let index = iso226_index(1000.0);
+        for phon in [20.0, 40.0, 60.0, 80.0] {
+            assert!((equal_loudness_spl(index, phon) - phon).abs() < 0.5);
+        }
+    }
+
+    #[test]
+    fn loudness_tracks_volume() {
+        let config = LoudnessSettings::default();
+        assert_eq!(loudness_gains(&config, 100), (0.0, 0.0));
+
+        let (bass_mid, _) = loudness_gains(&config, 60);
+        let (bass_low, treble_low) = loudness_gains(&config, 20);
+        assert!(bass_mid > 0.0);
+        assert!(bass_low > bass_mid);
+        assert!(treble_low >= 0.0);
+        assert!(bass_low <= MAX_LOUDNESS_BOOST_DB);
+
+        let disabled = LoudnessSettings {
+            enabled: false,
+            ..config
+        };
+        assert_eq!(loudness_gains(&disabled, 20), (0.0, 0.0));
+    }
+
+    #[test]
+    fn sweep_never_exceeds_ceiling() {
+        for ceiling_db in [-0.1, -1.0, -6.0] {
+            let ceiling = 10f32.powf(ceiling_db / 20.0);
+            for volume in [10, 50, 100] {
+                let mut chain = DspChain::new(SAMPLE_RATE, 2, &boosted_settings(ceiling_db), volume);
+                let output = run_chain(&mut chain, &sweep(2.0, 1.0));
+                let peak = peak(&output);
+                assert!(
+                    peak <= ceiling * 1.0001,
+                    "peak {} over ceiling {} at {}% volume",
+                    peak,
+                    ceiling,
+                    volume
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn boosted_sweep_clips_without_limiter() {
+        let mut config = boosted_settings(-1.0);
+        config.limiter.enabled = false;
+        let mut chain = DspChain::new(SAMPLE_RATE, 2, &config, 10);
+        let output = run_chain(&mut chain, &sweep(2.0, 1.0));
+        assert!(peak(&output) > 1.0);
+    }
+
+    #[test]
+    fn limiter_passes_quiet_signal_through() {
+        let config = LimiterSettings::default();
+        let mut limiter = Limiter::new(&config, SAMPLE_RATE, 2);
+        let input = sweep(0.5, 0.5);
+
+        let mut output = Vec::with_capacity(input.len());
+        for frame in input.chunks(2) {
+            let mut frame = [frame[0], frame[1]];
+            limiter.process_frame(&mut frame);
+            output.extend_from_slice(&frame);
+        }
+
+        let latency = limiter.latency() * 2;
+        for (out, expected) in output[latency..].iter().zip(input.iter()) {
+            assert!((out - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn source_flushes_limiter_and_respects_ceiling() {
+        let input = sweep(1.0, 1.0);
+        let input_len = input.len();
+        let dsp = DspSource::with_settings(SamplesBuffer::new(2, SAMPLE_RATE, input), &boosted_settings(-1.0));
+        let latency = dsp.chain.latency();
+        assert_eq!(dsp.channels(), 2);
+        assert_eq!(dsp.sample_rate(), SAMPLE_RATE);
+
+        let output: Vec<f32> = dsp.collect();
+        assert_eq!(output.len(), input_len + latency * 2);
+        assert!(peak(&output) <= 10f32.powf(-1.0 / 20.0) * 1.0001);
+    }
+}
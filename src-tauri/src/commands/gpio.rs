@@ -0,0 +1,437 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+#[cfg(all(target_os = "linux", not(feature = "fake-gpio")))]
+use rppal::gpio::{Gpio, InputPin, OutputPin, Trigger};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Pull {
+    None,
+    Up,
+    Down,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Edge {
+    Rising,
+    Falling,
+}
+
+/// Called from the GPIO interrupt thread on every watched edge
+pub type EdgeHandler = Box<dyn FnMut() + Send>;
+
+pub trait DigitalInput: Send {
+    fn is_high(&self) -> bool;
+}
+
+pub trait DigitalOutput: Send {
+    fn set_level(&mut self, high: bool);
+}
+
+/// Hands out pins, so services can run against real GPIO or a fake one
+pub trait GpioProvider: Send + Sync {
+    fn input(&self, pin: u8, pull: Pull) -> Result<Box<dyn DigitalInput>, Box<dyn Error + Send + Sync>>;
+
+    /// Claim `pin` as an output, driven to `high` straight away
+    fn output(&self, pin: u8, high: bool) -> Result<Box<dyn DigitalOutput>, Box<dyn Error + Send + Sync>>;
+
+    /// Claim `pin` as an input and call `on_edge` on every `edge` until the
+    /// returned input is dropped
+    fn watch_edges(
+        &self,
+        pin: u8,
+        pull: Pull,
+        edge: Edge,
+        on_edge: EdgeHandler,
+    ) -> Result<Box<dyn DigitalInput>, Box<dyn Error + Send + Sync>>;
+}
+
+#[cfg(all(target_os = "linux", not(feature = "fake-gpio")))]
+pub struct RppalGpio {
+    gpio: Gpio,
+}
+
+#[cfg(all(target_os = "linux", not(feature = "fake-gpio")))]
+impl RppalGpio {
+    pub fn new() -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(RppalGpio { gpio: Gpio::new()? })
+    }
+}
+
+#[cfg(all(target_os = "linux", not(feature = "fake-gpio")))]
+impl DigitalInput for InputPin {
+    fn is_high(&self) -> bool {
+        InputPin::is_high(self)
+    }
+}
+
+#[cfg(all(target_os = "linux", not(feature = "fake-gpio")))]
+impl DigitalOutput for OutputPin {
+    fn set_level(&mut self, high: bool) {
+        if high {
+            self.set_high();
+        } else {
+            self.set_low();
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", not(feature = "fake-gpio")))]
+impl GpioProvider for RppalGpio {
+    fn input(&self, pin: u8, pull: Pull) -> Result<Box<dyn DigitalInput>, Box<dyn Error + Send + Sync>> {
+        let pin = self.gpio.get(pin)?;
+        Ok(Box::new(match pull {
+            Pull::None => pin.into_input(),
+            Pull::Up => pin.into_input_pullup(),
+            Pull::Down => pin.into_input_pulldown(),
+        }))
+    }
+
+    fn output(&self, pin: u8, high: bool) -> Result<Box<dyn DigitalOutput>, Box<dyn Error + Send + Sync>> {
+        let pin = self.gpio.get(pin)?;
+        Ok(Box::new(if high { pin.into_output_high() } else { pin.into_output_low() }))
+    }
+
+    fn watch_edges(
+        &self,
+        pin: u8,
+        pull: Pull,
+        edge: Edge,
+        mut on_edge: EdgeHandler,
+    ) -> Result<Box<dyn DigitalInput>, Box<dyn Error + Send + Sync>> {
+        let pin = self.gpio.get(pin)?;
+        let mut pin = match pull {
+            Pull::None => pin.into_input(),
+            Pull::Up => pin.into_input_pullup(),
+            Pull::Down => pin.into_input_pulldown(),
+        };
+        let trigger = match edge {
+            Edge::Rising => Trigger::RisingEdge,
+            Edge::Falling => Trigger::FallingEdge,
+        };
+        pin.set_async_interrupt(trigger, move |_| on_edge())?;
+        Ok(Box::new(pin))
+    }
+}
+
+struct EdgeWatcher {
+    pin: u8,
+    pull: Pull,
+    edge: Edge,
+    on_edge: Weak<Mutex<EdgeHandler>>,
+}
+
+/// In-memory pins for running without a Pi. Levels are set by hand and
+/// read back by every input on that pin; unset pins float to their pull.
+#[derive(Clone, Default)]
+pub struct FakeGpio {
+    levels: Arc<Mutex<HashMap<u8, bool>>>,
+    watchers: Arc<Mutex<Vec<EdgeWatcher>>>,
+}
+
+impl FakeGpio {
+    pub fn set_level(&self, pin: u8, high: bool) {
+        let previous = match self.levels.lock() {
+            Ok(mut levels) => levels.insert(pin, high),
+            Err(_) => return,
+        };
+        let Ok(mut watchers) = self.watchers.lock() else {
+            return;
+        };
+        watchers.retain(|watcher| watcher.on_edge.strong_count() > 0);
+
+        for watcher in watchers.iter().filter(|watcher| watcher.pin == pin) {
+            let was_high = previous.unwrap_or(watcher.pull == Pull::Up);
+            let fired = match watcher.edge {
+                Edge::Rising => !was_high && high,
+                Edge::Falling => was_high && !high,
+            };
+            if let (true, Some(on_edge)) = (fired, watcher.on_edge.upgrade()) {
+                if let Ok(mut on_edge) = on_edge.lock() {
+                    on_edge();
+                }
+            }
+        }
+    }
+
+    /// Last level set on `pin`, by hand or through an output
+    pub fn level(&self, pin: u8) -> Option<bool> {
+        self.levels.lock().ok().and_then(|levels| levels.get(&pin).copied())
+    }
+}
+
+struct FakeInput {
+    gpio: FakeGpio,
+    pin: u8,
+    pull: Pull,
+}
+
+impl DigitalInput for FakeInput {
+    fn is_high(&self) -> bool {
+        self.gpio.level(self.pin).unwrap_or(self.pull == Pull::Up)
+    }
+}
+
+/// Keeps its edge handler registered until dropped
+struct FakeEdgeInput {
+    input: FakeInput,
+    _on_edge: Arc<Mutex<EdgeHandler>>,
+}
+
+impl DigitalInput for FakeEdgeInput {
+    fn is_high(&self) -> bool {
+        self.input.is_high()
+    }
+}
+
+struct FakeOutput {
+    gpio: FakeGpio,
+    pin: u8,
+}
+
+impl DigitalOutput for FakeOutput {
+    fn set_level(&mut self, high: bool) {
+        self.gpio.set_level(self.pin, high);
+    }
+}
+
+impl GpioProvider for FakeGpio {
+    fn input(&self, pin: u8, pull: Pull) -> Result<Box<dyn DigitalInput>, Box<dyn Error + Send + Sync>> {
+        Ok(Box::new(FakeInput {
+            gpio: self.clone(),
+            pin,
+            pull,
+        }))
+    }
+
+    fn output(&self, pin: u8, high: bool) -> Result<Box<dyn DigitalOutput>, Box<dyn Error + Send + Sync>> {
+        self.set_level(pin, high);
+        Ok(Box::new(FakeOutput { gpio: self.clone(), pin }))
+    }
+
+    fn watch_edges(
+        &self,
+        pin: u8,
+        pull: Pull,
+        edge: Edge,
+        on_edge: EdgeHandler,
+    ) -> Result<Box<dyn DigitalInput>, Box<dyn Error + Send + Sync>> {
+        let on_edge = Arc::new(Mutex::new(on_edge));
+        self.watchers.lock().map_err(|e| e.to_string())?.push(EdgeWatcher {
+            pin,
+            pull,
+            edge,
+            on_edge: Arc::downgrade(&on_edge),
+        });
+        Ok(Box::new(FakeEdgeInput {
+            input: FakeInput {
+                gpio: self.clone(),
+                pin,
+                pull,
+            },
+            _on_edge: on_edge,
+        }))
+    }
+}
+
+/// Stands in for GPIO that failed to open, so every pin claim fails with the
+/// reason instead of quietly reading floating levels
+#[cfg(all(target_os = "linux", not(feature = "fake-gpio")))]
+struct UnavailableGpio {
+    reason: String,
+}
+
+#[cfg(all(target_os = "linux", not(feature = "fake-gpio")))]
+impl GpioProvider for UnavailableGpio {
+    fn input(&self, pin: u8, _pull: Pull) -> Result<Box<dyn DigitalInput>, Box<dyn Error + Send + Sync>> {
+        Err(format!("GPIO{} unavailable, GPIO failed to open: {}", pin, self.reason).into())
+    }
+
+    fn output(&self, pin: u8, _high: bool) -> Result<Box<dyn DigitalOutput>, Box<dyn Error + Send + Sync>> {
+        Err(format!("GPIO{} unavailable, GPIO failed to open: {}", pin, self.reason).into())
+    }
+
+    fn watch_edges(
+        &self,
+        pin: u8,
+        _pull: Pull,
+        _edge: Edge,
+        _on_edge: EdgeHandler,
+    ) -> Result<Box<dyn DigitalInput>, Box<dyn Error + Send + Sync>> {
+        Err(format!("GPIO{} unavailable, GPIO failed to open: {}", pin, self.reason).into())
+    }
+}
+
+// Only used off the Pi, or when built with the `fake-gpio` feature
+static FAKE_GPIO: Lazy<FakeGpio> = Lazy::new(FakeGpio::default);
+
+const USING_FAKE_GPIO: bool = cfg!(any(not(target_os = "linux"), feature = "fake-gpio"));
+
+// A Pi that can't open GPIO must not act on made-up levels, e.g. shutting down
+// because a fake ACC pin floats to "off"
+#[cfg(all(target_os = "linux", not(feature = "fake-gpio")))]
+static PROVIDER: Lazy<Arc<dyn GpioProvider>> = Lazy::new(|| match RppalGpio::new() {
+    Ok(gpio) => Arc::new(gpio),
+    Err(e) => {
+        println!("GPIO not available: {}", e);
+        Arc::new(UnavailableGpio { reason: e.to_string() })
+    }
+});
+
+#[cfg(any(not(target_os = "linux"), feature = "fake-gpio"))]
+static PROVIDER: Lazy<Arc<dyn GpioProvider>> = Lazy::new(|| Arc::new(FAKE_GPIO.clone()));
+
+pub fn provider() -> Arc<dyn GpioProvider> {
+    PROVIDER.clone()
+}
+
+/// Only reports a new level once the input has held it for `period`
+pub struct Debouncer {
+    period: Duration,
+    stable: Option<bool>,
+    candidate: Option<(bool, Instant)>,
+}
+
+impl Debouncer {
+    pub fn new(period: Duration) -> Self {
+        Self {
+            period,
+            stable: None,
+            candidate: None,
+        }
+    }
+
+    /// Feed a raw sample. Returns the new stable level when it changes.
+    pub fn update(&mut self, level: bool, now: Instant) -> Option<bool> {
+        let since = match self.candidate {
+            Some((candidate, since)) if candidate == level => since,
+            _ => {
+                self.candidate = Some((level, now));
+                now
+            }
+        };
+
+        if self.stable != Some(level) && now.duration_since(since) >= self.period {
+            self.stable = Some(level);
+            return Some(level);
+        }
+        None
+    }
+}
+
+/// Debounced on/off sense line, such as an optocoupled 12 V feed
+pub struct SenseInput {
+    input: Box<dyn DigitalInput>,
+    debouncer: Debouncer,
+    active_low: bool,
+}
+
+impl SenseInput {
+    pub fn new(
+        gpio: &dyn GpioProvider,
+        pin: u8,
+        pull: Pull,
+        active_low: bool,
+        debounce: Duration,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(Self {
+            input: gpio.input(pin, pull)?,
+            debouncer: Debouncer::new(debounce),
+            active_low,
+        })
+    }
+
+    /// Sample the pin. Returns whether the line is active when that changes.
+    pub fn poll(&mut self, now: Instant) -> Option<bool> {
+        let active = self.input.is_high() != self.active_low;
+        self.debouncer.update(active, now)
+    }
+}
+
+/// Drive a fake pin from the UI, for testing GPIO features without a Pi
+#[tauri::command]
+pub fn set_fake_gpio_level(pin: u8, high: bool) -> Result<(), String> {
+    if !USING_FAKE_GPIO {
+        return Err("Real GPIO is in use, build with the fake-gpio feature to fake pins".to_string());
+    }
+    FAKE_GPIO.set_level(pin, high);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PERIOD: Duration = Duration::from_millis(50);
+
+    fn at(start: Instant, ms: u64) -> Instant {
+        start + Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn fake_pins_float_to_pull() {
+        let gpio = FakeGpio::default();
+        assert!(gpio.input(4, Pull::Up).unwrap().is_high());
+        assert!(!gpio.input(5, Pull::Down).unwrap().is_high());
+
+        let input = gpio.input(4, Pull::Up).unwrap();
+        gpio.set_level(4, false);
+        assert!(!input.is_high());
+    }
+
+    #[test]
+    fn fake_edges_fire_until_dropped() {
+        let gpio = FakeGpio::default();
+        let count = Arc::new(Mutex::new(0));
+        let counted = count.clone();
+        let on_edge = Box::new(move || *counted.lock().unwrap() += 1);
+        let input = gpio.watch_edges(6, Pull::Up, Edge::Falling, on_edge).unwrap();
+
+        // Floating high, so the first low is an edge and the rise back isn't
+        gpio.set_level(6, false);
+        gpio.set_level(6, false);
+        gpio.set_level(6, true);
+        gpio.set_level(6, false);
+        gpio.set_level(7, false);
+        assert_eq!(*count.lock().unwrap(), 2);
+
+        drop(input);
+        gpio.set_level(6, true);
+        gpio.set_level(6, false);
+        assert_eq!(*count.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn debouncer_waits_for_stable_level() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(PERIOD);
+
+        assert_eq!(debouncer.update(false, at(start, 0)), None);
+        assert_eq!(debouncer.update(false, at(start, 50)), Some(false));
+        assert_eq!(debouncer.update(false, at(start, 60)), None);
+
+        assert_eq!(debouncer.update(true, at(start, 100)), None);
+        assert_eq!(debouncer.update(true, at(start, 149)), None);
+        assert_eq!(debouncer.update(true, at(start, 150)), Some(true));
+        assert_eq!(debouncer.update(true, at(start, 200)), None);
+    }
+
+    #[test]
+    fn debouncer_ignores_bounces() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(PERIOD);
+        debouncer.update(false, at(start, 0));
+        debouncer.update(false, at(start, 50));
+
+        for (i, ms) in (100..140).step_by(5).enumerate() {
+            assert_eq!(debouncer.update(i % 2 == 0, at(start, ms)), None);
+        }
+        assert_eq!(debouncer.update(false, at(start, 200)), None);
+    }
+}
@@ -0,0 +1,705 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+
+use super::gpio::{self, DigitalOutput, GpioProvider, Pull, SenseInput};
+use super::{pins, settings, vehicle};
+
+const SETTINGS_NAME: &str = "headlights";
+const STATUS_EVENT: &str = "headlights://status";
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Relays and limit sense lines for one pop-up motor
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SideSettings {
+    /// BCM pin of the relay that drives the motor up
+    pub up_relay: u8,
+    pub down_relay: u8,
+    /// BCM pin that goes active when the light is fully up
+    pub up_sense: u8,
+    pub down_sense: u8,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct HeadlightSettings {
+    pub enabled: bool,
+    pub left: SideSettings,
+    pub right: SideSettings,
+    /// Most relay boards switch on when their input is pulled low
+    pub relay_active_low: bool,
+    pub sense_pull: Pull,
+    pub sense_active_low: bool,
+    pub debounce_ms: u64,
+    /// No lowering or winking above this speed
+    pub max_speed_kmh: f32,
+    /// Allow lowering while no speed source is reporting
+    pub allow_without_speed: bool,
+    /// A motor that hasn't reached its limit by now is stopped and the lights raised
+    pub motor_timeout_ms: u64,
+    /// How long the lights stay put in the middle of a wink
+    pub wink_pause_ms: u64,
+    /// How long to drive up from down for sleepy eyes
+    pub sleepy_ms: u64,
+}
+
+impl Default for HeadlightSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            left: SideSettings {
+                up_relay: 4,
+                down_relay: 12,
+                up_sense: 25,
+                down_sense: 26,
+            },
+            // Clear of SPI1, which the lighting strip has by default
+            right: SideSettings {
+                up_relay: 7,
+                down_relay: 8,
+                up_sense: 0,
+                down_sense: 1,
+            },
+            relay_active_low: true,
+            sense_pull: Pull::Up,
+            sense_active_low: true,
+            debounce_ms: 20,
+            max_speed_kmh: 10.0,
+            allow_without_speed: false,
+            motor_timeout_ms: 1500,
+            wink_pause_ms: 250,
+            sleepy_ms: 350,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum HeadlightCommand {
+    Up,
+    Down,
+    /// Down and back up, or up and back down, on one side
+    Wink {
+        side: Side,
+    },
+    /// Both lights part way up
+    SleepyEyes,
+}
+
+impl HeadlightCommand {
+    fn lowers(self) -> bool {
+        !matches!(self, HeadlightCommand::Up)
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Position {
+    Up,
+    Down,
+    Moving,
+    /// Stopped between the limits, e.g. sleepy eyes
+    Partial,
+    Unknown,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct HeadlightStatus {
+    pub left: Position,
+    pub right: Position,
+    /// Set when something went wrong and the lights were sent up. Cleared by the next up command.
+    pub fault: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Direction {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Step {
+    /// Run until the limit for that direction
+    Move(Direction),
+    /// Run for a fixed time, stopping between the limits
+    Nudge(Direction, Duration),
+    Pause(Duration),
+}
+
+struct Motor {
+    up_relay: Box<dyn DigitalOutput>,
+    down_relay: Box<dyn DigitalOutput>,
+    up_sense: SenseInput,
+    down_sense: SenseInput,
+    active_low: bool,
+    at_up: bool,
+    at_down: bool,
+    driving: Option<Direction>,
+    partial: bool,
+    steps: VecDeque<Step>,
+    step_started: Option<Instant>,
+}
+
+impl Motor {
+    fn open(
+        gpio: &dyn GpioProvider,
+        side: &SideSettings,
+        config: &HeadlightSettings,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let debounce = Duration::from_millis(config.debounce_ms);
+        let off = config.relay_active_low;
+        let sense = |pin| SenseInput::new(gpio, pin, config.sense_pull, config.sense_active_low, debounce);
+
+        Ok(Motor {
+            up_relay: gpio.output(side.up_relay, off)?,
+            down_relay: gpio.output(side.down_relay, off)?,
+            up_sense: sense(side.up_sense)?,
+            down_sense: sense(side.down_sense)?,
+            active_low: config.relay_active_low,
+            at_up: false,
+            at_down: false,
+            driving: None,
+            partial: false,
+            steps: VecDeque::new(),
+            step_started: None,
+        })
+    }
+
+    fn drive(&mut self, direction: Option<Direction>) {
+        if self.driving == direction {
+            return;
+        }
+        // Always release both before closing one, so up and down are never on together
+        self.up_relay.set_level(self.active_low);
+        self.down_relay.set_level(self.active_low);
+        match direction {
+            Some(Direction::Up) => self.up_relay.set_level(!self.active_low),
+            Some(Direction::Down) => self.down_relay.set_level(!self.active_low),
+            None => {}
+        }
+        self.driving = direction;
+    }
+
+    fn at_limit(&self, direction: Direction) -> bool {
+        match direction {
+            Direction::Up => self.at_up,
+            Direction::Down => self.at_down,
+        }
+    }
+
+    fn position(&self) -> Position {
+        if self.driving.is_some() {
+            Position::Moving
+        } else if self.at_up {
+            Position::Up
+        } else if self.at_down {
+            Position::Down
+        } else if self.partial {
+            Position::Partial
+        } else {
+            Position::Unknown
+        }
+    }
+
+    fn start(&mut self, steps: impl IntoIterator<Item = Step>) {
+        self.steps = steps.into_iter().collect();
+        self.step_started = None;
+    }
+
+    fn lowering(&self) -> bool {
+        self.steps
+            .iter()
+            .any(|step| matches!(step, Step::Move(Direction::Down) | Step::Nudge(Direction::Down, _)))
+    }
+
+    /// Read the limits and run the current step. Errors stop the motor.
+    fn poll(&mut self, now: Instant, timeout: Duration) -> Result<(), String> {
+        if let Some(up) = self.up_sense.poll(now) {
+            self.at_up = up;
+        }
+        if let Some(down) = self.down_sense.poll(now) {
+            self.at_down = down;
+        }
+        if self.at_up && self.at_down {
+            self.drive(None);
+            return Err("both limit switches active".to_string());
+        }
+
+        while let Some(&step) = self.steps.front() {
+            let started = *self.step_started.get_or_insert(now);
+            let elapsed = now.saturating_duration_since(started);
+
+            let done = match step {
+                Step::Move(direction) => {
+                    if self.at_limit(direction) {
+                        self.partial = false;
+                        true
+                    } else if elapsed >= timeout {
+                        self.steps.clear();
+                        self.drive(None);
+                        return Err(format!("Motor didn't reach the {:?} limit", direction).to_lowercase());
+                    } else {
+                        self.drive(Some(direction));
+                        false
+                    }
+                }
+                Step::Nudge(direction, length) => {
+                    if elapsed >= length || self.at_limit(direction) {
+                        self.partial = !self.at_limit(direction);
+                        true
+                    } else {
+                        self.drive(Some(direction));
+                        false
+                    }
+                }
+                Step::Pause(length) => {
+                    self.drive(None);
+                    elapsed >= length
+                }
+            };
+
+            if !done {
+                return Ok(());
+            }
+            self.steps.pop_front();
+            self.step_started = None;
+        }
+
+        self.drive(None);
+        Ok(())
+    }
+}
+
+/// Both pop-ups, with the speed interlock and the fail-safe
+pub struct Headlights {
+    config: HeadlightSettings,
+    left: Motor,
+    right: Motor,
+    fault: Option<String>,
+}
+
+impl Headlights {
+    pub fn open(gpio: &dyn GpioProvider, config: HeadlightSettings) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(Headlights {
+            left: Motor::open(gpio, &config.left, &config)?,
+            right: Motor::open(gpio, &config.right, &config)?,
+            config,
+            fault: None,
+        })
+    }
+
+    fn speed_allows_lowering(&self, speed_kmh: Option<f32>) -> bool {
+        match speed_kmh {
+            Some(speed) => speed <= self.config.max_speed_kmh,
+            None => self.config.allow_without_speed,
+        }
+    }
+
+    pub fn command(&mut self, command: HeadlightCommand, speed_kmh: Option<f32>) -> Result<(), String> {
+        if command.lowers() {
+            if let Some(fault) = &self.fault {
+                return Err(format!("Headlights faulted ({}), raise them first", fault));
+            }
+            if !self.speed_allows_lowering(speed_kmh) {
+                return Err(format!("Not while moving over {} km/h", self.config.max_speed_kmh));
+            }
+        }
+
+        let pause = Step::Pause(Duration::from_millis(self.config.wink_pause_ms));
+        let sleepy = Step::Nudge(Direction::Up, Duration::from_millis(self.config.sleepy_ms));
+        match command {
+            HeadlightCommand::Up => {
+                self.fault = None;
+                self.left.start([Step::Move(Direction::Up)]);
+                self.right.start([Step::Move(Direction::Up)]);
+            }
+            HeadlightCommand::Down => {
+                self.left.start([Step::Move(Direction::Down)]);
+                self.right.start([Step::Move(Direction::Down)]);
+            }
+            HeadlightCommand::Wink { side } => {
+                let motor = match side {
+                    Side::Left => &mut self.left,
+                    Side::Right => &mut self.right,
+                };
+                let (there, back) = if motor.at_up {
+                    (Direction::Down, Direction::Up)
+                } else {
+                    (Direction::Up, Direction::Down)
+                };
+                motor.start([Step::Move(there), pause, Step::Move(back)]);
+            }
+            HeadlightCommand::SleepyEyes => {
+                self.left.start([Step::Move(Direction::Down), sleepy]);
+                self.right.start([Step::Move(Direction::Down), sleepy]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Stop whatever was running and send both lights up
+    fn fail_safe(&mut self, reason: String) {
+        println!("Headlight fault, raising: {}", reason);
+        self.fault = Some(reason);
+        self.left.start([Step::Move(Direction::Up)]);
+        self.right.start([Step::Move(Direction::Up)]);
+    }
+
+    pub fn poll(&mut self, now: Instant, speed_kmh: Option<f32>) {
+        let lowering = self.left.lowering() || self.right.lowering();
+        if lowering && !self.speed_allows_lowering(speed_kmh) {
+            // Moving off mid-wink, finish by bringing them up
+            self.left.start([Step::Move(Direction::Up)]);
+            self.right.start([Step::Move(Direction::Up)]);
+        }
+
+        let timeout = Duration::from_millis(self.config.motor_timeout_ms);
+        let left = self.left.poll(now, timeout);
+        let right = self.right.poll(now, timeout);
+
+        if let Err(e) = left
+            .map_err(|e| format!("Left {}", e))
+            .and(right.map_err(|e| format!("Right {}", e)))
+        {
+            // Already raising after a fault, leave the stopped motor alone rather than burn it out
+            if self.fault.is_none() {
+                self.fail_safe(e);
+            }
+        }
+    }
+
+    pub fn status(&self) -> HeadlightStatus {
+        HeadlightStatus {
+            left: self.left.position(),
+            right: self.right.position(),
+            fault: self.fault.clone(),
+        }
+    }
+}
+
+impl HeadlightSettings {
+    pub(crate) fn pins(&self) -> [(u8, &'static str); 8] {
+        let (left, right) = (&self.left, &self.right);
+        [
+            (left.up_relay, "left up relay"),
+            (left.down_relay, "left down relay"),
+            (left.up_sense, "left up sense"),
+            (left.down_sense, "left down sense"),
+            (right.up_relay, "right up relay"),
+            (right.down_relay, "right down relay"),
+            (right.up_sense, "right up sense"),
+            (right.down_sense, "right down sense"),
+        ]
+    }
+}
+
+static SETTINGS: Lazy<Mutex<HeadlightSettings>> = Lazy::new(|| Mutex::new(settings::load(SETTINGS_NAME)));
+static HEADLIGHTS: Mutex<Option<Headlights>> = Mutex::new(None);
+static STATUS: Mutex<Option<HeadlightStatus>> = Mutex::new(None);
+
+fn publish_status(app: &AppHandle, status: Option<HeadlightStatus>) {
+    if let Ok(mut current) = STATUS.lock() {
+        if *current != status {
+            *current = status.clone();
+            if let Err(e) = app.emit(STATUS_EVENT, status) {
+                println!("Failed to emit headlight status: {}", e);
+            }
+        }
+    }
+}
+
+/// Start running the pop-up relays. Called once from the app setup hook.
+pub fn start(app: AppHandle) {
+    std::thread::spawn(move || {
+        let gpio = gpio::provider();
+        let mut opened: Option<HeadlightSettings> = None;
+
+        loop {
+            let config = match SETTINGS.lock() {
+                Ok(config) => config.clone(),
+                Err(_) => return,
+            };
+
+            if !config.enabled {
+                if let Ok(mut headlights) = HEADLIGHTS.lock() {
+                    *headlights = None;
+                }
+                opened = None;
+                publish_status(&app, None);
+                std::thread::sleep(RETRY_DELAY);
+                continue;
+            }
+
+            if opened.as_ref() != Some(&config) {
+                let mut headlights = match HEADLIGHTS.lock() {
+                    Ok(headlights) => headlights,
+                    Err(_) => return,
+                };
+                // Release the old pins before claiming them again
+                *headlights = None;
+                let checked = pins::check("headlights", &config.pins()).map_err(Into::into);
+                match checked.and_then(|_| Headlights::open(gpio.as_ref(), config.clone())) {
+                    Ok(new) => {
+                        *headlights = Some(new);
+                        opened = Some(config);
+                    }
+                    Err(e) => {
+                        println!("Failed to open headlight relays: {}", e);
+                        drop(headlights);
+                        std::thread::sleep(RETRY_DELAY);
+                        continue;
+                    }
+                }
+            }
+
+            let status = match HEADLIGHTS.lock() {
+                Ok(mut headlights) => headlights.as_mut().map(|headlights| {
+                    headlights.poll(Instant::now(), vehicle::speed_kmh());
+                    headlights.status()
+                }),
+                Err(_) => return,
+            };
+            publish_status(&app, status);
+
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    });
+}
+
+/// None while the relays aren't enabled
+#[tauri::command]
+pub fn get_headlight_status() -> Result<Option<HeadlightStatus>, String> {
+    let status = STATUS.lock().map_err(|e| e.to_string())?;
+    Ok(status.clone())
+}
+
+#[tauri::command]
+pub fn headlight_command(command: HeadlightCommand) -> Result<(), String> {
+    let mut headlights = HEADLIGHTS.lock().map_err(|e| e.to_string())?;
+    let headlights = headlights.as_mut().ok_or("Headlight control isn't enabled")?;
+    headlights.command(command, vehicle::speed_kmh())
+}
+
+#[tauri::command]
+pub fn get_headlight_settings() -> Result<HeadlightSettings, String> {
+    let config = SETTINGS.lock().map_err(|e| e.to_string())?;
+    Ok(config.clone())
+}
+
+#[tauri::command]
+pub fn set_headlight_settings(config: HeadlightSettings) -> Result<(), String> {
+    if config.enabled {
+        pins::check("headlights", &config.pins())?;
+    }
+    settings::save(SETTINGS_NAME, &config)?;
+    *SETTINGS.lock().map_err(|e| e.to_string())? = config;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::gpio::FakeGpio;
+
+    const TICK: Duration = Duration::from_millis(10);
+
+    fn config() -> HeadlightSettings {
+        HeadlightSettings {
+            enabled: true,
+            relay_active_low: false,
+            sense_active_low: false,
+            sense_pull: Pull::Down,
+            debounce_ms: 0,
+            ..Default::default()
+        }
+    }
+
+    /// Pop-up motors that take `travel` ticks end to end, moved by whichever relay is closed
+    struct Car {
+        gpio: FakeGpio,
+        config: HeadlightSettings,
+        // 0 is down, travel is up
+        left: i32,
+        right: i32,
+        travel: i32,
+        jammed: bool,
+    }
+
+    impl Car {
+        fn new(config: &HeadlightSettings, up: bool) -> Self {
+            let car = Car {
+                gpio: FakeGpio::default(),
+                config: config.clone(),
+                left: if up { 10 } else { 0 },
+                right: if up { 10 } else { 0 },
+                travel: 10,
+                jammed: false,
+            };
+            car.sense();
+            car
+        }
+
+        fn sense(&self) {
+            for (side, position) in [(&self.config.left, self.left), (&self.config.right, self.right)] {
+                self.gpio.set_level(side.up_sense, position >= self.travel);
+                self.gpio.set_level(side.down_sense, position <= 0);
+            }
+        }
+
+        fn step(&mut self) {
+            if !self.jammed {
+                let relay = |pin| self.gpio.level(pin) == Some(true);
+                for (side, position) in [
+                    (&self.config.left, &mut self.left),
+                    (&self.config.right, &mut self.right),
+                ] {
+                    if relay(side.up_relay) {
+                        *position = (*position + 1).min(self.travel);
+                    }
+                    if relay(side.down_relay) {
+                        *position = (*position - 1).max(0);
+                    }
+                }
+            }
+            self.sense();
+        }
+
+        fn run(&mut self, headlights: &mut Headlights, now: &mut Instant, ticks: usize, speed: Option<f32>) {
+            for _ in 0..ticks {
+                headlights.poll(*now, speed);
+                self.step();
+                *now += TICK;
+            }
+        }
+    }
+
+    fn setup(up: bool) -> (Car, Headlights, Instant) {
+        setup_with(config(), up)
+    }
+
+    fn setup_with(config: HeadlightSettings, up: bool) -> (Car, Headlights, Instant) {
+        let car = Car::new(&config, up);
+        let mut headlights = Headlights::open(&car.gpio, config).unwrap();
+        let mut now = Instant::now();
+        headlights.poll(now, None);
+        now += TICK;
+        (car, headlights, now)
+    }
+
+    #[test]
+    fn raises_and_lowers() {
+        let (mut car, mut headlights, mut now) = setup(true);
+        assert_eq!(headlights.status().left, Position::Up);
+
+        headlights.command(HeadlightCommand::Down, Some(0.0)).unwrap();
+        car.run(&mut headlights, &mut now, 5, Some(0.0));
+        assert_eq!(headlights.status().left, Position::Moving);
+        car.run(&mut headlights, &mut now, 10, Some(0.0));
+        assert_eq!(headlights.status().left, Position::Down);
+        assert_eq!(headlights.status().right, Position::Down);
+
+        // Both relays released once there
+        assert_eq!(car.gpio.level(4), Some(false));
+        assert_eq!(car.gpio.level(12), Some(false));
+    }
+
+    #[test]
+    fn speed_interlock() {
+        let (mut car, mut headlights, mut now) = setup(true);
+
+        assert!(headlights.command(HeadlightCommand::Down, Some(50.0)).is_err());
+        assert!(headlights.command(HeadlightCommand::Down, None).is_err());
+
+        // Moving off mid-wink sends the light back up
+        headlights
+            .command(HeadlightCommand::Wink { side: Side::Left }, Some(0.0))
+            .unwrap();
+        car.run(&mut headlights, &mut now, 4, Some(0.0));
+        car.run(&mut headlights, &mut now, 20, Some(30.0));
+        assert_eq!(headlights.status().left, Position::Up);
+        assert_eq!(car.left, 10);
+    }
+
+    #[test]
+    fn wink_goes_there_and_back() {
+        let (mut car, mut headlights, mut now) = setup(false);
+
+        headlights
+            .command(HeadlightCommand::Wink { side: Side::Right }, Some(0.0))
+            .unwrap();
+        car.run(&mut headlights, &mut now, 15, Some(0.0));
+        assert_eq!(car.right, 10);
+        assert_eq!(car.left, 0);
+
+        car.run(&mut headlights, &mut now, 50, Some(0.0));
+        assert_eq!(headlights.status().right, Position::Down);
+    }
+
+    #[test]
+    fn sleepy_eyes_stops_part_way() {
+        // Half of the simulated motor's travel
+        let config = HeadlightSettings {
+            sleepy_ms: 50,
+            ..config()
+        };
+        let (mut car, mut headlights, mut now) = setup_with(config, true);
+
+        headlights.command(HeadlightCommand::SleepyEyes, Some(0.0)).unwrap();
+        car.run(&mut headlights, &mut now, 60, Some(0.0));
+        let status = headlights.status();
+        assert_eq!((status.left, status.right), (Position::Partial, Position::Partial));
+        assert!(car.left > 0 && car.left < 10);
+    }
+
+    #[test]
+    fn timeout_fails_safe() {
+        let (mut car, mut headlights, mut now) = setup(true);
+        headlights.command(HeadlightCommand::Down, Some(0.0)).unwrap();
+        car.run(&mut headlights, &mut now, 5, Some(0.0));
+
+        // Motor seizes half way down
+        car.jammed = true;
+        car.run(&mut headlights, &mut now, 160, Some(0.0));
+        assert!(headlights.status().fault.is_some());
+        assert_eq!(car.gpio.level(4), Some(true), "tries to raise");
+        assert_eq!(car.gpio.level(12), Some(false));
+
+        car.run(&mut headlights, &mut now, 160, Some(0.0));
+        assert_eq!(car.gpio.level(4), Some(false), "gave up raising a jammed motor");
+
+        car.jammed = false;
+        assert!(headlights.command(HeadlightCommand::Down, Some(0.0)).is_err());
+        headlights.command(HeadlightCommand::Up, Some(0.0)).unwrap();
+        car.run(&mut headlights, &mut now, 15, Some(0.0));
+        assert_eq!(headlights.status().left, Position::Up);
+        assert_eq!(headlights.status().fault, None);
+    }
+
+    #[test]
+    fn fault_while_lowering_raises() {
+        let (mut car, mut headlights, mut now) = setup(true);
+        headlights.command(HeadlightCommand::Down, Some(0.0)).unwrap();
+        car.run(&mut headlights, &mut now, 3, Some(0.0));
+
+        // A shorted sense line shows both limits at once
+        car.gpio.set_level(config().left.up_sense, true);
+        car.gpio.set_level(config().left.down_sense, true);
+        headlights.poll(now, Some(0.0));
+        assert!(headlights.status().fault.is_some());
+
+        car.run(&mut headlights, &mut now, 15, Some(0.0));
+        assert_eq!((car.left, car.right), (10, 10));
+    }
+}
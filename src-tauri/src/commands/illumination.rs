@@ -0,0 +1,208 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+
+use super::display::BacklightService;
+use super::gpio::{self, GpioProvider, Pull, SenseInput};
+use super::{auto_brightness, settings};
+
+const SETTINGS_NAME: &str = "illumination";
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Dash illumination sense input, live whenever the headlights are on
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct IlluminationSettings {
+    pub enabled: bool,
+    /// BCM pin number
+    pub pin: u8,
+    pub pull: Pull,
+    /// An optocoupler pulls the pin low while the illumination circuit is live
+    pub active_low: bool,
+    pub debounce_ms: u64,
+    /// Backlight level while the headlights are on
+    pub night_brightness: u8,
+}
+
+impl Default for IlluminationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            pin: 17,
+            pull: Pull::Up,
+            active_low: true,
+            debounce_ms: 50,
+            night_brightness: 30,
+        }
+    }
+}
+
+pub struct IlluminationMonitor {
+    input: SenseInput,
+}
+
+impl IlluminationMonitor {
+    pub fn new(gpio: &dyn GpioProvider, config: &IlluminationSettings) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let debounce = Duration::from_millis(config.debounce_ms);
+        Ok(Self {
+            input: SenseInput::new(gpio, config.pin, config.pull, config.active_low, debounce)?,
+        })
+    }
+
+    /// Sample the pin. Returns whether the headlights are on when that changes.
+    pub fn poll(&mut self, now: Instant) -> Option<bool> {
+        self.input.poll(now)
+    }
+}
+
+static SETTINGS: Lazy<Mutex<IlluminationSettings>> = Lazy::new(|| Mutex::new(settings::load(SETTINGS_NAME)));
+static HEADLIGHTS: AtomicBool = AtomicBool::new(false);
+
+pub fn headlights_on() -> bool {
+    HEADLIGHTS.load(Ordering::Relaxed)
+}
+
+fn set_headlights(app: &AppHandle, on: bool, night_brightness: u8) {
+    HEADLIGHTS.store(on, Ordering::Relaxed);
+
+    // Not saved, so a restart with the headlights on doesn't keep the night level.
+    // Turning off hands back to the user's level until auto-brightness picks one.
+    let level = on.then_some(night_brightness);
+    if let Err(e) = app.state::<BacklightService>().set_auto(level, None) {
+        println!("Failed to apply headlight brightness: {}", e);
+    }
+
+    auto_brightness::refresh(app);
+}
+
+/// Start watching the illumination input. Called once from the app setup hook, after the backlight is managed.
+pub fn start(app: AppHandle) {
+    std::thread::spawn(move || {
+        let gpio = gpio::provider();
+        let mut monitor: Option<(IlluminationSettings, IlluminationMonitor)> = None;
+
+        loop {
+            let config = match SETTINGS.lock() {
+                Ok(config) => config.clone(),
+                Err(_) => return,
+            };
+
+            if !config.enabled {
+                if monitor.take().is_some() && headlights_on() {
+                    set_headlights(&app, false, config.night_brightness);
+                }
+                std::thread::sleep(RETRY_DELAY);
+                continue;
+            }
+
+            if monitor.as_ref().map(|(opened, _)| opened != &config).unwrap_or(true) {
+                match IlluminationMonitor::new(gpio.as_ref(), &config) {
+                    Ok(opened) => monitor = Some((config.clone(), opened)),
+                    Err(e) => {
+                        println!("Failed to open illumination input on GPIO{}: {}", config.pin, e);
+                        monitor = None;
+                        std::thread::sleep(RETRY_DELAY);
+                        continue;
+                    }
+                }
+            }
+
+            if let Some((_, monitor)) = monitor.as_mut() {
+                if let Some(on) = monitor.poll(Instant::now()) {
+                    println!("Headlights {}", if on { "on" } else { "off" });
+                    if on != headlights_on() {
+                        set_headlights(&app, on, config.night_brightness);
+                    }
+                }
+            }
+
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_illumination_settings() -> Result<IlluminationSettings, String> {
+    let config = SETTINGS.lock().map_err(|e| e.to_string())?;
+    Ok(config.clone())
+}
+
+#[tauri::command]
+pub fn set_illumination_settings(config: IlluminationSettings) -> Result<(), String> {
+    settings::save(SETTINGS_NAME, &config)?;
+    *SETTINGS.lock().map_err(|e| e.to_string())? = config;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_headlights_on() -> Result<bool, String> {
+    Ok(headlights_on())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::gpio::FakeGpio;
+
+    fn at(start: Instant, ms: u64) -> Instant {
+        start + Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn optocoupler_input_is_active_low() {
+        let gpio = FakeGpio::default();
+        let config = IlluminationSettings::default();
+        let mut monitor = IlluminationMonitor::new(&gpio, &config).unwrap();
+        let start = Instant::now();
+
+        // Pulled up with the lights off
+        monitor.poll(at(start, 0));
+        assert_eq!(monitor.poll(at(start, 50)), Some(false));
+
+        gpio.set_level(config.pin, false);
+        assert_eq!(monitor.poll(at(start, 100)), None);
+        assert_eq!(monitor.poll(at(start, 150)), Some(true));
+
+        gpio.set_level(config.pin, true);
+        monitor.poll(at(start, 200));
+        assert_eq!(monitor.poll(at(start, 260)), Some(false));
+    }
+
+    #[test]
+    fn direct_input_is_active_high() {
+        let gpio = FakeGpio::default();
+        let config = IlluminationSettings {
+            pull: Pull::Down,
+            active_low: false,
+            ..IlluminationSettings::default()
+        };
+        let mut monitor = IlluminationMonitor::new(&gpio, &config).unwrap();
+        let start = Instant::now();
+
+        gpio.set_level(config.pin, true);
+        monitor.poll(at(start, 0));
+        assert_eq!(monitor.poll(at(start, 50)), Some(true));
+    }
+
+    #[test]
+    fn glitches_shorter_than_debounce_are_ignored() {
+        let gpio = FakeGpio::default();
+        let config = IlluminationSettings::default();
+        let mut monitor = IlluminationMonitor::new(&gpio, &config).unwrap();
+        let start = Instant::now();
+        monitor.poll(at(start, 0));
+        monitor.poll(at(start, 50));
+
+        // Ignition noise on the sense line
+        gpio.set_level(config.pin, false);
+        assert_eq!(monitor.poll(at(start, 100)), None);
+        gpio.set_level(config.pin, true);
+        assert_eq!(monitor.poll(at(start, 120)), None);
+        assert_eq!(monitor.poll(at(start, 300)), None);
+    }
+}
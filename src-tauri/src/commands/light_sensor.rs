@@ -0,0 +1,475 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+#[cfg(target_os = "linux")]
+use rppal::i2c::I2c;
+
+use super::display::BacklightService;
+use super::{auto_brightness, illumination, settings};
+
+const SETTINGS_NAME: &str = "light_sensor";
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+// Short enough to keep up with tunnel entrances
+const SENSOR_FADE_MS: u32 = 500;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SensorModel {
+    Bh1750,
+    Tsl2561,
+}
+
+impl SensorModel {
+    fn default_address(self) -> u16 {
+        match self {
+            SensorModel::Bh1750 => 0x23,
+            SensorModel::Tsl2561 => 0x39,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct CurvePoint {
+    pub lux: f32,
+    pub brightness: u8,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct LightSensorSettings {
+    /// None when no sensor is fitted
+    pub model: Option<SensorModel>,
+    /// I2C address, None for the model's default
+    pub address: Option<u16>,
+    /// Lux to brightness points, interpolated on a log lux scale
+    pub curve: Vec<CurvePoint>,
+    /// Time constant for smoothing the lux readings
+    pub smoothing_secs: f32,
+    /// Brightness has to move this far before the backlight is touched
+    pub hysteresis_percent: u8,
+    pub poll_ms: u64,
+}
+
+impl Default for LightSensorSettings {
+    fn default() -> Self {
+        Self {
+            model: None,
+            address: None,
+            curve: vec![
+                CurvePoint { lux: 1.0, brightness: 10 },
+                CurvePoint { lux: 10.0, brightness: 25 },
+                CurvePoint { lux: 100.0, brightness: 45 },
+                CurvePoint { lux: 1000.0, brightness: 75 },
+                CurvePoint { lux: 10000.0, brightness: 100 },
+            ],
+            smoothing_secs: 1.5,
+            hysteresis_percent: 2,
+            poll_ms: 250,
+        }
+    }
+}
+
+impl LightSensorSettings {
+    /// Brightness for a lux reading, flat beyond the ends of the curve
+    pub fn brightness_for(&self, lux: f32) -> u8 {
+        let mut points = self.curve.clone();
+        points.sort_by(|a, b| a.lux.total_cmp(&b.lux));
+
+        let (Some(first), Some(last)) = (points.first(), points.last()) else {
+            return 100;
+        };
+        if lux <= first.lux {
+            return first.brightness.min(100);
+        }
+        if lux >= last.lux {
+            return last.brightness.min(100);
+        }
+
+        let log_lux = |lux: f32| lux.max(0.01).log10();
+        for pair in points.windows(2) {
+            let (low, high) = (pair[0], pair[1]);
+            if lux <= high.lux {
+                let span = log_lux(high.lux) - log_lux(low.lux);
+                let t = if span > 0.0 { (log_lux(lux) - log_lux(low.lux)) / span } else { 1.0 };
+                let level = low.brightness as f32 + (high.brightness as f32 - low.brightness as f32) * t;
+                return (level.round() as u8).min(100);
+            }
+        }
+        last.brightness.min(100)
+    }
+}
+
+pub trait LightSensor: Send {
+    fn read_lux(&mut self) -> Result<f32, Box<dyn Error + Send + Sync>>;
+}
+
+#[cfg(target_os = "linux")]
+pub struct Bh1750 {
+    i2c: I2c,
+}
+
+#[cfg(target_os = "linux")]
+impl Bh1750 {
+    const POWER_ON: u8 = 0x01;
+    // Continuous high resolution mode, 1 lx steps every 120 ms
+    const CONTINUOUS_HIGH_RES: u8 = 0x10;
+
+    pub fn new(address: u16) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut i2c = I2c::new()?;
+        i2c.set_slave_address(address)?;
+        i2c.write(&[Self::POWER_ON])?;
+        i2c.write(&[Self::CONTINUOUS_HIGH_RES])?;
+        Ok(Bh1750 { i2c })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl LightSensor for Bh1750 {
+    fn read_lux(&mut self) -> Result<f32, Box<dyn Error + Send + Sync>> {
+        let mut buffer = [0u8; 2];
+        self.i2c.read(&mut buffer)?;
+        Ok(u16::from_be_bytes(buffer) as f32 / 1.2)
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub struct Tsl2561 {
+    i2c: I2c,
+}
+
+#[cfg(target_os = "linux")]
+impl Tsl2561 {
+    const COMMAND: u8 = 0x80;
+    const WORD: u8 = 0x20;
+    const REG_CONTROL: u8 = 0x00;
+    const REG_TIMING: u8 = 0x01;
+    const REG_DATA0: u8 = 0x0C;
+    const REG_DATA1: u8 = 0x0E;
+    const POWER_ON: u8 = 0x03;
+    // 1x gain, 402 ms integration
+    const TIMING_402MS_LOW_GAIN: u8 = 0x02;
+
+    pub fn new(address: u16) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let mut i2c = I2c::new()?;
+        i2c.set_slave_address(address)?;
+        i2c.smbus_write_byte(Self::COMMAND | Self::REG_CONTROL, Self::POWER_ON)?;
+        i2c.smbus_write_byte(Self::COMMAND | Self::REG_TIMING, Self::TIMING_402MS_LOW_GAIN)?;
+        Ok(Tsl2561 { i2c })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl LightSensor for Tsl2561 {
+    fn read_lux(&mut self) -> Result<f32, Box<dyn Error + Send + Sync>> {
+        let broadband = self.i2c.smbus_read_word(Self::COMMAND | Self::WORD | Self::REG_DATA0)?;
+        let infrared = self.i2c.smbus_read_word(Self::COMMAND | Self::WORD | Self::REG_DATA1)?;
+        if broadband == u16::MAX {
+            return Err("TSL2561 saturated".into());
+        }
+        // The datasheet coefficients assume 16x gain
+        Ok(tsl2561_lux(broadband as f32 * 16.0, infrared as f32 * 16.0))
+    }
+}
+
+/// TSL2561 (T/FN/CL package) lux approximation from the two channel counts
+pub fn tsl2561_lux(broadband: f32, infrared: f32) -> f32 {
+    if broadband <= 0.0 {
+        return 0.0;
+    }
+
+    let ratio = infrared / broadband;
+    let lux = if ratio <= 0.50 {
+        0.0304 * broadband - 0.062 * broadband * ratio.powf(1.4)
+    } else if ratio <= 0.61 {
+        0.0224 * broadband - 0.031 * infrared
+    } else if ratio <= 0.80 {
+        0.0128 * broadband - 0.0153 * infrared
+    } else if ratio <= 1.30 {
+        0.00146 * broadband - 0.00112 * infrared
+    } else {
+        0.0
+    };
+    lux.max(0.0)
+}
+
+#[cfg(target_os = "linux")]
+fn open_sensor(model: SensorModel, address: u16) -> Result<Box<dyn LightSensor>, Box<dyn Error + Send + Sync>> {
+    Ok(match model {
+        SensorModel::Bh1750 => Box::new(Bh1750::new(address)?),
+        SensorModel::Tsl2561 => Box::new(Tsl2561::new(address)?),
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_sensor(_model: SensorModel, _address: u16) -> Result<Box<dyn LightSensor>, Box<dyn Error + Send + Sync>> {
+    Err("I2C not available on this platform".into())
+}
+
+/// Smooths sensor readings and decides when the backlight should follow them
+#[derive(Default)]
+pub struct AutoDimmer {
+    // Smoothed in log10 space, so a tunnel and a sunny road converge equally fast
+    smoothed_log_lux: Option<f32>,
+    level: Option<u8>,
+}
+
+impl AutoDimmer {
+    /// Take a reading from `sensor`. Returns the new brightness when it should be applied.
+    pub fn step(&mut self, config: &LightSensorSettings, sensor: &mut dyn LightSensor, dt: Duration) -> Result<Option<u8>, Box<dyn Error + Send + Sync>> {
+        let log_lux = sensor.read_lux()?.max(0.01).log10();
+
+        let smoothed = match self.smoothed_log_lux {
+            Some(previous) => {
+                let alpha = 1.0 - (-dt.as_secs_f32() / config.smoothing_secs.max(0.01)).exp();
+                previous + (log_lux - previous) * alpha
+            }
+            None => log_lux,
+        };
+        self.smoothed_log_lux = Some(smoothed);
+
+        let level = config.brightness_for(10f32.powf(smoothed));
+        if let Some(current) = self.level {
+            if level.abs_diff(current) < config.hysteresis_percent.max(1) {
+                return Ok(None);
+            }
+        }
+        self.level = Some(level);
+        Ok(Some(level))
+    }
+
+    pub fn lux(&self) -> Option<f32> {
+        self.smoothed_log_lux.map(|log_lux| 10f32.powf(log_lux))
+    }
+
+    /// Forget the last applied level, so the next reading is applied
+    pub fn reset(&mut self) {
+        self.level = None;
+    }
+}
+
+static SETTINGS: Lazy<Mutex<LightSensorSettings>> = Lazy::new(|| Mutex::new(settings::load(SETTINGS_NAME)));
+static LUX: Mutex<Option<f32>> = Mutex::new(None);
+// Set while the sensor is giving readings, so the solar curve backs off
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Whether auto-brightness is currently driven by the light sensor
+pub fn active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+fn set_lux(lux: Option<f32>) {
+    ACTIVE.store(lux.is_some(), Ordering::Relaxed);
+    if let Ok(mut current) = LUX.lock() {
+        *current = lux;
+    }
+}
+
+/// Start polling the light sensor. Called once from the app setup hook, after the backlight is managed.
+pub fn start(app: AppHandle) {
+    std::thread::spawn(move || {
+        let mut sensor: Option<(SensorModel, u16, Box<dyn LightSensor>)> = None;
+        let mut dimmer = AutoDimmer::default();
+
+        loop {
+            let config = match SETTINGS.lock() {
+                Ok(config) => config.clone(),
+                Err(_) => return,
+            };
+            let poll = Duration::from_millis(config.poll_ms.max(10));
+
+            let Some(model) = config.model else {
+                sensor = None;
+                set_lux(None);
+                std::thread::sleep(RETRY_DELAY);
+                continue;
+            };
+            let address = config.address.unwrap_or(model.default_address());
+
+            // (Re)open when the configured sensor changes or after a read error
+            if sensor.as_ref().map(|(m, a, _)| (*m, *a)) != Some((model, address)) {
+                match open_sensor(model, address) {
+                    Ok(opened) => {
+                        sensor = Some((model, address, opened));
+                        dimmer = AutoDimmer::default();
+                    }
+                    Err(e) => {
+                        println!("Failed to open {:?} light sensor at {:#04x}: {}", model, address, e);
+                        sensor = None;
+                        set_lux(None);
+                        std::thread::sleep(RETRY_DELAY);
+                        continue;
+                    }
+                }
+            }
+            let Some((_, _, device)) = sensor.as_mut() else { continue };
+
+            match dimmer.step(&config, device.as_mut(), poll) {
+                Ok(level) => {
+                    set_lux(dimmer.lux());
+
+                    let backlight = app.state::<BacklightService>();
+                    if illumination::headlights_on() {
+                        dimmer.reset();
+                    } else if !auto_brightness::enabled() {
+                        // Back to the level the user picked
+                        dimmer.reset();
+                        if let Err(e) = backlight.set_auto(None, Some(SENSOR_FADE_MS)) {
+                            println!("Failed to restore brightness: {}", e);
+                        }
+                    } else if let Some(level) = level {
+                        if let Err(e) = backlight.set_auto(Some(level), Some(SENSOR_FADE_MS)) {
+                            println!("Failed to apply sensor brightness: {}", e);
+                        }
+                    }
+                }
+                Err(e) => {
+                    println!("Light sensor read failed: {}", e);
+                    sensor = None;
+                    set_lux(None);
+                }
+            }
+
+            std::thread::sleep(poll);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_light_sensor_settings() -> Result<LightSensorSettings, String> {
+    let config = SETTINGS.lock().map_err(|e| e.to_string())?;
+    Ok(config.clone())
+}
+
+#[tauri::command]
+pub fn set_light_sensor_settings(config: LightSensorSettings) -> Result<(), String> {
+    if config.curve.is_empty() {
+        return Err("Brightness curve needs at least one point".to_string());
+    }
+    settings::save(SETTINGS_NAME, &config)?;
+    *SETTINGS.lock().map_err(|e| e.to_string())? = config;
+    Ok(())
+}
+
+/// Smoothed ambient light level, None without a working sensor
+#[tauri::command]
+pub fn get_ambient_lux() -> Result<Option<f32>, String> {
+    let lux = LUX.lock().map_err(|e| e.to_string())?;
+    Ok(*lux)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plays back a fixed list of readings, then keeps repeating the last one
+    struct MockSensor {
+        readings: Vec<f32>,
+    }
+
+    impl MockSensor {
+        fn new(readings: &[f32]) -> Self {
+            Self {
+                readings: readings.iter().rev().copied().collect(),
+            }
+        }
+    }
+
+    impl LightSensor for MockSensor {
+        fn read_lux(&mut self) -> Result<f32, Box<dyn Error + Send + Sync>> {
+            match self.readings.len() {
+                0 => Err("no readings".into()),
+                1 => Ok(self.readings[0]),
+                _ => Ok(self.readings.pop().unwrap()),
+            }
+        }
+    }
+
+    const POLL: Duration = Duration::from_millis(250);
+
+    #[test]
+    fn curve_interpolates_on_log_scale() {
+        let config = LightSensorSettings::default();
+        assert_eq!(config.brightness_for(0.0), 10);
+        assert_eq!(config.brightness_for(10.0), 25);
+        assert_eq!(config.brightness_for(100f32.sqrt() * 10f32.sqrt()), 35);
+        assert_eq!(config.brightness_for(10000.0), 100);
+        assert_eq!(config.brightness_for(100000.0), 100);
+    }
+
+    #[test]
+    fn curve_points_can_be_unsorted() {
+        let config = LightSensorSettings {
+            curve: vec![
+                CurvePoint { lux: 1000.0, brightness: 90 },
+                CurvePoint { lux: 10.0, brightness: 30 },
+            ],
+            ..LightSensorSettings::default()
+        };
+        assert_eq!(config.brightness_for(100.0), 60);
+    }
+
+    #[test]
+    fn first_reading_applies_immediately() {
+        let config = LightSensorSettings::default();
+        let mut sensor = MockSensor::new(&[1000.0]);
+        let mut dimmer = AutoDimmer::default();
+
+        assert_eq!(dimmer.step(&config, &mut sensor, POLL).unwrap(), Some(75));
+    }
+
+    #[test]
+    fn tunnel_dims_smoothly_and_settles() {
+        let config = LightSensorSettings::default();
+        let mut sensor = MockSensor::new(&[10000.0, 10.0]);
+        let mut dimmer = AutoDimmer::default();
+
+        let mut levels = vec![];
+        for _ in 0..60 {
+            if let Some(level) = dimmer.step(&config, &mut sensor, POLL).unwrap() {
+                levels.push(level);
+            }
+        }
+
+        assert_eq!(levels[0], 100);
+        // No jump straight to the tunnel level
+        assert!(levels[1] > 60, "levels {:?}", levels);
+        assert!(levels.windows(2).all(|pair| pair[1] < pair[0]), "levels {:?}", levels);
+        assert!(*levels.last().unwrap() <= 27, "levels {:?}", levels);
+    }
+
+    #[test]
+    fn hysteresis_ignores_flicker() {
+        let config = LightSensorSettings::default();
+        let mut sensor = MockSensor::new(&[100.0, 110.0, 95.0, 105.0, 100.0]);
+        let mut dimmer = AutoDimmer::default();
+
+        assert_eq!(dimmer.step(&config, &mut sensor, POLL).unwrap(), Some(45));
+        for _ in 0..10 {
+            assert_eq!(dimmer.step(&config, &mut sensor, POLL).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn read_errors_propagate() {
+        let config = LightSensorSettings::default();
+        let mut sensor = MockSensor::new(&[]);
+        let mut dimmer = AutoDimmer::default();
+
+        assert!(dimmer.step(&config, &mut sensor, POLL).is_err());
+        assert_eq!(dimmer.lux(), None);
+    }
+
+    #[test]
+    fn tsl2561_lux_regions() {
+        assert_eq!(tsl2561_lux(0.0, 0.0), 0.0);
+        // Mostly visible light
+        assert!((tsl2561_lux(1000.0, 100.0) - 27.9).abs() < 0.1);
+        // Mostly infrared
+        assert_eq!(tsl2561_lux(1000.0, 1400.0), 0.0);
+    }
+}
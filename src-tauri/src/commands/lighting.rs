@@ -0,0 +1,577 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::f32::consts::PI;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+
+use super::ws2812::{self, LedStrip, LedType, Rgb};
+use super::{auto_brightness, pins, settings, spectrum, telemetry};
+
+const SETTINGS_NAME: &str = "lighting";
+const FRAME_INTERVAL: Duration = Duration::from_millis(20);
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+// Music effects go dark once the analyzer stops producing bands
+const SPECTRUM_MAX_AGE: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "effect", rename_all = "snake_case")]
+pub enum Effect {
+    Off,
+    Static {
+        color: Rgb,
+    },
+    /// Fades in and out once every `period_secs`
+    Breathe {
+        color: Rgb,
+        period_secs: f32,
+    },
+    /// Hues spread along the zone, going round once every `period_secs`
+    Rainbow {
+        period_secs: f32,
+    },
+    /// Each LED follows a slice of the audio spectrum, bass first. Without a colour the zone is a rainbow.
+    Music {
+        color: Option<Rgb>,
+    },
+    /// Blends from `idle_color` to `redline_color` as the revs rise
+    Rpm {
+        idle_color: Rgb,
+        redline_color: Rgb,
+        idle_rpm: f32,
+        redline_rpm: f32,
+    },
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct EffectInfo {
+    pub id: &'static str,
+    pub description: &'static str,
+}
+
+pub const EFFECTS: [EffectInfo; 6] = [
+    EffectInfo {
+        id: "off",
+        description: "Dark",
+    },
+    EffectInfo {
+        id: "static",
+        description: "One steady colour",
+    },
+    EffectInfo {
+        id: "breathe",
+        description: "One colour fading in and out",
+    },
+    EffectInfo {
+        id: "rainbow",
+        description: "Hues cycling along the strip",
+    },
+    EffectInfo {
+        id: "music",
+        description: "Follows the audio spectrum",
+    },
+    EffectInfo {
+        id: "rpm",
+        description: "Colour follows engine speed",
+    },
+];
+
+/// What effects can react to, sampled once per frame
+#[derive(Debug, Default, Clone)]
+pub struct Inputs {
+    pub bands: Option<Vec<f32>>,
+    pub rpm: Option<f32>,
+}
+
+/// Fully saturated colour at `hue`, 0 to 1 round the wheel
+pub fn hue(hue: f32) -> Rgb {
+    let h = hue.rem_euclid(1.0) * 6.0;
+    let x = ((1.0 - (h % 2.0 - 1.0).abs()) * 255.0).round() as u8;
+    match h as u8 {
+        0 => Rgb::new(255, x, 0),
+        1 => Rgb::new(x, 255, 0),
+        2 => Rgb::new(0, 255, x),
+        3 => Rgb::new(0, x, 255),
+        4 => Rgb::new(x, 0, 255),
+        _ => Rgb::new(255, 0, x),
+    }
+}
+
+fn lerp(from: Rgb, to: Rgb, t: f32) -> Rgb {
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t.clamp(0.0, 1.0)).round() as u8;
+    Rgb::new(mix(from.r, to.r), mix(from.g, to.g), mix(from.b, to.b))
+}
+
+impl Effect {
+    /// Colours for a zone of `len` LEDs, `seconds` into the scene
+    pub fn render(&self, len: usize, seconds: f32, inputs: &Inputs) -> Vec<Rgb> {
+        match self {
+            Effect::Off => vec![Rgb::OFF; len],
+            Effect::Static { color } => vec![*color; len],
+            Effect::Breathe { color, period_secs } => {
+                let level = (1.0 - (2.0 * PI * seconds / period_secs.max(0.1)).cos()) / 2.0;
+                vec![color.dim((level * 255.0).round() as u8); len]
+            }
+            Effect::Rainbow { period_secs } => {
+                let offset = seconds / period_secs.max(0.1);
+                (0..len).map(|i| hue(i as f32 / len as f32 + offset)).collect()
+            }
+            Effect::Music { color } => {
+                let Some(bands) = inputs.bands.as_ref().filter(|bands| !bands.is_empty()) else {
+                    return vec![Rgb::OFF; len];
+                };
+                (0..len)
+                    .map(|i| {
+                        let level = bands[i * bands.len() / len];
+                        let base = color.unwrap_or_else(|| hue(i as f32 / len as f32));
+                        base.dim((level.clamp(0.0, 1.0) * 255.0).round() as u8)
+                    })
+                    .collect()
+            }
+            Effect::Rpm {
+                idle_color,
+                redline_color,
+                idle_rpm,
+                redline_rpm,
+            } => {
+                let rpm = inputs.rpm.unwrap_or(0.0);
+                let t = (rpm - idle_rpm) / (redline_rpm - idle_rpm).max(1.0);
+                vec![lerp(*idle_color, *redline_color, t); len]
+            }
+        }
+    }
+}
+
+/// A run of LEDs on the strip lit as one
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Zone {
+    pub name: String,
+    pub start: usize,
+    pub len: usize,
+    /// Effects run from the far end, e.g. for the left side of a mirrored pair
+    #[serde(default)]
+    pub reversed: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Scene {
+    pub name: String,
+    /// Effect per zone name, zones not listed stay dark
+    pub zones: BTreeMap<String, Effect>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct LightingSettings {
+    pub enabled: bool,
+    /// SPI bus the strip's data line is on, see `ws2812::SpiStrip` and
+    /// `pins::strip_pins`. SPI0 is the shift light's by default.
+    pub bus: u8,
+    pub leds: usize,
+    pub led_type: LedType,
+    pub zones: Vec<Zone>,
+    pub scenes: Vec<Scene>,
+    /// Scene showing now
+    pub scene: String,
+    /// Telemetry channel for RPM effects, None for whichever RPM channel updated last
+    pub rpm_channel: Option<String>,
+    pub brightness: u8,
+    /// Brightness while the UI is in night mode
+    pub night_brightness: u8,
+}
+
+impl Default for LightingSettings {
+    fn default() -> Self {
+        let scene = |name: &str, zones: &[(&str, Effect)]| Scene {
+            name: name.to_string(),
+            zones: zones
+                .iter()
+                .map(|(zone, effect)| (zone.to_string(), effect.clone()))
+                .collect(),
+        };
+
+        Self {
+            enabled: false,
+            bus: 1,
+            leds: 60,
+            led_type: LedType::Ws2812,
+            zones: vec![
+                Zone {
+                    name: "footwell".to_string(),
+                    start: 0,
+                    len: 20,
+                    reversed: false,
+                },
+                Zone {
+                    name: "underglow".to_string(),
+                    start: 20,
+                    len: 40,
+                    reversed: false,
+                },
+            ],
+            scenes: vec![
+                scene(
+                    "cruise",
+                    &[
+                        (
+                            "footwell",
+                            Effect::Static {
+                                color: Rgb::new(255, 120, 40),
+                            },
+                        ),
+                        (
+                            "underglow",
+                            Effect::Breathe {
+                                color: Rgb::new(0, 80, 255),
+                                period_secs: 6.0,
+                            },
+                        ),
+                    ],
+                ),
+                scene(
+                    "party",
+                    &[
+                        ("footwell", Effect::Music { color: None }),
+                        ("underglow", Effect::Music { color: None }),
+                    ],
+                ),
+                scene(
+                    "track",
+                    &[
+                        (
+                            "footwell",
+                            Effect::Static {
+                                color: Rgb::new(80, 0, 0),
+                            },
+                        ),
+                        (
+                            "underglow",
+                            Effect::Rpm {
+                                idle_color: Rgb::new(0, 0, 255),
+                                redline_color: Rgb::new(255, 0, 0),
+                                idle_rpm: 1000.0,
+                                redline_rpm: 7000.0,
+                            },
+                        ),
+                    ],
+                ),
+                scene(
+                    "rainbow",
+                    &[
+                        ("footwell", Effect::Rainbow { period_secs: 10.0 }),
+                        ("underglow", Effect::Rainbow { period_secs: 10.0 }),
+                    ],
+                ),
+                scene("off", &[]),
+            ],
+            scene: "cruise".to_string(),
+            rpm_channel: None,
+            brightness: 255,
+            night_brightness: 60,
+        }
+    }
+}
+
+impl LightingSettings {
+    fn current_scene(&self) -> Option<&Scene> {
+        self.scenes.iter().find(|scene| scene.name == self.scene)
+    }
+
+    fn uses_music(&self) -> bool {
+        self.current_scene().is_some_and(|scene| {
+            scene
+                .zones
+                .values()
+                .any(|effect| matches!(effect, Effect::Music { .. }))
+        })
+    }
+
+    /// Put `effect` on `zone` in the current scene
+    pub fn set_zone_effect(&mut self, zone: &str, effect: Effect) -> Result<(), String> {
+        if !self.zones.iter().any(|z| z.name == zone) {
+            return Err(format!("No lighting zone called {}", zone));
+        }
+        let name = self.scene.clone();
+        let scene = self
+            .scenes
+            .iter_mut()
+            .find(|scene| scene.name == name)
+            .ok_or_else(|| format!("No lighting scene called {}", name))?;
+        scene.zones.insert(zone.to_string(), effect);
+        Ok(())
+    }
+
+    /// The whole strip, `seconds` into the current scene
+    pub fn render(&self, seconds: f32, inputs: &Inputs, night: bool) -> Vec<Rgb> {
+        let mut frame = vec![Rgb::OFF; self.leds];
+        let Some(scene) = self.current_scene() else {
+            return frame;
+        };
+        let brightness = if night { self.night_brightness } else { self.brightness };
+
+        for zone in &self.zones {
+            let Some(effect) = scene.zones.get(&zone.name) else {
+                continue;
+            };
+            let mut colors = effect.render(zone.len, seconds, inputs);
+            if zone.reversed {
+                colors.reverse();
+            }
+
+            // Zones hanging off the end of the strip are cut short
+            let end = (zone.start + zone.len).min(self.leds);
+            for (led, color) in frame.iter_mut().take(end).skip(zone.start).zip(colors) {
+                *led = color.dim(brightness);
+            }
+        }
+        frame
+    }
+}
+
+static SETTINGS: Lazy<Mutex<LightingSettings>> = Lazy::new(|| Mutex::new(settings::load(SETTINGS_NAME)));
+
+fn update_settings(change: impl FnOnce(&mut LightingSettings) -> Result<(), String>) -> Result<(), String> {
+    let mut current = SETTINGS.lock().map_err(|e| e.to_string())?;
+    let mut config = current.clone();
+    change(&mut config)?;
+    settings::save(SETTINGS_NAME, &config)?;
+    *current = config;
+    Ok(())
+}
+
+/// Start driving the lighting strip. Called once from the app setup hook.
+pub fn start(app: AppHandle) {
+    std::thread::spawn(move || {
+        let mut strip: Option<Box<dyn LedStrip>> = None;
+        // (bus, leds, type) the strip was opened with
+        let mut opened = None;
+        let mut last: Option<Vec<Rgb>> = None;
+        let mut last_analyzer_start: Option<Instant> = None;
+        let epoch = Instant::now();
+
+        loop {
+            let config = match SETTINGS.lock() {
+                Ok(config) => config.clone(),
+                Err(_) => return,
+            };
+
+            if !config.enabled {
+                if let Some(mut strip) = strip.take() {
+                    let _ = strip.write(&vec![Rgb::OFF; strip.len()]);
+                }
+                last = None;
+                std::thread::sleep(RETRY_DELAY);
+                continue;
+            }
+
+            let wiring = (config.bus, config.leds, config.led_type);
+            if strip.is_none() || opened != Some(wiring) {
+                strip = None;
+                last = None;
+                let checked = pins::check_strip("lighting strip", config.bus).map_err(Into::into);
+                match checked.and_then(|_| ws2812::open_strip(config.bus, config.leds, config.led_type)) {
+                    Ok(new_strip) => {
+                        strip = Some(new_strip);
+                        opened = Some(wiring);
+                    }
+                    Err(e) => {
+                        println!("Failed to open lighting strip: {}", e);
+                        std::thread::sleep(RETRY_DELAY);
+                        continue;
+                    }
+                }
+            }
+
+            let now = Instant::now();
+            let mut inputs = Inputs {
+                bands: None,
+                rpm: telemetry::rpm(config.rpm_channel.as_deref(), now),
+            };
+            if config.uses_music() {
+                inputs.bands = spectrum::latest_bands(SPECTRUM_MAX_AGE);
+                // The UI stops the analyzer when it leaves the visualizer, so keep it going
+                let due = last_analyzer_start.is_none_or(|at| at.elapsed() >= RETRY_DELAY);
+                if inputs.bands.is_none() && due {
+                    last_analyzer_start = Some(now);
+                    if let Err(e) = spectrum::start_spectrum_analyzer(app.clone()) {
+                        println!("Failed to start spectrum analyzer for lighting: {}", e);
+                    }
+                }
+            }
+
+            let frame = config.render(
+                now.duration_since(epoch).as_secs_f32(),
+                &inputs,
+                auto_brightness::night_mode(),
+            );
+            if let Some(strip) = strip.as_mut() {
+                if last.as_ref() != Some(&frame) {
+                    match strip.write(&frame) {
+                        Ok(()) => last = Some(frame),
+                        Err(e) => println!("Failed to update lighting: {}", e),
+                    }
+                }
+            }
+
+            std::thread::sleep(FRAME_INTERVAL);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_lighting_settings() -> Result<LightingSettings, String> {
+    let config = SETTINGS.lock().map_err(|e| e.to_string())?;
+    Ok(config.clone())
+}
+
+#[tauri::command]
+pub fn set_lighting_settings(config: LightingSettings) -> Result<(), String> {
+    if config.enabled {
+        pins::check_strip("lighting strip", config.bus)?;
+    }
+    update_settings(|current| {
+        *current = config;
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn set_lighting_scene(scene: String) -> Result<(), String> {
+    update_settings(|config| {
+        if !config.scenes.iter().any(|s| s.name == scene) {
+            return Err(format!("No lighting scene called {}", scene));
+        }
+        config.scene = scene;
+        Ok(())
+    })
+}
+
+/// Set a zone to one steady colour in the current scene
+#[tauri::command]
+pub fn set_zone_color(zone: String, color: Rgb) -> Result<(), String> {
+    update_settings(|config| config.set_zone_effect(&zone, Effect::Static { color }))
+}
+
+#[tauri::command]
+pub fn list_effects() -> Result<Vec<EffectInfo>, String> {
+    Ok(EFFECTS.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgb = Rgb::new(255, 0, 0);
+    const BLUE: Rgb = Rgb::new(0, 0, 255);
+
+    #[test]
+    fn breathe_and_rainbow() {
+        let inputs = Inputs::default();
+        let breathe = Effect::Breathe {
+            color: RED,
+            period_secs: 4.0,
+        };
+        assert_eq!(breathe.render(2, 0.0, &inputs), vec![Rgb::OFF; 2]);
+        assert_eq!(breathe.render(2, 2.0, &inputs), vec![RED; 2]);
+
+        let rainbow = Effect::Rainbow { period_secs: 10.0 }.render(6, 0.0, &inputs);
+        assert_eq!(rainbow[0], RED);
+        assert_eq!(rainbow[2], Rgb::new(0, 255, 0));
+        assert_eq!(rainbow[4], BLUE);
+        // A full period later it's back where it started
+        assert_eq!(Effect::Rainbow { period_secs: 10.0 }.render(6, 10.0, &inputs), rainbow);
+    }
+
+    #[test]
+    fn music_follows_bands() {
+        let effect = Effect::Music { color: Some(BLUE) };
+        assert_eq!(effect.render(2, 0.0, &Inputs::default()), vec![Rgb::OFF; 2]);
+
+        let inputs = Inputs {
+            bands: Some(vec![1.0, 1.0, 0.0, 0.0]),
+            rpm: None,
+        };
+        assert_eq!(effect.render(2, 0.0, &inputs), vec![BLUE, Rgb::OFF]);
+    }
+
+    #[test]
+    fn rpm_blends_colors() {
+        let effect = Effect::Rpm {
+            idle_color: BLUE,
+            redline_color: RED,
+            idle_rpm: 1000.0,
+            redline_rpm: 7000.0,
+        };
+        let at = |rpm| {
+            effect.render(
+                1,
+                0.0,
+                &Inputs {
+                    bands: None,
+                    rpm: Some(rpm),
+                },
+            )[0]
+        };
+        assert_eq!(at(800.0), BLUE);
+        assert_eq!(at(4000.0), Rgb::new(128, 0, 128));
+        assert_eq!(at(9000.0), RED);
+    }
+
+    #[test]
+    fn zones_map_onto_the_strip() {
+        let mut config = LightingSettings {
+            leds: 6,
+            zones: vec![
+                Zone {
+                    name: "left".to_string(),
+                    start: 0,
+                    len: 3,
+                    reversed: true,
+                },
+                Zone {
+                    name: "right".to_string(),
+                    start: 3,
+                    len: 5,
+                    reversed: false,
+                },
+            ],
+            scenes: vec![Scene {
+                name: "test".to_string(),
+                zones: BTreeMap::new(),
+            }],
+            scene: "test".to_string(),
+            ..Default::default()
+        };
+        config
+            .set_zone_effect("left", Effect::Rainbow { period_secs: 1.0 })
+            .unwrap();
+        config.set_zone_effect("right", Effect::Static { color: BLUE }).unwrap();
+        assert!(config.set_zone_effect("roof", Effect::Off).is_err());
+
+        let frame = config.render(0.0, &Inputs::default(), false);
+        // Reversed, so the rainbow starts at the far end
+        assert_eq!(frame[2], RED);
+        // Cut short at the end of the strip
+        assert_eq!(&frame[3..], &[BLUE; 3]);
+
+        let night = config.render(0.0, &Inputs::default(), true);
+        assert_eq!(night[3], BLUE.dim(60));
+    }
+
+    #[test]
+    fn default_scenes_cover_every_zone() {
+        let config = LightingSettings::default();
+        assert!(config.current_scene().is_some());
+        for scene in config.scenes.iter().filter(|scene| scene.name != "off") {
+            for zone in &config.zones {
+                assert!(
+                    scene.zones.contains_key(&zone.name),
+                    "{} missing {}",
+                    scene.name,
+                    zone.name
+                );
+            }
+        }
+    }
+}
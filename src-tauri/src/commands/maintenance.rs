@@ -0,0 +1,707 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter};
+
+use super::{settings, trip};
+
+const SETTINGS_NAME: &str = "maintenance";
+const HISTORY_NAME: &str = "service_history";
+const DUE_EVENT: &str = "maintenance://due";
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const SECS_PER_DAY: f64 = 86400.0;
+const CSV_HEADER: &str = "item,date,odometer_km,engine_hours,notes";
+const SEEDED_NOTE: &str = "No earlier service logged, counting from here";
+
+/// Something that needs doing every so often. Whichever interval runs out first makes it due.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ServiceItem {
+    /// Stable name the history refers to, e.g. `oil_change`
+    pub id: String,
+    pub name: String,
+    pub interval_km: Option<f64>,
+    pub interval_days: Option<f64>,
+    pub interval_hours: Option<f64>,
+}
+
+impl ServiceItem {
+    fn new(id: &str, name: &str, km: Option<f64>, days: Option<f64>, hours: Option<f64>) -> Self {
+        ServiceItem {
+            id: id.to_string(),
+            name: name.to_string(),
+            interval_km: km,
+            interval_days: days,
+            interval_hours: hours,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct MaintenanceSettings {
+    pub items: Vec<ServiceItem>,
+    /// Remind when this much of an interval is left
+    pub due_soon_fraction: f64,
+}
+
+impl Default for MaintenanceSettings {
+    fn default() -> Self {
+        Self {
+            items: vec![
+                // Rotaries inject oil to seal the apexes and it thins out fast
+                ServiceItem::new("oil_change", "Oil and filter", Some(5000.0), Some(180.0), Some(150.0)),
+                ServiceItem::new("premix", "Premix top-up", Some(400.0), None, None),
+                ServiceItem::new("spark_plugs", "Spark plugs", Some(20000.0), Some(730.0), None),
+                ServiceItem::new(
+                    "compression_check",
+                    "Compression check",
+                    Some(20000.0),
+                    Some(365.0),
+                    None,
+                ),
+                ServiceItem::new("coolant", "Coolant flush", None, Some(730.0), None),
+            ],
+            due_soon_fraction: 0.1,
+        }
+    }
+}
+
+impl MaintenanceSettings {
+    fn validate(&self) -> Result<(), String> {
+        let mut ids = HashSet::new();
+        for item in &self.items {
+            if item.id.is_empty() {
+                return Err(format!("Service item \"{}\" needs an id", item.name));
+            }
+            if !ids.insert(&item.id) {
+                return Err(format!("Service item id \"{}\" is used twice", item.id));
+            }
+            let intervals = [item.interval_km, item.interval_days, item.interval_hours];
+            if intervals.iter().all(Option::is_none) {
+                return Err(format!("Service item \"{}\" needs an interval", item.id));
+            }
+            if intervals.iter().flatten().any(|interval| *interval <= 0.0) {
+                return Err(format!(
+                    "Service item \"{}\" has an interval that isn't positive",
+                    item.id
+                ));
+            }
+        }
+        if !(0.0..1.0).contains(&self.due_soon_fraction) {
+            return Err("Due soon fraction must be between 0 and 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// One completed service
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ServiceRecord {
+    /// Id of the service item
+    pub item: String,
+    /// Unix seconds
+    pub date: u64,
+    pub odometer_km: f64,
+    pub engine_hours: f64,
+    #[serde(default)]
+    pub notes: String,
+}
+
+/// Where the car is now, to measure intervals against
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Odometer {
+    pub km: f64,
+    pub engine_hours: f64,
+    pub date: u64,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DueState {
+    Ok,
+    DueSoon,
+    Overdue,
+    /// Never logged, so there's nothing to count from
+    Unlogged,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ItemStatus {
+    pub item: ServiceItem,
+    pub state: DueState,
+    pub last_service: Option<ServiceRecord>,
+    /// Negative once overdue
+    pub remaining_km: Option<f64>,
+    pub remaining_days: Option<f64>,
+    pub remaining_hours: Option<f64>,
+}
+
+fn status(item: &ServiceItem, history: &[ServiceRecord], now: Odometer, due_soon_fraction: f64) -> ItemStatus {
+    let last = history
+        .iter()
+        .filter(|record| record.item == item.id)
+        .max_by_key(|record| record.date);
+    let Some(last) = last else {
+        return ItemStatus {
+            item: item.clone(),
+            state: DueState::Unlogged,
+            last_service: None,
+            remaining_km: None,
+            remaining_days: None,
+            remaining_hours: None,
+        };
+    };
+
+    let since_days = now.date.saturating_sub(last.date) as f64 / SECS_PER_DAY;
+    let remaining = |interval: Option<f64>, used: f64| interval.map(|interval| (interval - used, interval));
+    let remaining = [
+        remaining(item.interval_km, now.km - last.odometer_km),
+        remaining(item.interval_days, since_days),
+        remaining(item.interval_hours, now.engine_hours - last.engine_hours),
+    ];
+
+    let state = if remaining.iter().flatten().any(|(left, _)| *left <= 0.0) {
+        DueState::Overdue
+    } else if remaining
+        .iter()
+        .flatten()
+        .any(|(left, interval)| *left <= interval * due_soon_fraction)
+    {
+        DueState::DueSoon
+    } else {
+        DueState::Ok
+    };
+
+    ItemStatus {
+        item: item.clone(),
+        state,
+        last_service: Some(last.clone()),
+        remaining_km: remaining[0].map(|(left, _)| left),
+        remaining_days: remaining[1].map(|(left, _)| left),
+        remaining_hours: remaining[2].map(|(left, _)| left),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryFormat {
+    Json,
+    Csv,
+}
+
+// Civil date conversions after Howard Hinnant's days_from_civil
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// `YYYY-MM-DD`, UTC
+fn format_date(secs: u64) -> String {
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn parse_date(date: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid date \"{}\", expected YYYY-MM-DD", date);
+    let mut parts = date.trim().splitn(3, '-');
+    let mut next = || parts.next().ok_or_else(invalid);
+    let year: i64 = next()?.parse().map_err(|_| invalid())?;
+    let month: u32 = next()?.parse().map_err(|_| invalid())?;
+    let day: u32 = next()?.parse().map_err(|_| invalid())?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+    let days = days_from_civil(year, month, day);
+    u64::try_from(days * 86400).map_err(|_| invalid())
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Service history as CSV, dates to the day
+pub fn to_csv(history: &[ServiceRecord]) -> String {
+    let mut out = format!("{}\n", CSV_HEADER);
+    for record in history {
+        out.push_str(&format!(
+            "{},{},{},{},{}\n",
+            csv_field(&record.item),
+            format_date(record.date),
+            record.odometer_km,
+            record.engine_hours,
+            csv_field(&record.notes)
+        ));
+    }
+    out
+}
+
+/// Rows of fields, with quoted fields allowed to hold commas, quotes and newlines
+fn split_csv(data: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = data.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("Unterminated quote".to_string());
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+pub fn from_csv(data: &str) -> Result<Vec<ServiceRecord>, String> {
+    let mut rows = split_csv(data)?.into_iter().enumerate();
+    match rows.next() {
+        Some((_, header)) if header.join(",") == CSV_HEADER => {}
+        _ => return Err(format!("Expected a \"{}\" header", CSV_HEADER)),
+    }
+
+    rows.filter(|(_, row)| row.iter().any(|field| !field.is_empty()))
+        .map(|(line, row)| {
+            let [item, date, odometer_km, engine_hours, notes] = <[String; 5]>::try_from(row)
+                .map_err(|row| format!("Row {}: expected 5 fields, got {}", line + 1, row.len()))?;
+            let number = |field: &str| {
+                field
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| format!("Row {}: \"{}\" isn't a number", line + 1, field))
+            };
+            Ok(ServiceRecord {
+                item,
+                date: parse_date(&date).map_err(|e| format!("Row {}: {}", line + 1, e))?,
+                odometer_km: number(&odometer_km)?,
+                engine_hours: number(&engine_hours)?,
+                notes,
+            })
+        })
+        .collect()
+}
+
+/// Add `records` that aren't already in `history`, keeping it in date order. Returns how many were new.
+fn merge(history: &mut Vec<ServiceRecord>, records: Vec<ServiceRecord>) -> usize {
+    let before = history.len();
+    for record in records {
+        if !history.contains(&record) {
+            history.push(record);
+        }
+    }
+    history.sort_by_key(|record| record.date);
+    history.len() - before
+}
+
+/// Start counting items that have never been logged from `now`, so they still come due.
+/// Returns whether any were added.
+fn seed_unlogged(history: &mut Vec<ServiceRecord>, items: &[ServiceItem], now: Odometer) -> bool {
+    let seeds: Vec<ServiceRecord> = items
+        .iter()
+        .filter(|item| !history.iter().any(|record| record.item == item.id))
+        .map(|item| ServiceRecord {
+            item: item.id.clone(),
+            date: now.date,
+            odometer_km: now.km,
+            engine_hours: now.engine_hours,
+            notes: SEEDED_NOTE.to_string(),
+        })
+        .collect();
+    merge(history, seeds) > 0
+}
+
+static SETTINGS: Lazy<Mutex<MaintenanceSettings>> = Lazy::new(|| Mutex::new(settings::load(SETTINGS_NAME)));
+static HISTORY: Lazy<Mutex<Vec<ServiceRecord>>> = Lazy::new(|| Mutex::new(settings::load(HISTORY_NAME)));
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0)
+}
+
+fn odometer() -> Odometer {
+    let lifetime = trip::lifetime();
+    Odometer {
+        km: lifetime.distance_km,
+        engine_hours: lifetime.engine_secs / 3600.0,
+        date: unix_secs(),
+    }
+}
+
+fn current_status() -> Result<Vec<ItemStatus>, String> {
+    let config = SETTINGS.lock().map_err(|e| e.to_string())?.clone();
+    let history = HISTORY.lock().map_err(|e| e.to_string())?;
+    let now = odometer();
+    Ok(config
+        .items
+        .iter()
+        .map(|item| status(item, &history, now, config.due_soon_fraction))
+        .collect())
+}
+
+fn due(status: Vec<ItemStatus>) -> Vec<ItemStatus> {
+    status
+        .into_iter()
+        .filter(|status| matches!(status.state, DueState::DueSoon | DueState::Overdue))
+        .collect()
+}
+
+// Due items last sent, to only remind again when they change
+static LAST_DUE: Mutex<Option<Vec<(String, bool)>>> = Mutex::new(None);
+
+fn seed_history() -> Result<(), String> {
+    let items = SETTINGS.lock().map_err(|e| e.to_string())?.items.clone();
+    let mut history = HISTORY.lock().map_err(|e| e.to_string())?;
+    let mut updated = history.clone();
+    if seed_unlogged(&mut updated, &items, odometer()) {
+        save_history(&updated)?;
+        *history = updated;
+    }
+    Ok(())
+}
+
+fn check_due(app: &AppHandle) {
+    if let Err(e) = seed_history() {
+        println!("Failed to start counting new service items: {}", e);
+    }
+    let due = match current_status() {
+        Ok(status) => due(status),
+        Err(_) => return,
+    };
+    let key: Vec<(String, bool)> = due
+        .iter()
+        .map(|status| (status.item.id.clone(), status.state == DueState::Overdue))
+        .collect();
+
+    if let Ok(mut last) = LAST_DUE.lock() {
+        if last.as_ref() != Some(&key) {
+            *last = Some(key);
+            if let Err(e) = app.emit(DUE_EVENT, due) {
+                println!("Failed to emit maintenance reminders: {}", e);
+            }
+        }
+    }
+}
+
+fn save_history(history: &[ServiceRecord]) -> Result<(), String> {
+    settings::save(HISTORY_NAME, &history)
+}
+
+/// Start checking for due services. Called once from the app setup hook.
+pub fn start(app: AppHandle) {
+    std::thread::spawn(move || loop {
+        check_due(&app);
+        std::thread::sleep(CHECK_INTERVAL);
+    });
+}
+
+#[tauri::command]
+pub fn get_maintenance_settings() -> Result<MaintenanceSettings, String> {
+    let config = SETTINGS.lock().map_err(|e| e.to_string())?;
+    Ok(config.clone())
+}
+
+#[tauri::command]
+pub fn set_maintenance_settings(app: AppHandle, config: MaintenanceSettings) -> Result<(), String> {
+    config.validate()?;
+    settings::save(SETTINGS_NAME, &config)?;
+    *SETTINGS.lock().map_err(|e| e.to_string())? = config;
+    check_due(&app);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_maintenance_status() -> Result<Vec<ItemStatus>, String> {
+    current_status()
+}
+
+/// Only the items that are due soon or overdue, for the home screen
+#[tauri::command]
+pub fn get_due_maintenance() -> Result<Vec<ItemStatus>, String> {
+    current_status().map(due)
+}
+
+/// Log `item` as done. Odometer, engine hours and date default to now, for logging a service done earlier.
+#[tauri::command]
+pub fn log_service(
+    app: AppHandle,
+    item: String,
+    notes: String,
+    odometer_km: Option<f64>,
+    engine_hours: Option<f64>,
+    date: Option<u64>,
+) -> Result<ServiceRecord, String> {
+    let known = SETTINGS
+        .lock()
+        .map_err(|e| e.to_string())?
+        .items
+        .iter()
+        .any(|known| known.id == item);
+    if !known {
+        return Err(format!("No service item \"{}\"", item));
+    }
+
+    let now = odometer();
+    let record = ServiceRecord {
+        item,
+        date: date.unwrap_or(now.date),
+        odometer_km: odometer_km.unwrap_or(now.km),
+        engine_hours: engine_hours.unwrap_or(now.engine_hours),
+        notes,
+    };
+
+    let mut history = HISTORY.lock().map_err(|e| e.to_string())?;
+    let mut updated = history.clone();
+    merge(&mut updated, vec![record.clone()]);
+    save_history(&updated)?;
+    *history = updated;
+    drop(history);
+
+    check_due(&app);
+    Ok(record)
+}
+
+#[tauri::command]
+pub fn get_service_history() -> Result<Vec<ServiceRecord>, String> {
+    let history = HISTORY.lock().map_err(|e| e.to_string())?;
+    Ok(history.clone())
+}
+
+#[tauri::command]
+pub fn delete_service_record(app: AppHandle, record: ServiceRecord) -> Result<(), String> {
+    let mut history = HISTORY.lock().map_err(|e| e.to_string())?;
+    let updated: Vec<ServiceRecord> = history.iter().filter(|kept| **kept != record).cloned().collect();
+    if updated.len() == history.len() {
+        return Err("No such service record".to_string());
+    }
+    save_history(&updated)?;
+    *history = updated;
+    drop(history);
+
+    check_due(&app);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn export_service_history(path: String, format: HistoryFormat) -> Result<(), String> {
+    let history = HISTORY.lock().map_err(|e| e.to_string())?.clone();
+    let contents = match format {
+        HistoryFormat::Json => serde_json::to_string_pretty(&history).map_err(|e| e.to_string())?,
+        HistoryFormat::Csv => to_csv(&history),
+    };
+    std::fs::write(&path, contents).map_err(|e| format!("Failed to write {}: {}", path, e))
+}
+
+/// Merge records from an export into the history, skipping ones already there. Returns how many were added.
+#[tauri::command]
+pub fn import_service_history(app: AppHandle, path: String, format: HistoryFormat) -> Result<usize, String> {
+    let contents = std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let records = match format {
+        HistoryFormat::Json => serde_json::from_str(&contents).map_err(|e| e.to_string())?,
+        HistoryFormat::Csv => from_csv(&contents)?,
+    };
+
+    let mut history = HISTORY.lock().map_err(|e| e.to_string())?;
+    let mut updated = history.clone();
+    let added = merge(&mut updated, records);
+    save_history(&updated)?;
+    *history = updated;
+    drop(history);
+
+    check_due(&app);
+    Ok(added)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 86400;
+
+    fn oil() -> ServiceItem {
+        ServiceItem::new("oil_change", "Oil and filter", Some(5000.0), Some(180.0), Some(150.0))
+    }
+
+    fn record(item: &str, date: u64, odometer_km: f64) -> ServiceRecord {
+        ServiceRecord {
+            item: item.to_string(),
+            date,
+            odometer_km,
+            engine_hours: 0.0,
+            notes: String::new(),
+        }
+    }
+
+    fn at(km: f64, engine_hours: f64, date: u64) -> Odometer {
+        Odometer { km, engine_hours, date }
+    }
+
+    #[test]
+    fn due_by_whichever_interval_runs_out_first() {
+        let history = vec![record("oil_change", 0, 100000.0), record("premix", 0, 90000.0)];
+
+        let status = status(&oil(), &history, at(102000.0, 10.0, 30 * DAY), 0.1);
+        assert_eq!(status.state, DueState::Ok);
+        assert_eq!(status.remaining_km, Some(3000.0));
+        assert_eq!(status.remaining_days, Some(150.0));
+        assert_eq!(status.remaining_hours, Some(140.0));
+
+        // Within 10% of the distance
+        assert_eq!(
+            super::status(&oil(), &history, at(104600.0, 10.0, 30 * DAY), 0.1).state,
+            DueState::DueSoon
+        );
+        // Hardly driven, but half a year has gone by
+        assert_eq!(
+            super::status(&oil(), &history, at(100500.0, 10.0, 181 * DAY), 0.1).state,
+            DueState::Overdue
+        );
+        // A track weekend's worth of hours
+        assert_eq!(
+            super::status(&oil(), &history, at(100500.0, 151.0, 30 * DAY), 0.1).state,
+            DueState::Overdue
+        );
+    }
+
+    #[test]
+    fn counts_from_latest_service() {
+        let history = vec![
+            record("oil_change", 10 * DAY, 105000.0),
+            record("oil_change", 0, 100000.0),
+        ];
+        let status = status(&oil(), &history, at(106000.0, 0.0, 20 * DAY), 0.1);
+        assert_eq!(status.last_service.unwrap().odometer_km, 105000.0);
+        assert_eq!(status.remaining_km, Some(4000.0));
+
+        let never = super::status(&oil(), &[], at(106000.0, 0.0, 20 * DAY), 0.1);
+        assert_eq!(never.state, DueState::Unlogged);
+    }
+
+    #[test]
+    fn unlogged_items_count_from_first_seen() {
+        let items = MaintenanceSettings::default().items;
+        let mut history = vec![record("oil_change", 0, 100000.0)];
+        let now = at(101000.0, 20.0, 10 * DAY);
+
+        assert!(seed_unlogged(&mut history, &items, now));
+        assert_eq!(history.len(), items.len());
+        // Logged items keep counting from their last service
+        assert_eq!(history[0], record("oil_change", 0, 100000.0));
+        // Nothing left to seed the second time round
+        assert!(!seed_unlogged(&mut history, &items, now));
+
+        let premix = items.iter().find(|item| item.id == "premix").unwrap();
+        let later = status(premix, &history, at(101390.0, 25.0, 11 * DAY), 0.1);
+        assert_eq!(later.state, DueState::DueSoon);
+        assert_eq!(later.remaining_km, Some(10.0));
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let history = vec![
+            ServiceRecord {
+                item: "oil_change".to_string(),
+                // 2024-03-05
+                date: 1709596800,
+                odometer_km: 123456.5,
+                engine_hours: 812.25,
+                notes: "Motul 10W-40, \"Idemitsu\" filter,\nchecked premix".to_string(),
+            },
+            record("spark_plugs", 1709596800 + 40 * DAY, 124000.0),
+        ];
+
+        let csv = to_csv(&history);
+        assert!(csv.starts_with("item,date,odometer_km,engine_hours,notes\noil_change,2024-03-05,123456.5,"));
+        assert_eq!(from_csv(&csv).unwrap(), history);
+    }
+
+    #[test]
+    fn rejects_bad_csv() {
+        assert!(from_csv("oil_change,2024-03-05,1,2,\n").is_err());
+        assert!(from_csv(&format!("{}\noil_change,2024-13-05,1,2,\n", CSV_HEADER)).is_err());
+        assert!(from_csv(&format!("{}\noil_change,2024-03-05,lots,2,\n", CSV_HEADER)).is_err());
+        assert!(from_csv(&format!("{}\noil_change,2024-03-05,1\n", CSV_HEADER)).is_err());
+        assert_eq!(from_csv(&format!("{}\n\n", CSV_HEADER)).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951782400), "2000-02-29");
+        assert_eq!(parse_date("2000-02-29"), Ok(951782400));
+        assert_eq!(parse_date("2024-12-31").map(format_date), Ok("2024-12-31".to_string()));
+    }
+
+    #[test]
+    fn merge_skips_duplicates() {
+        let mut history = vec![record("premix", 5 * DAY, 100.0)];
+        let added = merge(
+            &mut history,
+            vec![record("premix", 5 * DAY, 100.0), record("premix", DAY, 50.0)],
+        );
+        assert_eq!(added, 1);
+        assert_eq!(history[0].date, DAY);
+    }
+
+    #[test]
+    fn validates_items() {
+        assert!(MaintenanceSettings::default().validate().is_ok());
+
+        let mut config = MaintenanceSettings::default();
+        config.items.push(oil());
+        assert!(config.validate().is_err());
+
+        let mut config = MaintenanceSettings::default();
+        config.items[0].interval_km = None;
+        config.items[0].interval_days = None;
+        config.items[0].interval_hours = None;
+        assert!(config.validate().is_err());
+    }
+}
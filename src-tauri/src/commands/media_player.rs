@@ -46,6 +46,9 @@ trait MediaTransport1 {
     
     #[zbus(property)]
     fn set_volume(&self, volume: u16) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn codec(&self) -> zbus::Result<u8>;
 }
 
 /// Convert a Bluetooth address (78:3F:4D:F4:02:CE) to D-Bus path format (78_3F_4D_F4_02_CE)
@@ -284,6 +287,30 @@ pub async fn set_volume(address: &str, volume: u8) -> Result<(), Box<dyn std::er
     Ok(())
 }
 
+/// Name of the A2DP codec negotiated on the device's media transport
+pub async fn get_codec(address: &str) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let connection = Connection::system().await?;
+    
+    if let Some(transport_path) = find_media_transport_path(&connection, address).await {
+        let proxy = MediaTransport1Proxy::builder(&connection)
+            .path(transport_path.as_str())?
+            .build()
+            .await?;
+        
+        // A2DP codec IDs from the Bluetooth assigned numbers
+        let codec = match proxy.codec().await? {
+            0x00 => "SBC",
+            0x01 => "MP3",
+            0x02 => "AAC",
+            0x04 => "ATRAC",
+            _ => "Vendor",
+        };
+        Ok(Some(codec.to_string()))
+    } else {
+        Ok(None)
+    }
+}
+
 fn extract_string_from_variant(map: &HashMap<String, OwnedValue>, key: &str) -> Option<String> {
     map.get(key).and_then(|v| {
         // Try to get string value using TryFrom for reference
@@ -298,10 +325,8 @@ fn extract_u64_from_variant(map: &HashMap<String, OwnedValue>, key: &str) -> Opt
         // Try u32 first (most common for duration in milliseconds)
         if let Ok(n) = <u32 as TryFrom<&OwnedValue>>::try_from(v) {
             Some(n as u64)
-        } else if let Ok(n) = <u64 as TryFrom<&OwnedValue>>::try_from(v) {
-            Some(n)
         } else {
-            None
+            <u64 as TryFrom<&OwnedValue>>::try_from(v).ok()
         }
     })
 }
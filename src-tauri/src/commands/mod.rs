@@ -1,11 +1,42 @@
+pub mod adc;
+pub mod alarms;
+pub mod analog_sensors;
 pub mod audio;
+pub mod audio_route;
+pub mod auto_brightness;
+pub mod camera;
+pub mod display;
+pub mod display_power;
+pub mod dsp;
+pub mod gpio;
+pub mod headlights;
+pub mod illumination;
+pub mod light_sensor;
+pub mod lighting;
+pub mod maintenance;
+pub mod physical_controls;
+pub mod pins;
+pub mod power;
+pub mod power_fc;
+pub mod pulse;
+pub mod reverse;
+pub mod settings;
+pub mod shift_light;
+pub mod solar;
+pub mod soundboard;
+pub mod speed_volume;
+pub mod spectrum;
+pub mod steering_buttons;
+pub mod tach;
+pub mod telemetry;
+pub mod trip;
+pub mod vehicle;
+pub mod wideband;
+pub mod ws2812;
 
 #[cfg(target_os = "linux")]
 pub mod bluetooth;
 
-#[cfg(target_os = "linux")]
-pub mod display;
-
 #[cfg(target_os = "linux")]
 pub mod media_player;
 
@@ -143,12 +174,3 @@ pub mod bluetooth {
         Ok(None)
     }
 }
-
-#[cfg(not(target_os = "linux"))]
-pub mod display {
-    #[tauri::command]
-    pub fn set_brightness(value: u8) -> Result<(), String> {
-        println!("Brightness control not available on this platform (requested: {})", value);
-        Ok(())
-    }
-}
@@ -0,0 +1,531 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+
+use super::gpio::{self, Debouncer, DigitalInput, GpioProvider, Pull};
+use super::{display_power, settings};
+
+const SETTINGS_NAME: &str = "physical_controls";
+const ACTION_EVENT: &str = "input://action";
+// Quadrature edges on a hand-turned knob are a few ms apart at the fastest
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// What a knob turn or button press does, carried out by the UI
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlAction {
+    VolumeUp,
+    VolumeDown,
+    NextTrack,
+    PreviousTrack,
+    PlayPause,
+    NextScreen,
+    PreviousScreen,
+    Home,
+    Back,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ControlEvent {
+    pub action: ControlAction,
+    /// Repeat count, more than 1 when the knob is spun quickly
+    pub steps: u32,
+    /// Name of the control that fired
+    pub source: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct EncoderSettings {
+    /// BCM pins for the CLK (A) and DT (B) outputs
+    pub pin_a: u8,
+    pub pin_b: u8,
+    pub pull: Pull,
+    /// 4 for KY-040 style full-step encoders, 2 for half-step ones
+    pub transitions_per_detent: u8,
+    pub clockwise: ControlAction,
+    pub counter_clockwise: ControlAction,
+    /// Send several steps per detent when the knob is spun quickly
+    pub acceleration: bool,
+}
+
+impl Default for EncoderSettings {
+    fn default() -> Self {
+        Self {
+            pin_a: 5,
+            pin_b: 6,
+            pull: Pull::Up,
+            transitions_per_detent: 4,
+            clockwise: ControlAction::VolumeUp,
+            counter_clockwise: ControlAction::VolumeDown,
+            acceleration: true,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ButtonSettings {
+    pub name: String,
+    pub pin: u8,
+    pub pull: Pull,
+    /// Buttons switching to ground read low while pressed
+    pub active_low: bool,
+    pub press: ControlAction,
+    /// Fired instead of `press` when held, None to fire `press` straight away
+    pub long_press: Option<ControlAction>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct PhysicalControlsSettings {
+    pub enabled: bool,
+    pub encoder: Option<EncoderSettings>,
+    pub buttons: Vec<ButtonSettings>,
+    pub debounce_ms: u64,
+    pub long_press_ms: u64,
+}
+
+impl Default for PhysicalControlsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            encoder: Some(EncoderSettings::default()),
+            buttons: vec![ButtonSettings {
+                // KY-040 push switch
+                name: "knob".to_string(),
+                pin: 13,
+                pull: Pull::Up,
+                active_low: true,
+                press: ControlAction::PlayPause,
+                long_press: Some(ControlAction::Home),
+            }],
+            debounce_ms: 20,
+            long_press_ms: 600,
+        }
+    }
+}
+
+// Steps for each (previous << 2 | current) Gray code transition, 0 for
+// no change or an impossible jump where both lines changed at once
+const TRANSITIONS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+// Both lines high, where a full-step encoder rests between detents
+const DETENT_STATE: u8 = 0b11;
+
+/// Turns A/B line levels into detent clicks. Contact bounce shows up as
+/// back-and-forth transitions that cancel out, so the lines aren't debounced.
+pub struct QuadratureDecoder {
+    state: u8,
+    accumulator: i8,
+    transitions_per_detent: i8,
+}
+
+impl QuadratureDecoder {
+    pub fn new(a: bool, b: bool, transitions_per_detent: u8) -> Self {
+        Self {
+            state: (a as u8) << 1 | b as u8,
+            accumulator: 0,
+            transitions_per_detent: transitions_per_detent.clamp(1, 4) as i8,
+        }
+    }
+
+    /// Feed the current line levels. Returns 1 for a clockwise click, -1 for counter-clockwise.
+    pub fn update(&mut self, a: bool, b: bool) -> i8 {
+        let next = (a as u8) << 1 | b as u8;
+        if next == self.state {
+            return 0;
+        }
+
+        self.accumulator += TRANSITIONS[(self.state << 2 | next) as usize];
+        self.state = next;
+
+        if self.accumulator >= self.transitions_per_detent {
+            self.accumulator = 0;
+            return 1;
+        }
+        if self.accumulator <= -self.transitions_per_detent {
+            self.accumulator = 0;
+            return -1;
+        }
+
+        // Resync at rest, so a missed edge can't leave every later click half a detent off
+        if next == DETENT_STATE && self.transitions_per_detent == 4 {
+            self.accumulator = 0;
+        }
+        0
+    }
+}
+
+/// Scales clicks up when they come in quick succession
+#[derive(Default)]
+pub struct Acceleration {
+    last_click: Option<Instant>,
+}
+
+impl Acceleration {
+    pub fn steps(&mut self, now: Instant) -> u32 {
+        let gap = self.last_click.map(|last| now.saturating_duration_since(last));
+        self.last_click = Some(now);
+
+        match gap {
+            Some(gap) if gap < Duration::from_millis(40) => 4,
+            Some(gap) if gap < Duration::from_millis(100) => 2,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ButtonEvent {
+    Press,
+    LongPress,
+}
+
+pub struct ButtonDecoder {
+    debouncer: Debouncer,
+    long_press: Option<Duration>,
+    pressed_at: Option<Instant>,
+    long_fired: bool,
+}
+
+impl ButtonDecoder {
+    pub fn new(debounce: Duration, long_press: Option<Duration>) -> Self {
+        Self {
+            debouncer: Debouncer::new(debounce),
+            long_press,
+            pressed_at: None,
+            long_fired: false,
+        }
+    }
+
+    pub fn update(&mut self, pressed: bool, now: Instant) -> Option<ButtonEvent> {
+        match self.debouncer.update(pressed, now) {
+            Some(true) => {
+                self.pressed_at = Some(now);
+                self.long_fired = false;
+                // Without a long press action there's nothing to wait for
+                if self.long_press.is_none() {
+                    return Some(ButtonEvent::Press);
+                }
+            }
+            Some(false) => {
+                let was_pressed = self.pressed_at.take().is_some();
+                if was_pressed && self.long_press.is_some() && !self.long_fired {
+                    return Some(ButtonEvent::Press);
+                }
+            }
+            None => {}
+        }
+
+        match (self.pressed_at, self.long_press) {
+            (Some(pressed_at), Some(hold)) if !self.long_fired && now.saturating_duration_since(pressed_at) >= hold => {
+                self.long_fired = true;
+                Some(ButtonEvent::LongPress)
+            }
+            _ => None,
+        }
+    }
+}
+
+struct Encoder {
+    config: EncoderSettings,
+    a: Box<dyn DigitalInput>,
+    b: Box<dyn DigitalInput>,
+    decoder: QuadratureDecoder,
+    acceleration: Acceleration,
+}
+
+struct Button {
+    config: ButtonSettings,
+    input: Box<dyn DigitalInput>,
+    decoder: ButtonDecoder,
+}
+
+struct Controls {
+    encoder: Option<Encoder>,
+    buttons: Vec<Button>,
+}
+
+impl Controls {
+    fn open(gpio: &dyn GpioProvider, config: &PhysicalControlsSettings) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let encoder = match &config.encoder {
+            Some(encoder) => {
+                let a = gpio.input(encoder.pin_a, encoder.pull)?;
+                let b = gpio.input(encoder.pin_b, encoder.pull)?;
+                Some(Encoder {
+                    decoder: QuadratureDecoder::new(a.is_high(), b.is_high(), encoder.transitions_per_detent),
+                    config: encoder.clone(),
+                    a,
+                    b,
+                    acceleration: Acceleration::default(),
+                })
+            }
+            None => None,
+        };
+
+        let debounce = Duration::from_millis(config.debounce_ms);
+        let long_press = Duration::from_millis(config.long_press_ms);
+        let buttons = config
+            .buttons
+            .iter()
+            .map(|button| {
+                Ok(Button {
+                    input: gpio.input(button.pin, button.pull)?,
+                    decoder: ButtonDecoder::new(debounce, button.long_press.map(|_| long_press)),
+                    config: button.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, Box<dyn Error + Send + Sync>>>()?;
+
+        Ok(Self { encoder, buttons })
+    }
+
+    fn poll(&mut self, now: Instant) -> Vec<ControlEvent> {
+        let mut events = vec![];
+
+        if let Some(encoder) = self.encoder.as_mut() {
+            let click = encoder.decoder.update(encoder.a.is_high(), encoder.b.is_high());
+            if click != 0 {
+                let steps = if encoder.config.acceleration {
+                    encoder.acceleration.steps(now)
+                } else {
+                    1
+                };
+                events.push(ControlEvent {
+                    action: if click > 0 {
+                        encoder.config.clockwise
+                    } else {
+                        encoder.config.counter_clockwise
+                    },
+                    steps,
+                    source: "encoder".to_string(),
+                });
+            }
+        }
+
+        for button in self.buttons.iter_mut() {
+            let pressed = button.input.is_high() != button.config.active_low;
+            let action = match button.decoder.update(pressed, now) {
+                Some(ButtonEvent::Press) => Some(button.config.press),
+                Some(ButtonEvent::LongPress) => button.config.long_press,
+                None => None,
+            };
+            if let Some(action) = action {
+                events.push(ControlEvent {
+                    action,
+                    steps: 1,
+                    source: button.config.name.clone(),
+                });
+            }
+        }
+
+        events
+    }
+}
+
+static SETTINGS: Lazy<Mutex<PhysicalControlsSettings>> = Lazy::new(|| Mutex::new(settings::load(SETTINGS_NAME)));
+
+/// Send an action to the UI, waking the display first
+pub fn dispatch(app: &AppHandle, event: ControlEvent) {
+    display_power::wake();
+    if let Err(e) = app.emit(ACTION_EVENT, event) {
+        println!("Failed to emit control action: {}", e);
+    }
+}
+
+/// Start reading the knob and buttons. Called once from the app setup hook.
+pub fn start(app: AppHandle) {
+    let config = match SETTINGS.lock() {
+        Ok(config) => config.clone(),
+        Err(_) => return,
+    };
+    if !config.enabled {
+        return;
+    }
+
+    std::thread::spawn(move || {
+        let gpio = gpio::provider();
+        let mut controls = loop {
+            match Controls::open(gpio.as_ref(), &config) {
+                Ok(controls) => break controls,
+                Err(e) => {
+                    println!("Failed to open physical controls: {}", e);
+                    std::thread::sleep(RETRY_DELAY);
+                }
+            }
+        };
+
+        loop {
+            for event in controls.poll(Instant::now()) {
+                dispatch(&app, event);
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_physical_controls_settings() -> Result<PhysicalControlsSettings, String> {
+    let config = SETTINGS.lock().map_err(|e| e.to_string())?;
+    Ok(config.clone())
+}
+
+/// Pins are claimed at startup, changes apply after a restart
+#[tauri::command]
+pub fn set_physical_controls_settings(config: PhysicalControlsSettings) -> Result<(), String> {
+    settings::save(SETTINGS_NAME, &config)?;
+    *SETTINGS.lock().map_err(|e| e.to_string())? = config;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::gpio::FakeGpio;
+
+    // One KY-040 detent clockwise, as (A, B) samples starting from rest
+    const CLOCKWISE: [(bool, bool); 4] = [(false, true), (false, false), (true, false), (true, true)];
+    const COUNTER_CLOCKWISE: [(bool, bool); 4] = [(true, false), (false, false), (false, true), (true, true)];
+
+    fn decode(decoder: &mut QuadratureDecoder, samples: &[(bool, bool)]) -> Vec<i8> {
+        samples
+            .iter()
+            .map(|&(a, b)| decoder.update(a, b))
+            .filter(|&click| click != 0)
+            .collect()
+    }
+
+    fn at(start: Instant, ms: u64) -> Instant {
+        start + Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn full_step_detents() {
+        let mut decoder = QuadratureDecoder::new(true, true, 4);
+
+        let samples: Vec<_> = CLOCKWISE.iter().chain(CLOCKWISE.iter()).copied().collect();
+        assert_eq!(decode(&mut decoder, &samples), vec![1, 1]);
+        assert_eq!(decode(&mut decoder, &COUNTER_CLOCKWISE), vec![-1]);
+    }
+
+    #[test]
+    fn bouncing_edges_cancel_out() {
+        let mut decoder = QuadratureDecoder::new(true, true, 4);
+
+        // A chatters as it falls, then B as it rises
+        let samples = [
+            (false, true),
+            (true, true),
+            (false, true),
+            (true, true),
+            (false, true),
+            (false, false),
+            (true, false),
+            (true, true),
+            (true, false),
+            (true, true),
+        ];
+        assert_eq!(decode(&mut decoder, &samples), vec![1]);
+    }
+
+    #[test]
+    fn turning_back_before_the_detent_is_ignored() {
+        let mut decoder = QuadratureDecoder::new(true, true, 4);
+
+        let samples = [(false, true), (false, false), (false, true), (true, true)];
+        assert!(decode(&mut decoder, &samples).is_empty());
+    }
+
+    #[test]
+    fn missed_edge_resyncs_at_rest() {
+        let mut decoder = QuadratureDecoder::new(true, true, 4);
+
+        // The (0, 0) sample was missed, so this detent is lost...
+        let samples = [(false, true), (true, false), (true, true)];
+        assert!(decode(&mut decoder, &samples).is_empty());
+        // ...but the next one isn't thrown off
+        assert_eq!(decode(&mut decoder, &CLOCKWISE), vec![1]);
+    }
+
+    #[test]
+    fn half_step_encoder() {
+        let mut decoder = QuadratureDecoder::new(true, true, 2);
+        assert_eq!(decode(&mut decoder, &CLOCKWISE), vec![1, 1]);
+    }
+
+    #[test]
+    fn fast_spins_accelerate() {
+        let start = Instant::now();
+        let mut acceleration = Acceleration::default();
+
+        assert_eq!(acceleration.steps(at(start, 0)), 1);
+        assert_eq!(acceleration.steps(at(start, 300)), 1);
+        assert_eq!(acceleration.steps(at(start, 370)), 2);
+        assert_eq!(acceleration.steps(at(start, 390)), 4);
+        assert_eq!(acceleration.steps(at(start, 1000)), 1);
+    }
+
+    #[test]
+    fn button_short_and_long_press() {
+        let start = Instant::now();
+        let mut button = ButtonDecoder::new(Duration::from_millis(20), Some(Duration::from_millis(600)));
+
+        button.update(false, at(start, 0));
+        button.update(false, at(start, 20));
+
+        // Short press fires on release
+        assert_eq!(button.update(true, at(start, 100)), None);
+        assert_eq!(button.update(true, at(start, 120)), None);
+        assert_eq!(button.update(false, at(start, 300)), None);
+        assert_eq!(button.update(false, at(start, 320)), Some(ButtonEvent::Press));
+
+        // Long press fires while held, and nothing on release
+        button.update(true, at(start, 1000));
+        assert_eq!(button.update(true, at(start, 1020)), None);
+        assert_eq!(button.update(true, at(start, 1620)), Some(ButtonEvent::LongPress));
+        assert_eq!(button.update(true, at(start, 2000)), None);
+        button.update(false, at(start, 2100));
+        assert_eq!(button.update(false, at(start, 2120)), None);
+    }
+
+    #[test]
+    fn button_without_long_press_fires_on_press() {
+        let start = Instant::now();
+        let mut button = ButtonDecoder::new(Duration::from_millis(20), None);
+
+        button.update(false, at(start, 0));
+        button.update(false, at(start, 20));
+        button.update(true, at(start, 100));
+        assert_eq!(button.update(true, at(start, 120)), Some(ButtonEvent::Press));
+    }
+
+    #[test]
+    fn controls_map_to_actions() {
+        let gpio = FakeGpio::default();
+        let config = PhysicalControlsSettings::default();
+        let mut controls = Controls::open(&gpio, &config).unwrap();
+        let start = Instant::now();
+
+        let mut actions = vec![];
+        for (i, &(a, b)) in CLOCKWISE.iter().enumerate() {
+            gpio.set_level(5, a);
+            gpio.set_level(6, b);
+            actions.extend(controls.poll(at(start, i as u64)).into_iter().map(|e| e.action));
+        }
+        assert_eq!(actions, vec![ControlAction::VolumeUp]);
+
+        // Push switch pulls the line low
+        gpio.set_level(13, false);
+        controls.poll(at(start, 100));
+        controls.poll(at(start, 120));
+        gpio.set_level(13, true);
+        controls.poll(at(start, 200));
+        let events = controls.poll(at(start, 220));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].action, ControlAction::PlayPause);
+        assert_eq!(events[0].source, "knob");
+    }
+}
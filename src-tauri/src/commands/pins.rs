@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+use super::adc::{AdcModel, AdcSettings};
+use super::analog_sensors::{self, AnalogSensorsConfig};
+use super::headlights::{self, HeadlightSettings};
+use super::illumination::{self, IlluminationSettings};
+use super::lighting::{self, LightingSettings};
+use super::physical_controls::{self, PhysicalControlsSettings};
+use super::power::{self, PowerSettings};
+use super::reverse::{self, ReverseSettings};
+use super::shift_light::{self, ShiftLightOutput, ShiftLightSettings};
+use super::steering_buttons::{self, SteeringButtonsSettings};
+use super::tach::{self, TachSettings};
+
+/// A pin and what it's used for
+pub type Claim = (u8, &'static str);
+
+// Taken whatever the settings say
+const RESERVED: [Claim; 5] = [
+    (2, "I2C bus"),
+    (3, "I2C bus"),
+    (14, "serial console"),
+    (15, "serial console"),
+    (18, "backlight PWM"),
+];
+
+/// Pins the kernel claims for an LED strip's SPI bus, as deploy/setup-pi.sh
+/// sets the buses up: SPI0 with no chip select or MISO, SPI1 with CE0 moved
+/// off the backlight to GPIO16
+pub fn strip_pins(bus: u8) -> &'static [u8] {
+    match bus {
+        0 => &[10, 11],
+        1 => &[16, 19, 20, 21],
+        _ => &[],
+    }
+}
+
+/// An MCP3008 needs SPI0 with its chip selects and MISO back, so it can't
+/// share the bus with a strip
+fn mcp3008_pins(adc: &AdcSettings) -> &'static [u8] {
+    match (adc.model, adc.chip_select) {
+        (AdcModel::Ads1115, _) => &[],
+        (AdcModel::Mcp3008, 1) => &[7, 9, 10, 11],
+        (AdcModel::Mcp3008, _) => &[8, 9, 10, 11],
+    }
+}
+
+/// Every module's settings that decide which pins it takes
+#[derive(Default)]
+struct Modules {
+    illumination: IlluminationSettings,
+    controls: PhysicalControlsSettings,
+    power: PowerSettings,
+    reverse: ReverseSettings,
+    tach: TachSettings,
+    shift_light: ShiftLightSettings,
+    lighting: LightingSettings,
+    steering_buttons: SteeringButtonsSettings,
+    sensors: AnalogSensorsConfig,
+    headlights: HeadlightSettings,
+}
+
+impl Modules {
+    fn current() -> Self {
+        Self {
+            illumination: illumination::get_illumination_settings().unwrap_or_default(),
+            controls: physical_controls::get_physical_controls_settings().unwrap_or_default(),
+            power: power::get_power_settings().unwrap_or_default(),
+            reverse: reverse::get_reverse_settings().unwrap_or_default(),
+            tach: tach::get_tach_settings().unwrap_or_default(),
+            shift_light: shift_light::get_shift_light_settings().unwrap_or_default(),
+            lighting: lighting::get_lighting_settings().unwrap_or_default(),
+            steering_buttons: steering_buttons::get_steering_buttons_settings().unwrap_or_default(),
+            sensors: analog_sensors::get_analog_sensors_config().unwrap_or_default(),
+            headlights: headlights::get_headlight_settings().unwrap_or_default(),
+        }
+    }
+
+    /// Pins held by the enabled modules, labelled with the module's name
+    fn claims(&self) -> Vec<Claim> {
+        let mut claims = RESERVED.to_vec();
+        let mut add = |pins: &[u8], user: &'static str| claims.extend(pins.iter().map(|pin| (*pin, user)));
+
+        if self.illumination.enabled {
+            add(&[self.illumination.pin], "illumination input");
+        }
+        if self.controls.enabled {
+            if let Some(encoder) = &self.controls.encoder {
+                add(&[encoder.pin_a, encoder.pin_b], "rotary encoder");
+            }
+            for button in &self.controls.buttons {
+                add(&[button.pin], "dash buttons");
+            }
+        }
+        if self.power.enabled {
+            add(&[self.power.acc_pin], "ACC sense");
+            if let Some(pin) = self.power.hold_pin {
+                add(&[pin], "power hold");
+            }
+        }
+        if self.reverse.enabled {
+            add(&[self.reverse.pin], "reverse input");
+        }
+        if self.tach.enabled {
+            add(&[self.tach.pin], "tachometer");
+        }
+        if self.shift_light.enabled {
+            match self.shift_light.output {
+                ShiftLightOutput::Gpio { pin, .. } => add(&[pin], "shift light"),
+                ShiftLightOutput::Strip { bus, .. } => add(strip_pins(bus), "shift light"),
+            }
+        }
+        if self.lighting.enabled {
+            add(strip_pins(self.lighting.bus), "lighting strip");
+        }
+        if self.steering_buttons.enabled {
+            add(mcp3008_pins(&self.steering_buttons.adc), "steering wheel ADC");
+        }
+        if !self.sensors.sensors.is_empty() {
+            add(mcp3008_pins(&self.sensors.adc), "sensor ADC");
+        }
+        if self.headlights.enabled {
+            let pins: Vec<u8> = self.headlights.pins().iter().map(|(pin, _)| *pin).collect();
+            add(&pins, "headlights");
+        }
+        claims
+    }
+}
+
+/// Refuse `pins` if one is listed twice, or is already claimed by anything but `owner`
+fn check_against(owner: &str, pins: &[(u8, &str)], claims: &[Claim]) -> Result<(), String> {
+    let mut seen = HashMap::new();
+    for (pin, name) in pins {
+        if let Some(other) = seen.insert(pin, name) {
+            return Err(format!("GPIO{} is set for both the {} and the {}", pin, other, name));
+        }
+        if let Some((_, user)) = claims.iter().find(|(used, user)| used == pin && *user != owner) {
+            return Err(format!("GPIO{} for the {} is already used by the {}", pin, name, user));
+        }
+    }
+    Ok(())
+}
+
+/// Check the pins `owner` is about to claim against every other enabled module's
+pub fn check(owner: &str, pins: &[(u8, &str)]) -> Result<(), String> {
+    check_against(owner, pins, &Modules::current().claims())
+}
+
+/// Check an LED strip's SPI bus against every other enabled module's pins
+pub fn check_strip(owner: &str, bus: u8) -> Result<(), String> {
+    let pins = strip_pins(bus);
+    if pins.is_empty() {
+        return Err(format!("SPI{} isn't set up for LED strips, use bus 0 or 1", bus));
+    }
+    let named: Vec<(u8, &str)> = pins.iter().map(|pin| (*pin, owner)).collect();
+    check(owner, &named)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn everything_enabled() -> Modules {
+        let mut modules = Modules::default();
+        modules.illumination.enabled = true;
+        modules.controls.enabled = true;
+        modules.power.enabled = true;
+        modules.reverse.enabled = true;
+        modules.tach.enabled = true;
+        modules.shift_light.enabled = true;
+        modules.lighting.enabled = true;
+        modules.steering_buttons.enabled = true;
+        modules.headlights.enabled = true;
+        modules
+    }
+
+    #[test]
+    fn defaults_dont_overlap() {
+        let claims = everything_enabled().claims();
+        let mut seen = HashMap::new();
+        for (pin, user) in &claims {
+            if let Some(other) = seen.insert(pin, user) {
+                assert_eq!(other, user, "GPIO{} is claimed twice", pin);
+            }
+        }
+    }
+
+    #[test]
+    fn clashes_are_refused() {
+        let mut modules = everything_enabled();
+        let claims = modules.claims();
+
+        // A module's own pins don't clash with themselves
+        let headlights = modules.headlights.pins();
+        assert_eq!(check_against("headlights", &headlights, &claims), Ok(()));
+
+        // The lighting strip moved onto the shift light's bus
+        let err = check_against("lighting strip", &[(10, "lighting strip")], &claims).unwrap_err();
+        assert!(err.contains("shift light"), "{}", err);
+
+        // Or onto the backlight
+        let err = check_against("lighting strip", &[(18, "lighting strip")], &claims).unwrap_err();
+        assert!(err.contains("backlight"), "{}", err);
+
+        // An MCP3008 wants SPI0 back from the shift light strip
+        modules.steering_buttons.adc.model = AdcModel::Mcp3008;
+        let claims = modules.claims();
+        let mcp3008: Vec<(u8, &str)> = mcp3008_pins(&modules.steering_buttons.adc)
+            .iter()
+            .map(|pin| (*pin, "steering wheel ADC"))
+            .collect();
+        assert!(check_against("steering wheel ADC", &mcp3008, &claims).is_err());
+
+        let mut twice = modules.headlights.clone();
+        twice.right.up_sense = twice.left.up_relay;
+        assert!(check_against("headlights", &twice.pins(), &[]).is_err());
+    }
+}
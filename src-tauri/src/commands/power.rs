@@ -0,0 +1,345 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+
+#[cfg(target_os = "linux")]
+use zbus::Connection;
+
+use super::gpio::{self, DigitalOutput, GpioProvider, Pull, SenseInput};
+use super::{settings, trip};
+
+const SETTINGS_NAME: &str = "power";
+const POWER_STATE_EVENT: &str = "power://state";
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+// Tries through logind before falling back to systemctl
+const POWER_OFF_ATTEMPTS: u32 = 3;
+
+/// D-Bus proxy for systemd-logind, used to power off cleanly
+#[cfg(target_os = "linux")]
+#[zbus::proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait LoginManager {
+    fn power_off(&self, interactive: bool) -> zbus::Result<()>;
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct PowerSettings {
+    /// Off by default so a bench setup without ACC wiring doesn't shut itself down
+    pub enabled: bool,
+    /// BCM pin wired to the switched accessory feed
+    pub acc_pin: u8,
+    pub acc_pull: Pull,
+    /// An optocoupler pulls the pin low while ACC is live
+    pub acc_active_low: bool,
+    /// Pin holding the supply latch on, None without a latch
+    pub hold_pin: Option<u8>,
+    pub debounce_ms: u64,
+    /// How long ACC has to stay off before shutting down
+    pub grace_secs: u64,
+}
+
+impl Default for PowerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            acc_pin: 22,
+            acc_pull: Pull::Up,
+            acc_active_low: true,
+            hold_pin: Some(27),
+            debounce_ms: 500,
+            grace_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum PowerState {
+    Running,
+    /// ACC is off, shutting down once the grace period runs out
+    ShutdownPending { seconds: u64 },
+    ShuttingDown,
+}
+
+/// Grace timer between ACC going off and the actual shutdown
+pub struct ShutdownTimer {
+    grace: Duration,
+    deadline: Option<Instant>,
+    committed: bool,
+}
+
+impl ShutdownTimer {
+    pub fn new(grace: Duration) -> Self {
+        Self {
+            grace,
+            deadline: None,
+            committed: false,
+        }
+    }
+
+    /// Feed a debounced ACC change. Returns the new state when it changes.
+    pub fn acc_changed(&mut self, acc_on: bool, now: Instant) -> Option<PowerState> {
+        // Too late once the shutdown has started
+        if self.committed {
+            return None;
+        }
+
+        match (acc_on, self.deadline) {
+            (false, None) => {
+                self.deadline = Some(now + self.grace);
+                Some(PowerState::ShutdownPending {
+                    seconds: self.grace.as_secs(),
+                })
+            }
+            (true, Some(_)) => {
+                self.deadline = None;
+                Some(PowerState::Running)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns `ShuttingDown` once the grace period has run out
+    pub fn tick(&mut self, now: Instant) -> Option<PowerState> {
+        match self.deadline {
+            Some(deadline) if !self.committed && now >= deadline => {
+                self.committed = true;
+                Some(PowerState::ShuttingDown)
+            }
+            _ => None,
+        }
+    }
+}
+
+struct AccSense {
+    input: SenseInput,
+    // Kept for the life of the process so the latch stays held through shutdown
+    hold: Option<Box<dyn DigitalOutput>>,
+}
+
+impl AccSense {
+    fn new(gpio: &dyn GpioProvider, config: &PowerSettings) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let hold = match config.hold_pin {
+            Some(pin) => Some(gpio.output(pin, true)?),
+            None => None,
+        };
+
+        let debounce = Duration::from_millis(config.debounce_ms);
+        Ok(Self {
+            input: SenseInput::new(gpio, config.acc_pin, config.acc_pull, config.acc_active_low, debounce)?,
+            hold,
+        })
+    }
+
+    fn poll(&mut self, now: Instant) -> Option<bool> {
+        let change = self.input.poll(now);
+
+        // Latch the supply again whenever the key is confirmed on
+        if change == Some(true) {
+            if let Some(hold) = self.hold.as_mut() {
+                hold.set_level(true);
+            }
+        }
+        change
+    }
+}
+
+static SETTINGS: Lazy<Mutex<PowerSettings>> = Lazy::new(|| Mutex::new(settings::load(SETTINGS_NAME)));
+static STATE: Mutex<PowerState> = Mutex::new(PowerState::Running);
+
+fn set_state(app: &AppHandle, state: PowerState) {
+    println!("Power state: {:?}", state);
+    if let Ok(mut current) = STATE.lock() {
+        *current = state.clone();
+    }
+    if let Err(e) = app.emit(POWER_STATE_EVENT, state) {
+        println!("Failed to emit power state: {}", e);
+    }
+}
+
+#[cfg(target_os = "linux")]
+async fn disconnect_bluetooth() {
+    if let Some(address) = super::bluetooth::connected_device_address().await {
+        if let Err(e) = super::bluetooth::disconnect_bluetooth_device(address).await {
+            println!("Failed to disconnect Bluetooth: {}", e);
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn disconnect_bluetooth() {}
+
+#[cfg(target_os = "linux")]
+async fn power_off() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let connection = Connection::system().await?;
+    LoginManagerProxy::new(&connection).await?.power_off(false).await?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn power_off() -> Result<(), Box<dyn Error + Send + Sync>> {
+    Err("Power off not supported on this platform".into())
+}
+
+async fn shut_down() {
+    trip::save();
+    if let Err(e) = settings::sync() {
+        println!("Failed to sync settings: {}", e);
+    }
+    disconnect_bluetooth().await;
+
+    for attempt in 1..=POWER_OFF_ATTEMPTS {
+        match power_off().await {
+            Ok(()) => return,
+            Err(e) => println!("Failed to power off (attempt {}): {}", attempt, e),
+        }
+        tokio::time::sleep(RETRY_DELAY).await;
+    }
+
+    // Staying up with the key off would flatten the battery
+    match tokio::process::Command::new("systemctl").arg("poweroff").status().await {
+        Ok(status) if status.success() => {}
+        Ok(status) => println!("systemctl poweroff failed: {}", status),
+        Err(e) => println!("Failed to run systemctl poweroff: {}", e),
+    }
+}
+
+/// Start watching ACC. Called once from the app setup hook.
+pub fn start(app: AppHandle) {
+    let config = match SETTINGS.lock() {
+        Ok(config) => config.clone(),
+        Err(_) => return,
+    };
+    if !config.enabled {
+        return;
+    }
+
+    std::thread::spawn(move || {
+        let gpio = gpio::provider();
+        let mut sense = loop {
+            match AccSense::new(gpio.as_ref(), &config) {
+                Ok(sense) => break sense,
+                Err(e) => {
+                    println!("Failed to open ACC sense on GPIO{}: {}", config.acc_pin, e);
+                    std::thread::sleep(RETRY_DELAY);
+                }
+            }
+        };
+        let mut timer = ShutdownTimer::new(Duration::from_secs(config.grace_secs));
+
+        loop {
+            let now = Instant::now();
+            let change = match sense.poll(now) {
+                Some(acc_on) => timer.acc_changed(acc_on, now),
+                None => timer.tick(now),
+            };
+
+            if let Some(state) = change {
+                let shutting_down = state == PowerState::ShuttingDown;
+                set_state(&app, state);
+                if shutting_down {
+                    tauri::async_runtime::block_on(shut_down());
+                }
+            }
+
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_power_state() -> Result<PowerState, String> {
+    let state = STATE.lock().map_err(|e| e.to_string())?;
+    Ok(state.clone())
+}
+
+#[tauri::command]
+pub fn get_power_settings() -> Result<PowerSettings, String> {
+    let config = SETTINGS.lock().map_err(|e| e.to_string())?;
+    Ok(config.clone())
+}
+
+/// Pins and timings are read at startup, changes apply after a restart
+#[tauri::command]
+pub fn set_power_settings(config: PowerSettings) -> Result<(), String> {
+    settings::save(SETTINGS_NAME, &config)?;
+    *SETTINGS.lock().map_err(|e| e.to_string())? = config;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::gpio::FakeGpio;
+
+    const GRACE: Duration = Duration::from_secs(30);
+
+    fn at(start: Instant, secs: u64) -> Instant {
+        start + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn shuts_down_after_grace_period() {
+        let start = Instant::now();
+        let mut timer = ShutdownTimer::new(GRACE);
+
+        assert_eq!(timer.acc_changed(true, start), None);
+        assert_eq!(timer.acc_changed(false, at(start, 10)), Some(PowerState::ShutdownPending { seconds: 30 }));
+        assert_eq!(timer.tick(at(start, 39)), None);
+        assert_eq!(timer.tick(at(start, 40)), Some(PowerState::ShuttingDown));
+        assert_eq!(timer.tick(at(start, 41)), None);
+    }
+
+    #[test]
+    fn key_on_during_grace_cancels() {
+        let start = Instant::now();
+        let mut timer = ShutdownTimer::new(GRACE);
+
+        timer.acc_changed(false, start);
+        assert_eq!(timer.acc_changed(true, at(start, 20)), Some(PowerState::Running));
+        assert_eq!(timer.tick(at(start, 60)), None);
+
+        // A second key-off starts a fresh grace period
+        assert_eq!(timer.acc_changed(false, at(start, 100)), Some(PowerState::ShutdownPending { seconds: 30 }));
+        assert_eq!(timer.tick(at(start, 129)), None);
+        assert_eq!(timer.tick(at(start, 130)), Some(PowerState::ShuttingDown));
+    }
+
+    #[test]
+    fn key_on_after_shutdown_started_is_ignored() {
+        let start = Instant::now();
+        let mut timer = ShutdownTimer::new(GRACE);
+
+        timer.acc_changed(false, start);
+        timer.tick(at(start, 30));
+        assert_eq!(timer.acc_changed(true, at(start, 31)), None);
+    }
+
+    #[test]
+    fn hold_pin_is_latched() {
+        let gpio = FakeGpio::default();
+        let config = PowerSettings::default();
+        let mut sense = AccSense::new(&gpio, &config).unwrap();
+        assert_eq!(gpio.level(27), Some(true));
+
+        // Optocoupler pulls the line low with the key on
+        let start = Instant::now();
+        gpio.set_level(27, false);
+        gpio.set_level(config.acc_pin, false);
+        sense.poll(start);
+        assert_eq!(sense.poll(start + Duration::from_millis(500)), Some(true));
+        assert_eq!(gpio.level(27), Some(true));
+
+        gpio.set_level(config.acc_pin, true);
+        sense.poll(start + Duration::from_secs(1));
+        assert_eq!(sense.poll(start + Duration::from_millis(1500)), Some(false));
+    }
+}
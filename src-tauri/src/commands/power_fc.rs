@@ -0,0 +1,464 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serialport::SerialPort;
+use std::error::Error;
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+use tokio::sync::watch;
+
+use super::settings;
+use super::telemetry::{self, Telemetry};
+
+const SETTINGS_NAME: &str = "power_fc";
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+const READ_TIMEOUT: Duration = Duration::from_millis(500);
+
+// Commander port frames are [id, length, payload..., checksum], where the
+// length counts itself, the payload and the checksum
+const ADVANCED_DATA: u8 = 0xF0;
+const ADVANCED_DATA_LEN: usize = 30;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct PowerFcSettings {
+    pub enabled: bool,
+    /// Commander port through a USB serial adapter
+    pub port: String,
+    pub baud_rate: u32,
+    /// Frames per second to request
+    pub sample_hz: u32,
+    /// Append every frame to this file, for replaying later
+    pub record_path: Option<String>,
+    /// Replay a recording through a pty instead of opening `port`
+    pub replay_path: Option<String>,
+}
+
+impl Default for PowerFcSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: "/dev/ttyUSB0".to_string(),
+            baud_rate: 57600,
+            sample_hz: 20,
+            record_path: None,
+            replay_path: None,
+        }
+    }
+}
+
+/// Decoded "advanced data" frame
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct EngineData {
+    pub rpm: u16,
+    /// Manifold pressure relative to atmosphere, negative in vacuum
+    pub boost_kpa: f32,
+    pub map_volts: f32,
+    pub throttle_volts: f32,
+    pub injector_ms: f32,
+    pub injector_duty: f32,
+    pub secondary_injector_ms: f32,
+    pub secondary_injector_duty: f32,
+    /// Degrees BTDC
+    pub leading_ignition: f32,
+    pub trailing_ignition: f32,
+    pub water_temp_c: f32,
+    pub intake_temp_c: f32,
+    pub fuel_temp_c: f32,
+    pub knock: u8,
+    pub battery_volts: f32,
+    pub speed_kmh: u16,
+    pub iscv_duty: f32,
+    pub o2_volts: f32,
+}
+
+impl EngineData {
+    /// Decode the 30 byte advanced data payload. Words are little-endian.
+    pub fn decode(payload: &[u8]) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if payload.len() != ADVANCED_DATA_LEN {
+            return Err(format!(
+                "Advanced data is {} bytes, expected {}",
+                payload.len(),
+                ADVANCED_DATA_LEN
+            )
+            .into());
+        }
+        let word = |offset: usize| u16::from_le_bytes([payload[offset], payload[offset + 1]]);
+        let temperature = |raw: u8| raw as f32 - 80.0;
+        let ignition = |raw: u8| raw as f32 - 25.0;
+
+        let rpm = word(0);
+        let injector_ms = word(8) as f32 * 0.004;
+        let secondary_injector_ms = word(28) as f32 * 0.004;
+        // Each rotor's injectors fire once per eccentric shaft revolution
+        let duty = |ms: f32| (ms * rpm as f32 / 600.0).min(100.0);
+        Ok(EngineData {
+            rpm,
+            // Absolute pressure in mmHg
+            boost_kpa: word(2) as f32 * 0.133322 - 101.325,
+            map_volts: word(4) as f32 * 0.001,
+            throttle_volts: word(6) as f32 * 0.001,
+            injector_ms,
+            injector_duty: duty(injector_ms),
+            leading_ignition: ignition(payload[12]),
+            trailing_ignition: ignition(payload[13]),
+            fuel_temp_c: temperature(payload[14]),
+            water_temp_c: temperature(payload[18]),
+            intake_temp_c: temperature(payload[19]),
+            knock: payload[20],
+            battery_volts: payload[21] as f32 * 0.1,
+            speed_kmh: word(22),
+            iscv_duty: word(24) as f32 * 0.1,
+            o2_volts: payload[26] as f32 * 0.02,
+            secondary_injector_ms,
+            secondary_injector_duty: duty(secondary_injector_ms),
+        })
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    0xFF - bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+/// Build a frame around `payload`
+pub fn encode_frame(id: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![id, (payload.len() + 2) as u8];
+    frame.extend_from_slice(payload);
+    frame.push(checksum(&frame));
+    frame
+}
+
+/// Read one frame, returning its id and payload
+pub fn read_frame(port: &mut dyn Read) -> Result<(u8, Vec<u8>), Box<dyn Error + Send + Sync>> {
+    let mut header = [0u8; 2];
+    port.read_exact(&mut header)?;
+    let [id, len] = header;
+    if len < 2 {
+        return Err(format!("Bad frame length {}", len).into());
+    }
+
+    let mut rest = vec![0u8; len as usize - 1];
+    port.read_exact(&mut rest)?;
+    let Some(received) = rest.pop() else {
+        return Err("Empty frame".into());
+    };
+
+    let expected = checksum(&[&header[..], &rest].concat());
+    if received != expected {
+        return Err(format!("Bad checksum on frame {:02X}: {:02X} != {:02X}", id, received, expected).into());
+    }
+    Ok((id, rest))
+}
+
+/// Every valid frame in a recording, skipping over any garbage between them
+pub fn split_recording(mut data: &[u8]) -> Vec<Vec<u8>> {
+    let mut frames = vec![];
+    while data.len() >= 2 {
+        let len = data[1] as usize + 1;
+        let mut frame = data.get(..len).unwrap_or(data);
+        if len > 2 && frame.len() == len && read_frame(&mut frame).is_ok() {
+            frames.push(data[..len].to_vec());
+            data = &data[len..];
+        } else {
+            data = &data[1..];
+        }
+    }
+    frames
+}
+
+pub struct PowerFc {
+    port: Box<dyn SerialPort>,
+    recording: Option<std::fs::File>,
+}
+
+impl PowerFc {
+    pub fn new(mut port: Box<dyn SerialPort>, record_path: Option<&str>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        port.set_timeout(READ_TIMEOUT)?;
+        let recording = match record_path {
+            Some(path) => Some(OpenOptions::new().create(true).append(true).open(path)?),
+            None => None,
+        };
+        Ok(PowerFc { port, recording })
+    }
+
+    pub fn read_engine_data(&mut self) -> Result<EngineData, Box<dyn Error + Send + Sync>> {
+        self.port.write_all(&encode_frame(ADVANCED_DATA, &[]))?;
+
+        let (id, payload) = read_frame(&mut self.port)?;
+        if id != ADVANCED_DATA {
+            // Out of step, drop whatever else is buffered and try again next time
+            self.port.clear(serialport::ClearBuffer::Input)?;
+            return Err(format!("Expected advanced data, got frame {:02X}", id).into());
+        }
+
+        if let Some(recording) = self.recording.as_mut() {
+            recording.write_all(&encode_frame(id, &payload))?;
+        }
+        EngineData::decode(&payload)
+    }
+}
+
+/// Answer advanced data requests on `port` with recorded frames, in a loop,
+/// like a Power FC would
+pub fn serve_replay(mut port: Box<dyn SerialPort>, frames: Vec<Vec<u8>>) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if frames.is_empty() {
+        return Err("Recording has no frames".into());
+    }
+    // Requests only come in while someone is connected
+    port.set_timeout(Duration::from_secs(3600))?;
+
+    for frame in frames.iter().cycle() {
+        loop {
+            let (id, _) = read_frame(&mut port)?;
+            if id == ADVANCED_DATA {
+                break;
+            }
+        }
+        port.write_all(frame)?;
+    }
+    Ok(())
+}
+
+/// A recording answering on a pty. Kept across reconnects so each one talks
+/// to the same replay instead of leaving another pty and thread behind.
+struct Replay {
+    port: Box<dyn SerialPort>,
+    server: JoinHandle<()>,
+}
+
+impl Replay {
+    fn open(path: &str) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let frames = split_recording(&std::fs::read(path)?);
+        println!("Replaying {} Power FC frames from {}", frames.len(), path);
+        Self::serve(frames)
+    }
+
+    #[cfg(unix)]
+    fn serve(frames: Vec<Vec<u8>>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if frames.is_empty() {
+            return Err("Recording has no frames".into());
+        }
+        let (master, slave) = serialport::TTYPort::pair()?;
+        let server = std::thread::spawn(move || {
+            if let Err(e) = serve_replay(Box::new(master), frames) {
+                println!("Power FC replay stopped: {}", e);
+            }
+        });
+        Ok(Replay {
+            port: Box::new(slave),
+            server,
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn serve(_frames: Vec<Vec<u8>>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Err("Replay needs a pty".into())
+    }
+
+    fn is_running(&self) -> bool {
+        !self.server.is_finished()
+    }
+
+    /// Another handle on the replayed port, without whatever the last connection left unread
+    fn connect(&self) -> Result<Box<dyn SerialPort>, Box<dyn Error + Send + Sync>> {
+        let port = self.port.try_clone()?;
+        port.clear(serialport::ClearBuffer::Input)?;
+        Ok(port)
+    }
+}
+
+fn open(config: &PowerFcSettings, replay: &mut Option<Replay>) -> Result<PowerFc, Box<dyn Error + Send + Sync>> {
+    let port = match &config.replay_path {
+        Some(path) => {
+            // Only start the replay over if it stopped
+            let running = match replay.take() {
+                Some(running) if running.is_running() => running,
+                _ => Replay::open(path)?,
+            };
+            let port = running.connect()?;
+            *replay = Some(running);
+            port
+        }
+        None => serialport::new(&config.port, config.baud_rate).open()?,
+    };
+    PowerFc::new(port, config.record_path.as_deref())
+}
+
+static SETTINGS: Lazy<Mutex<PowerFcSettings>> = Lazy::new(|| Mutex::new(settings::load(SETTINGS_NAME)));
+static ENGINE: Lazy<watch::Sender<Option<EngineData>>> = Lazy::new(|| watch::channel(None).0);
+
+/// Latest reading, None while the ECU isn't talking
+pub fn engine_data() -> Option<EngineData> {
+    ENGINE.borrow().clone()
+}
+
+/// Start polling the Power FC. Called once from the app setup hook.
+pub fn start(app: AppHandle) {
+    let config = match SETTINGS.lock() {
+        Ok(config) => config.clone(),
+        Err(_) => return,
+    };
+    if !config.enabled {
+        return;
+    }
+
+    std::thread::spawn(move || {
+        let interval = Duration::from_secs(1) / config.sample_hz.max(1);
+        let mut replay = None;
+
+        loop {
+            let mut power_fc = match open(&config, &mut replay) {
+                Ok(power_fc) => power_fc,
+                Err(e) => {
+                    println!("Failed to open Power FC on {}: {}", config.port, e);
+                    std::thread::sleep(RETRY_DELAY);
+                    continue;
+                }
+            };
+
+            let mut failures = 0;
+            loop {
+                let started = Instant::now();
+                match power_fc.read_engine_data() {
+                    Ok(data) => {
+                        failures = 0;
+                        ENGINE.send_replace(Some(data.clone()));
+                        telemetry::publish(&app, Telemetry::PowerFc(data));
+                    }
+                    Err(e) => {
+                        println!("Power FC read failed: {}", e);
+                        failures += 1;
+                        // Unplugged or the ECU is off, start over
+                        if failures >= 5 {
+                            ENGINE.send_replace(None);
+                            break;
+                        }
+                    }
+                }
+
+                if let Some(remaining) = interval.checked_sub(started.elapsed()) {
+                    std::thread::sleep(remaining);
+                }
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_engine_data() -> Result<Option<EngineData>, String> {
+    Ok(engine_data())
+}
+
+#[tauri::command]
+pub fn get_power_fc_settings() -> Result<PowerFcSettings, String> {
+    let config = SETTINGS.lock().map_err(|e| e.to_string())?;
+    Ok(config.clone())
+}
+
+/// The port is opened at startup, changes apply after a restart
+#[tauri::command]
+pub fn set_power_fc_settings(config: PowerFcSettings) -> Result<(), String> {
+    settings::save(SETTINGS_NAME, &config)?;
+    *SETTINGS.lock().map_err(|e| e.to_string())? = config;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Idling warm: 850 rpm, light vacuum, 20/20 degrees of timing
+    fn idle_payload() -> Vec<u8> {
+        let mut payload = vec![0u8; ADVANCED_DATA_LEN];
+        payload[0..2].copy_from_slice(&850u16.to_le_bytes());
+        payload[2..4].copy_from_slice(&380u16.to_le_bytes());
+        payload[8..10].copy_from_slice(&625u16.to_le_bytes());
+        payload[12] = 45;
+        payload[13] = 45;
+        payload[18] = 162;
+        payload[19] = 110;
+        payload[20] = 12;
+        payload[21] = 138;
+        payload[26] = 23;
+        payload
+    }
+
+    #[test]
+    fn request_frame() {
+        assert_eq!(encode_frame(ADVANCED_DATA, &[]), vec![0xF0, 0x02, 0x0D]);
+    }
+
+    #[test]
+    fn decodes_advanced_data() {
+        let data = EngineData::decode(&idle_payload()).unwrap();
+
+        assert_eq!(data.rpm, 850);
+        assert!((data.boost_kpa - -50.66).abs() < 0.1);
+        assert!((data.injector_ms - 2.5).abs() < 0.001);
+        assert!((data.injector_duty - 3.54).abs() < 0.01);
+        assert_eq!(data.secondary_injector_duty, 0.0);
+        assert_eq!(data.leading_ignition, 20.0);
+        assert_eq!(data.trailing_ignition, 20.0);
+        assert_eq!(data.water_temp_c, 82.0);
+        assert_eq!(data.intake_temp_c, 30.0);
+        assert_eq!(data.knock, 12);
+        assert!((data.battery_volts - 13.8).abs() < 0.001);
+        assert!((data.o2_volts - 0.46).abs() < 0.001);
+    }
+
+    #[test]
+    fn rejects_bad_checksum() {
+        let mut frame = encode_frame(ADVANCED_DATA, &idle_payload());
+        let last = frame.len() - 1;
+        frame[last] ^= 0x01;
+
+        assert!(read_frame(&mut frame.as_slice()).is_err());
+    }
+
+    #[test]
+    fn recording_skips_garbage() {
+        let frame = encode_frame(ADVANCED_DATA, &idle_payload());
+        let data = [&[0x00, 0x13][..], &frame, &[0xF0], &frame].concat();
+
+        assert_eq!(split_recording(&data), vec![frame.clone(), frame]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn replays_over_pty() {
+        let mut revving = idle_payload();
+        revving[0..2].copy_from_slice(&6500u16.to_le_bytes());
+        let frames = vec![
+            encode_frame(ADVANCED_DATA, &idle_payload()),
+            encode_frame(ADVANCED_DATA, &revving),
+        ];
+
+        let (master, slave) = serialport::TTYPort::pair().unwrap();
+        std::thread::spawn(move || serve_replay(Box::new(master), frames));
+
+        let mut power_fc = PowerFc::new(Box::new(slave), None).unwrap();
+        let rpm: Vec<u16> = (0..3).map(|_| power_fc.read_engine_data().unwrap().rpm).collect();
+        assert_eq!(rpm, vec![850, 6500, 850]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reconnects_reuse_the_replay() {
+        let frames = vec![encode_frame(ADVANCED_DATA, &idle_payload())];
+        // Never read, the running replay is reused
+        let config = PowerFcSettings {
+            replay_path: Some("missing.bin".to_string()),
+            ..Default::default()
+        };
+        let mut replay = Some(Replay::serve(frames).unwrap());
+
+        for _ in 0..3 {
+            let mut power_fc = open(&config, &mut replay).unwrap();
+            assert_eq!(power_fc.read_engine_data().unwrap().rpm, 850);
+        }
+        assert!(replay.unwrap().is_running());
+    }
+}
@@ -0,0 +1,636 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+// Client for the PulseAudio native protocol, which pipewire-pulse serves too.
+// Only the introspection, module and volume commands the audio router needs.
+
+// Asking for an old protocol version keeps every info reply to a fixed set of fields
+const PROTOCOL_VERSION: u32 = 13;
+const COOKIE_LEN: usize = 256;
+// Packets on this channel are commands, anything else is stream audio
+const CONTROL_CHANNEL: u32 = u32::MAX;
+const HEADER_LEN: usize = 20;
+const MAX_PACKET: usize = 16 * 1024 * 1024;
+// Index meaning "none", e.g. the monitor of a source that isn't one
+const INVALID_INDEX: u32 = u32::MAX;
+const VOLUME_NORM: u32 = 0x10000;
+
+const COMMAND_ERROR: u32 = 0;
+const COMMAND_REPLY: u32 = 2;
+const COMMAND_AUTH: u32 = 8;
+const COMMAND_SET_CLIENT_NAME: u32 = 9;
+const COMMAND_GET_SERVER_INFO: u32 = 20;
+const COMMAND_GET_SINK_INFO_LIST: u32 = 22;
+const COMMAND_GET_SOURCE_INFO_LIST: u32 = 24;
+const COMMAND_GET_MODULE_INFO_LIST: u32 = 26;
+const COMMAND_GET_SINK_INPUT_INFO_LIST: u32 = 30;
+const COMMAND_SET_SINK_INPUT_VOLUME: u32 = 37;
+const COMMAND_SET_DEFAULT_SINK: u32 = 44;
+const COMMAND_LOAD_MODULE: u32 = 51;
+const COMMAND_UNLOAD_MODULE: u32 = 52;
+
+/// A sink or source
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    pub index: u32,
+    pub name: String,
+    pub description: Option<String>,
+    /// Sink this source monitors, None for a real capture source
+    pub monitor_of_sink: Option<u32>,
+    pub properties: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    pub index: u32,
+    pub name: String,
+    pub argument: String,
+}
+
+/// A stream playing to a sink
+#[derive(Debug, Clone, PartialEq)]
+pub struct SinkInput {
+    pub index: u32,
+    pub channels: u8,
+    pub properties: BTreeMap<String, String>,
+}
+
+/// One value of a reply. Types the router never reads are only checked and skipped.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    String(Option<String>),
+    U32(u32),
+    Bool(bool),
+    Volume(Vec<u32>),
+    Proplist(BTreeMap<String, String>),
+    Other,
+}
+
+/// Arguments of a request, each value prefixed with its type tag
+#[derive(Default)]
+struct TagStruct(Vec<u8>);
+
+impl TagStruct {
+    fn u32(&mut self, value: u32) -> &mut Self {
+        self.0.push(b'L');
+        self.0.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    fn string(&mut self, value: &str) -> &mut Self {
+        self.0.push(b't');
+        self.0.extend_from_slice(value.as_bytes());
+        self.0.push(0);
+        self
+    }
+
+    fn arbitrary(&mut self, value: &[u8]) -> &mut Self {
+        self.0.push(b'x');
+        self.0.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        self.0.extend_from_slice(value);
+        self
+    }
+
+    fn proplist(&mut self, properties: &[(&str, &str)]) -> &mut Self {
+        self.0.push(b'P');
+        for (key, value) in properties {
+            // Values are NUL terminated strings stored as raw bytes
+            let value = [value.as_bytes(), &[0]].concat();
+            self.string(key).u32(value.len() as u32).arbitrary(&value);
+        }
+        self.0.push(b'N');
+        self
+    }
+
+    fn volume(&mut self, channels: u8, volume: u32) -> &mut Self {
+        self.0.push(b'v');
+        self.0.push(channels);
+        for _ in 0..channels {
+            self.0.extend_from_slice(&volume.to_be_bytes());
+        }
+        self
+    }
+}
+
+struct Parser<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Parser<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.data.len() < len {
+            return Err("Reply from the sound server was cut short".to_string());
+        }
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn be_u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn c_string(&mut self) -> Result<String, String> {
+        let len = self
+            .data
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or("Unterminated string from the sound server")?;
+        let text = String::from_utf8_lossy(self.take(len)?).to_string();
+        self.take(1)?;
+        Ok(text)
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        Ok(match self.byte()? {
+            b't' => Value::String(Some(self.c_string()?)),
+            b'N' => Value::String(None),
+            b'L' => Value::U32(self.be_u32()?),
+            b'1' => Value::Bool(true),
+            b'0' => Value::Bool(false),
+            b'v' => {
+                let channels = self.byte()?;
+                Value::Volume((0..channels).map(|_| self.be_u32()).collect::<Result<_, _>>()?)
+            }
+            b'P' => {
+                let mut properties = BTreeMap::new();
+                while let Value::String(Some(key)) = self.value()? {
+                    let Value::U32(len) = self.value()? else {
+                        return Err("Bad property list from the sound server".to_string());
+                    };
+                    if self.byte()? != b'x' || self.be_u32()? != len {
+                        return Err("Bad property list from the sound server".to_string());
+                    }
+                    let value = self.take(len as usize)?;
+                    let value = value.strip_suffix(&[0]).unwrap_or(value);
+                    properties.insert(key, String::from_utf8_lossy(value).to_string());
+                }
+                Value::Proplist(properties)
+            }
+            b'B' => {
+                self.take(1)?;
+                Value::Other
+            }
+            b'R' | b'r' | b'U' | b'T' => {
+                self.take(8)?;
+                Value::Other
+            }
+            b'V' => {
+                self.take(4)?;
+                Value::Other
+            }
+            // Sample format, channels and rate
+            b'a' => {
+                self.take(6)?;
+                Value::Other
+            }
+            b'm' => {
+                let channels = self.byte()?;
+                self.take(channels as usize)?;
+                Value::Other
+            }
+            b'x' => {
+                let len = self.be_u32()?;
+                self.take(len as usize)?;
+                Value::Other
+            }
+            // Encoding, then a property list
+            b'f' => {
+                self.value()?;
+                self.value()?;
+                Value::Other
+            }
+            tag => return Err(format!("Unknown value type {:?} from the sound server", tag as char)),
+        })
+    }
+}
+
+fn parse(data: &[u8]) -> Result<Vec<Value>, String> {
+    let mut parser = Parser { data };
+    let mut values = Vec::new();
+    while !parser.data.is_empty() {
+        values.push(parser.value()?);
+    }
+    Ok(values)
+}
+
+/// A reply's values, read off in the order the server sent them
+struct Fields(std::vec::IntoIter<Value>);
+
+impl Fields {
+    fn next(&mut self) -> Result<Value, String> {
+        self.0
+            .next()
+            .ok_or_else(|| "Reply from the sound server is missing values".to_string())
+    }
+
+    fn more(&self) -> bool {
+        !self.0.as_slice().is_empty()
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        match self.next()? {
+            Value::U32(value) => Ok(value),
+            other => Err(format!("Expected a number from the sound server, got {:?}", other)),
+        }
+    }
+
+    fn string(&mut self) -> Result<Option<String>, String> {
+        match self.next()? {
+            Value::String(value) => Ok(value),
+            other => Err(format!("Expected a string from the sound server, got {:?}", other)),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        self.string()?
+            .ok_or_else(|| "Sound server sent an object without a name".to_string())
+    }
+
+    fn bool(&mut self) -> Result<bool, String> {
+        match self.next()? {
+            Value::Bool(value) => Ok(value),
+            other => Err(format!("Expected a boolean from the sound server, got {:?}", other)),
+        }
+    }
+
+    fn volume(&mut self) -> Result<Vec<u32>, String> {
+        match self.next()? {
+            Value::Volume(value) => Ok(value),
+            other => Err(format!("Expected a volume from the sound server, got {:?}", other)),
+        }
+    }
+
+    fn proplist(&mut self) -> Result<BTreeMap<String, String>, String> {
+        match self.next()? {
+            Value::Proplist(value) => Ok(value),
+            other => Err(format!("Expected properties from the sound server, got {:?}", other)),
+        }
+    }
+
+    fn skip(&mut self, count: usize) -> Result<(), String> {
+        for _ in 0..count {
+            self.next()?;
+        }
+        Ok(())
+    }
+
+    /// Sinks and sources share a layout, the monitor field pointing opposite ways
+    fn device(&mut self, is_source: bool) -> Result<Device, String> {
+        let index = self.u32()?;
+        let name = self.name()?;
+        let description = self.string()?;
+        // Sample spec, channel map, owner module, volume, mute
+        self.skip(5)?;
+        let monitor = self.u32()?;
+        // Monitor name, latency, driver, flags
+        self.skip(4)?;
+        let properties = self.proplist()?;
+        // Configured latency
+        self.skip(1)?;
+        Ok(Device {
+            index,
+            name,
+            description,
+            monitor_of_sink: Some(monitor).filter(|index| is_source && *index != INVALID_INDEX),
+            properties,
+        })
+    }
+
+    fn module(&mut self) -> Result<Module, String> {
+        let index = self.u32()?;
+        let name = self.name()?;
+        let argument = self.string()?.unwrap_or_default();
+        // Use count
+        self.skip(1)?;
+        // Before protocol 15 there's an obsolete autoload flag, after it a property list
+        match self.next()? {
+            Value::Bool(_) | Value::Proplist(_) => {}
+            other => return Err(format!("Unexpected module field from the sound server: {:?}", other)),
+        }
+        Ok(Module { index, name, argument })
+    }
+
+    fn sink_input(&mut self) -> Result<SinkInput, String> {
+        let index = self.u32()?;
+        // Name, owner module, client, sink, sample spec, channel map
+        self.skip(6)?;
+        let channels = self.volume()?.len() as u8;
+        // Buffer and sink latency, resample method, driver
+        self.skip(4)?;
+        self.bool()?;
+        let properties = self.proplist()?;
+        Ok(SinkInput {
+            index,
+            channels,
+            properties,
+        })
+    }
+
+    fn list<T>(mut self, entry: impl Fn(&mut Self) -> Result<T, String>) -> Result<Vec<T>, String> {
+        let mut entries = Vec::new();
+        while self.more() {
+            entries.push(entry(&mut self)?);
+        }
+        Ok(entries)
+    }
+}
+
+fn error_name(code: u32) -> Option<&'static str> {
+    Some(match code {
+        1 => "access denied",
+        2 => "unknown command",
+        3 => "invalid argument",
+        4 => "entity exists",
+        5 => "no such entity",
+        9 => "bad authentication key",
+        14 => "module initialization failed",
+        17 => "bad protocol version",
+        19 => "not supported",
+        _ => return None,
+    })
+}
+
+/// `$PULSE_SERVER` if it's a local socket, otherwise the per-user socket
+fn socket_path() -> PathBuf {
+    if let Some(path) = std::env::var("PULSE_SERVER")
+        .ok()
+        .and_then(|server| server.strip_prefix("unix:").map(PathBuf::from))
+    {
+        return path;
+    }
+    let runtime_dir = std::env::var("XDG_RUNTIME_DIR").map(PathBuf::from).unwrap_or_else(|_| {
+        use std::os::unix::fs::MetadataExt;
+        let uid = std::fs::metadata("/proc/self").map(|meta| meta.uid()).unwrap_or(1000);
+        PathBuf::from(format!("/run/user/{}", uid))
+    });
+    runtime_dir.join("pulse").join("native")
+}
+
+/// PulseAudio checks the cookie, pipewire-pulse takes any
+fn cookie() -> Vec<u8> {
+    let home = std::env::var("HOME").map(PathBuf::from).unwrap_or_default();
+    let paths = [
+        std::env::var("PULSE_COOKIE").map(PathBuf::from).ok(),
+        Some(home.join(".config/pulse/cookie")),
+        Some(home.join(".pulse-cookie")),
+    ];
+    paths
+        .into_iter()
+        .flatten()
+        .filter_map(|path| std::fs::read(path).ok())
+        .find(|cookie| cookie.len() == COOKIE_LEN)
+        .unwrap_or_else(|| vec![0; COOKIE_LEN])
+}
+
+/// A connection to PulseAudio or pipewire-pulse
+pub struct Connection {
+    stream: UnixStream,
+    tag: u32,
+}
+
+impl Connection {
+    pub async fn connect() -> Result<Self, String> {
+        let path = socket_path();
+        let stream = UnixStream::connect(&path)
+            .await
+            .map_err(|e| format!("Can't reach the sound server at {}: {}", path.display(), e))?;
+        let mut connection = Connection { stream, tag: 0 };
+
+        let mut auth = TagStruct::default();
+        auth.u32(PROTOCOL_VERSION).arbitrary(&cookie());
+        connection.request(COMMAND_AUTH, auth).await?;
+
+        let mut name = TagStruct::default();
+        name.proplist(&[("application.name", "headunit")]);
+        connection.request(COMMAND_SET_CLIENT_NAME, name).await?;
+        Ok(connection)
+    }
+
+    async fn request(&mut self, command: u32, args: TagStruct) -> Result<Fields, String> {
+        self.tag += 1;
+        let mut packet = TagStruct::default();
+        packet.u32(command).u32(self.tag);
+        packet.0.extend(args.0);
+
+        let mut header = [0u8; HEADER_LEN];
+        header[0..4].copy_from_slice(&(packet.0.len() as u32).to_be_bytes());
+        header[4..8].copy_from_slice(&CONTROL_CHANNEL.to_be_bytes());
+        let io_error = |e: std::io::Error| format!("Lost the sound server: {}", e);
+        self.stream.write_all(&header).await.map_err(io_error)?;
+        self.stream.write_all(&packet.0).await.map_err(io_error)?;
+
+        loop {
+            self.stream.read_exact(&mut header).await.map_err(io_error)?;
+            let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+            let channel = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+            if len > MAX_PACKET {
+                return Err(format!("Sound server sent a {} byte packet", len));
+            }
+            let mut payload = vec![0u8; len];
+            self.stream.read_exact(&mut payload).await.map_err(io_error)?;
+            if channel != CONTROL_CHANNEL {
+                continue;
+            }
+
+            let mut fields = Fields(parse(&payload)?.into_iter());
+            let (reply, tag) = (fields.u32()?, fields.u32()?);
+            // Anything else is an event or a reply to an earlier request
+            if tag != self.tag {
+                continue;
+            }
+            return match reply {
+                COMMAND_REPLY => Ok(fields),
+                COMMAND_ERROR => {
+                    let code = fields.u32().unwrap_or(0);
+                    match error_name(code) {
+                        Some(name) => Err(format!("Sound server refused the request: {}", name)),
+                        None => Err(format!("Sound server refused the request with error {}", code)),
+                    }
+                }
+                other => Err(format!("Unexpected command {} from the sound server", other)),
+            };
+        }
+    }
+
+    pub async fn sinks(&mut self) -> Result<Vec<Device>, String> {
+        let reply = self.request(COMMAND_GET_SINK_INFO_LIST, TagStruct::default()).await?;
+        reply.list(|fields| fields.device(false))
+    }
+
+    pub async fn sources(&mut self) -> Result<Vec<Device>, String> {
+        let reply = self.request(COMMAND_GET_SOURCE_INFO_LIST, TagStruct::default()).await?;
+        reply.list(|fields| fields.device(true))
+    }
+
+    pub async fn modules(&mut self) -> Result<Vec<Module>, String> {
+        let reply = self.request(COMMAND_GET_MODULE_INFO_LIST, TagStruct::default()).await?;
+        reply.list(Fields::module)
+    }
+
+    pub async fn sink_inputs(&mut self) -> Result<Vec<SinkInput>, String> {
+        let reply = self
+            .request(COMMAND_GET_SINK_INPUT_INFO_LIST, TagStruct::default())
+            .await?;
+        reply.list(Fields::sink_input)
+    }
+
+    pub async fn default_sink(&mut self) -> Result<Option<String>, String> {
+        let mut reply = self.request(COMMAND_GET_SERVER_INFO, TagStruct::default()).await?;
+        // Package name and version, user name, host name, sample spec
+        reply.skip(5)?;
+        reply.string()
+    }
+
+    pub async fn set_default_sink(&mut self, name: &str) -> Result<(), String> {
+        let mut args = TagStruct::default();
+        args.string(name);
+        self.request(COMMAND_SET_DEFAULT_SINK, args).await?;
+        Ok(())
+    }
+
+    /// Load a module, returning its index
+    pub async fn load_module(&mut self, name: &str, argument: &str) -> Result<u32, String> {
+        let mut args = TagStruct::default();
+        args.string(name).string(argument);
+        self.request(COMMAND_LOAD_MODULE, args).await?.u32()
+    }
+
+    pub async fn unload_module(&mut self, index: u32) -> Result<(), String> {
+        let mut args = TagStruct::default();
+        args.u32(index);
+        self.request(COMMAND_UNLOAD_MODULE, args).await?;
+        Ok(())
+    }
+
+    /// Set every channel of a stream to `percent` of full volume
+    pub async fn set_sink_input_volume(&mut self, input: &SinkInput, percent: u8) -> Result<(), String> {
+        let mut args = TagStruct::default();
+        args.u32(input.index)
+            .volume(input.channels, VOLUME_NORM * percent as u32 / 100);
+        self.request(COMMAND_SET_SINK_INPUT_VOLUME, args).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Values only the server sends, as it would send them
+    fn sample_spec(args: &mut TagStruct) {
+        args.0.extend_from_slice(&[b'a', 5, 2, 0, 0, 0xBB, 0x80]);
+    }
+
+    fn channel_map(args: &mut TagStruct) {
+        args.0.extend_from_slice(&[b'm', 2, 1, 2]);
+    }
+
+    fn usec(args: &mut TagStruct) {
+        args.0.push(b'U');
+        args.0.extend_from_slice(&20_000u64.to_be_bytes());
+    }
+
+    fn source(args: &mut TagStruct, index: u32, name: &str, monitor_of_sink: u32, codec: Option<&str>) {
+        args.u32(index).string(name).string(&format!("{} source", name));
+        sample_spec(args);
+        channel_map(args);
+        args.u32(3).volume(2, VOLUME_NORM);
+        args.0.push(b'0');
+        args.u32(monitor_of_sink);
+        args.0.push(b'N');
+        usec(args);
+        args.string("module-bluez5-device.c").u32(0);
+        match codec {
+            Some(codec) => args.proplist(&[("api.bluez5.codec", codec)]),
+            None => args.proplist(&[]),
+        };
+        usec(args);
+    }
+
+    #[test]
+    fn reads_sources() {
+        let mut reply = TagStruct::default();
+        source(&mut reply, 4, "bluez_input.AA_BB", INVALID_INDEX, Some("aac"));
+        source(&mut reply, 5, "alsa_output.monitor", 1, None);
+
+        let sources = Fields(parse(&reply.0).unwrap().into_iter())
+            .list(|fields| fields.device(true))
+            .unwrap();
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[0].name, "bluez_input.AA_BB");
+        assert_eq!(sources[0].description.as_deref(), Some("bluez_input.AA_BB source"));
+        assert_eq!(sources[0].monitor_of_sink, None);
+        assert_eq!(
+            sources[0].properties.get("api.bluez5.codec").map(String::as_str),
+            Some("aac")
+        );
+        assert_eq!(sources[1].monitor_of_sink, Some(1));
+    }
+
+    #[test]
+    fn reads_modules_and_sink_inputs() {
+        let mut reply = TagStruct::default();
+        reply.u32(12).string("module-loopback").string("source=x").u32(u32::MAX);
+        reply.0.push(b'0');
+        // A newer server sends a property list instead of the autoload flag
+        reply.u32(13).string("module-null-sink");
+        reply.0.push(b'N');
+        reply.u32(u32::MAX).proplist(&[]);
+        let modules = Fields(parse(&reply.0).unwrap().into_iter())
+            .list(Fields::module)
+            .unwrap();
+        assert_eq!(
+            modules,
+            vec![
+                Module {
+                    index: 12,
+                    name: "module-loopback".to_string(),
+                    argument: "source=x".to_string(),
+                },
+                Module {
+                    index: 13,
+                    name: "module-null-sink".to_string(),
+                    argument: String::new(),
+                },
+            ]
+        );
+
+        let mut reply = TagStruct::default();
+        reply.u32(41).string("Loopback").u32(12).u32(7).u32(0);
+        sample_spec(&mut reply);
+        channel_map(&mut reply);
+        reply.volume(2, VOLUME_NORM);
+        usec(&mut reply);
+        usec(&mut reply);
+        reply.string("trivial").string("module-loopback.c");
+        reply.0.push(b'0');
+        reply.proplist(&[("headunit.route", "loopback")]);
+        let inputs = Fields(parse(&reply.0).unwrap().into_iter())
+            .list(Fields::sink_input)
+            .unwrap();
+        assert_eq!(inputs[0].index, 41);
+        assert_eq!(inputs[0].channels, 2);
+        assert_eq!(
+            inputs[0].properties.get("headunit.route").map(String::as_str),
+            Some("loopback")
+        );
+    }
+
+    #[test]
+    fn rejects_short_replies() {
+        let mut reply = TagStruct::default();
+        reply.u32(4).string("bluez_input.AA_BB");
+        assert!(parse(&reply.0[..reply.0.len() - 2]).is_err());
+        assert!(Fields(parse(&reply.0).unwrap().into_iter())
+            .list(|fields| fields.device(true))
+            .is_err());
+    }
+}
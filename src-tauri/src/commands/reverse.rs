@@ -0,0 +1,140 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+
+use super::gpio::{self, Pull, SenseInput};
+use super::{audio_route, camera, display_power, settings};
+
+const SETTINGS_NAME: &str = "reverse";
+const REVERSE_EVENT: &str = "vehicle://reverse";
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Reverse light feed, live while the car is in reverse
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ReverseSettings {
+    pub enabled: bool,
+    /// BCM pin number
+    pub pin: u8,
+    pub pull: Pull,
+    /// An optocoupler pulls the pin low while the reverse lights are on
+    pub active_low: bool,
+    pub debounce_ms: u64,
+    /// Music volume while reversing as a percentage of normal, 0 to mute, None to leave it alone
+    pub duck_percent: Option<u8>,
+    pub show_camera: bool,
+}
+
+impl Default for ReverseSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            pin: 23,
+            pull: Pull::Up,
+            active_low: true,
+            debounce_ms: 100,
+            duck_percent: Some(20),
+            show_camera: true,
+        }
+    }
+}
+
+static SETTINGS: Lazy<Mutex<ReverseSettings>> = Lazy::new(|| Mutex::new(settings::load(SETTINGS_NAME)));
+static REVERSE: AtomicBool = AtomicBool::new(false);
+
+pub fn in_reverse() -> bool {
+    REVERSE.load(Ordering::Relaxed)
+}
+
+fn set_reverse(app: &AppHandle, on: bool, config: &ReverseSettings) {
+    println!("Reverse {}", if on { "engaged" } else { "disengaged" });
+    REVERSE.store(on, Ordering::Relaxed);
+
+    if on {
+        // The camera is no use on a blank screen
+        display_power::wake();
+        if config.show_camera {
+            camera::start();
+        }
+    } else {
+        camera::stop();
+    }
+
+    if config.duck_percent.is_some() {
+        if let Err(e) = audio_route::set_duck(if on { config.duck_percent } else { None }) {
+            println!("Failed to duck audio for reverse: {}", e);
+        }
+    }
+
+    if let Err(e) = app.emit(REVERSE_EVENT, on) {
+        println!("Failed to emit reverse state: {}", e);
+    }
+}
+
+/// Start watching the reverse input. Called once from the app setup hook.
+pub fn start(app: AppHandle) {
+    std::thread::spawn(move || {
+        let gpio = gpio::provider();
+        let mut sense: Option<(ReverseSettings, SenseInput)> = None;
+
+        loop {
+            let config = match SETTINGS.lock() {
+                Ok(config) => config.clone(),
+                Err(_) => return,
+            };
+
+            if !config.enabled {
+                if sense.take().is_some() && in_reverse() {
+                    set_reverse(&app, false, &config);
+                }
+                std::thread::sleep(RETRY_DELAY);
+                continue;
+            }
+
+            if sense.as_ref().map(|(opened, _)| opened != &config).unwrap_or(true) {
+                let debounce = Duration::from_millis(config.debounce_ms);
+                match SenseInput::new(gpio.as_ref(), config.pin, config.pull, config.active_low, debounce) {
+                    Ok(opened) => sense = Some((config.clone(), opened)),
+                    Err(e) => {
+                        println!("Failed to open reverse input on GPIO{}: {}", config.pin, e);
+                        sense = None;
+                        std::thread::sleep(RETRY_DELAY);
+                        continue;
+                    }
+                }
+            }
+
+            if let Some((_, sense)) = sense.as_mut() {
+                if let Some(on) = sense.poll(Instant::now()) {
+                    if on != in_reverse() {
+                        set_reverse(&app, on, &config);
+                    }
+                }
+            }
+
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_reverse_settings() -> Result<ReverseSettings, String> {
+    let config = SETTINGS.lock().map_err(|e| e.to_string())?;
+    Ok(config.clone())
+}
+
+#[tauri::command]
+pub fn set_reverse_settings(config: ReverseSettings) -> Result<(), String> {
+    settings::save(SETTINGS_NAME, &config)?;
+    *SETTINGS.lock().map_err(|e| e.to_string())? = config;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_reverse() -> Result<bool, String> {
+    Ok(in_reverse())
+}
@@ -0,0 +1,74 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+
+/// Directory the headunit keeps its settings in.
+///
+/// `HEADUNIT_CONFIG_DIR` wins if set, otherwise `$XDG_CONFIG_HOME/headunit`
+/// or `~/.config/headunit`.
+pub fn config_dir() -> PathBuf {
+    if let Ok(dir) = std::env::var("HEADUNIT_CONFIG_DIR") {
+        return PathBuf::from(dir);
+    }
+
+    let base = std::env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+            PathBuf::from(home).join(".config")
+        });
+
+    base.join("headunit")
+}
+
+/// Load `<name>.json` from the config dir, falling back to defaults if the
+/// file is missing or unreadable.
+pub fn load<T: DeserializeOwned + Default>(name: &str) -> T {
+    let path = config_dir().join(format!("{}.json", name));
+
+    match fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+            println!("Ignoring invalid settings file {}: {}", path.display(), e);
+            T::default()
+        }),
+        Err(_) => T::default(),
+    }
+}
+
+/// Save `<name>.json` to the config dir.
+///
+/// Writes to a temp file and renames it over the old one so a power cut
+/// mid-write can't leave a half-written file behind.
+pub fn save<T: Serialize>(name: &str, value: &T) -> Result<(), String> {
+    let dir = config_dir();
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+
+    let path = dir.join(format!("{}.json", name));
+    let tmp_path = dir.join(format!("{}.json.tmp", name));
+
+    let contents = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    fs::write(&tmp_path, contents).map_err(|e| e.to_string())?;
+    fs::rename(&tmp_path, &path).map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Flush every settings file, and the directory entries pointing at them, to
+/// disk ahead of a shutdown.
+pub fn sync() -> Result<(), String> {
+    let dir = config_dir();
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(()),
+    };
+
+    for entry in entries.flatten() {
+        fs::File::open(entry.path())
+            .and_then(|file| file.sync_all())
+            .map_err(|e| format!("Failed to sync {}: {}", entry.path().display(), e))?;
+    }
+    fs::File::open(&dir)
+        .and_then(|dir| dir.sync_all())
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,323 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::ws2812::{self, GpioLed, LedStrip, LedType, Rgb};
+use super::{auto_brightness, gpio, pins, settings, telemetry};
+
+const SETTINGS_NAME: &str = "shift_light";
+const FRAME_INTERVAL: Duration = Duration::from_millis(20);
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ShiftLightOutput {
+    /// A single LED, lit from `shift_rpm`. It can only be on or off, so it isn't dimmed at night.
+    Gpio { pin: u8, active_low: bool },
+    /// WS2812 bar on an SPI bus
+    Strip {
+        bus: u8,
+        leds: usize,
+        #[serde(default)]
+        led_type: LedType,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ShiftLightSettings {
+    pub enabled: bool,
+    pub output: ShiftLightOutput,
+    /// Telemetry channel to follow, e.g. `tach.rpm`, None for whichever RPM channel updated last
+    pub rpm_channel: Option<String>,
+    /// First LED lights here
+    pub start_rpm: f32,
+    /// Whole bar lit
+    pub shift_rpm: f32,
+    /// Whole bar flashes
+    pub redline_rpm: f32,
+    /// Bar colours from the first LED to the last, spread evenly along it
+    pub palette: Vec<Rgb>,
+    pub flash_color: Rgb,
+    pub flash_hz: f32,
+    pub brightness: u8,
+    /// Brightness while the UI is in night mode
+    pub night_brightness: u8,
+}
+
+impl Default for ShiftLightSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            // SPI0 is set up without chip selects for it, SPI1 is the lighting strip's
+            output: ShiftLightOutput::Strip {
+                bus: 0,
+                leds: 8,
+                led_type: LedType::Ws2812,
+            },
+            rpm_channel: None,
+            start_rpm: 4500.0,
+            shift_rpm: 6500.0,
+            redline_rpm: 7000.0,
+            palette: vec![
+                Rgb::new(0, 255, 0),
+                Rgb::new(0, 255, 0),
+                Rgb::new(255, 160, 0),
+                Rgb::new(255, 0, 0),
+            ],
+            flash_color: Rgb::new(0, 80, 255),
+            flash_hz: 8.0,
+            brightness: 255,
+            night_brightness: 40,
+        }
+    }
+}
+
+/// Works out the bar for an engine speed and writes it out when it changes
+pub struct ShiftLight {
+    config: ShiftLightSettings,
+    strip: Box<dyn LedStrip>,
+    flash_since: Option<Instant>,
+    last: Option<Vec<Rgb>>,
+}
+
+impl ShiftLight {
+    pub fn new(config: ShiftLightSettings, strip: Box<dyn LedStrip>) -> Self {
+        ShiftLight {
+            config,
+            strip,
+            flash_since: None,
+            last: None,
+        }
+    }
+
+    fn frame(&self, rpm: Option<f32>, now: Instant, night: bool) -> Vec<Rgb> {
+        let leds = self.strip.len();
+        let mut frame = vec![Rgb::OFF; leds];
+        let Some(rpm) = rpm else {
+            return frame;
+        };
+        let brightness = if night {
+            self.config.night_brightness
+        } else {
+            self.config.brightness
+        };
+
+        if let Some(since) = self.flash_since {
+            // Starts lit so the first flash isn't late
+            let half_periods = now.saturating_duration_since(since).as_secs_f32() * self.config.flash_hz * 2.0;
+            if (half_periods as u64).is_multiple_of(2) {
+                frame.fill(self.config.flash_color.dim(brightness));
+            }
+            return frame;
+        }
+
+        let window = (self.config.shift_rpm - self.config.start_rpm).max(1.0);
+        let fraction = ((rpm - self.config.start_rpm) / window).clamp(0.0, 1.0);
+        // The last LED lights exactly at shift_rpm
+        let lit = (fraction * leds as f32 + 1e-3).floor() as usize;
+
+        let palette = &self.config.palette;
+        for (i, led) in frame.iter_mut().enumerate().take(lit) {
+            if !palette.is_empty() {
+                *led = palette[i * palette.len() / leds].dim(brightness);
+            }
+        }
+        frame
+    }
+
+    pub fn update(&mut self, rpm: Option<f32>, now: Instant, night: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if rpm.is_some_and(|rpm| rpm >= self.config.redline_rpm) {
+            self.flash_since.get_or_insert(now);
+        } else {
+            self.flash_since = None;
+        }
+
+        let frame = self.frame(rpm, now, night);
+        if self.last.as_ref() != Some(&frame) {
+            self.strip.write(&frame)?;
+            self.last = Some(frame);
+        }
+        Ok(())
+    }
+}
+
+static SETTINGS: Lazy<Mutex<ShiftLightSettings>> = Lazy::new(|| Mutex::new(settings::load(SETTINGS_NAME)));
+
+fn check_pins(output: &ShiftLightOutput) -> Result<(), String> {
+    match *output {
+        ShiftLightOutput::Gpio { pin, .. } => pins::check("shift light", &[(pin, "shift light")]),
+        ShiftLightOutput::Strip { bus, .. } => pins::check_strip("shift light", bus),
+    }
+}
+
+fn open_output(output: &ShiftLightOutput) -> Result<Box<dyn LedStrip>, Box<dyn Error + Send + Sync>> {
+    check_pins(output)?;
+    match *output {
+        ShiftLightOutput::Gpio { pin, active_low } => {
+            // Start off, which is high for an active low LED
+            let output = gpio::provider().output(pin, active_low)?;
+            Ok(Box::new(GpioLed::new(output, active_low)))
+        }
+        ShiftLightOutput::Strip { bus, leds, led_type } => ws2812::open_strip(bus, leds, led_type),
+    }
+}
+
+/// Start driving the shift light. Called once from the app setup hook.
+pub fn start() {
+    std::thread::spawn(move || {
+        let mut light: Option<(ShiftLightSettings, ShiftLight)> = None;
+
+        loop {
+            let config = match SETTINGS.lock() {
+                Ok(config) => config.clone(),
+                Err(_) => return,
+            };
+
+            if !config.enabled {
+                if let Some((_, mut light)) = light.take() {
+                    let _ = light.update(None, Instant::now(), false);
+                }
+                std::thread::sleep(RETRY_DELAY);
+                continue;
+            }
+
+            if light.as_ref().map(|(opened, _)| opened != &config).unwrap_or(true) {
+                // Turn the old output off before the new one takes over
+                if let Some((_, mut old)) = light.take() {
+                    let _ = old.update(None, Instant::now(), false);
+                }
+                match open_output(&config.output) {
+                    Ok(strip) => light = Some((config.clone(), ShiftLight::new(config.clone(), strip))),
+                    Err(e) => {
+                        println!("Failed to open shift light: {}", e);
+                        std::thread::sleep(RETRY_DELAY);
+                        continue;
+                    }
+                }
+            }
+
+            if let Some((_, light)) = light.as_mut() {
+                let now = Instant::now();
+                let rpm = telemetry::rpm(config.rpm_channel.as_deref(), now);
+                if let Err(e) = light.update(rpm, now, auto_brightness::night_mode()) {
+                    println!("Failed to update shift light: {}", e);
+                }
+            }
+
+            std::thread::sleep(FRAME_INTERVAL);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_shift_light_settings() -> Result<ShiftLightSettings, String> {
+    let config = SETTINGS.lock().map_err(|e| e.to_string())?;
+    Ok(config.clone())
+}
+
+#[tauri::command]
+pub fn set_shift_light_settings(config: ShiftLightSettings) -> Result<(), String> {
+    if config.enabled {
+        check_pins(&config.output)?;
+    }
+    settings::save(SETTINGS_NAME, &config)?;
+    *SETTINGS.lock().map_err(|e| e.to_string())? = config;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::ws2812::MockStrip;
+
+    const GREEN: Rgb = Rgb::new(0, 255, 0);
+    const AMBER: Rgb = Rgb::new(255, 160, 0);
+    const RED: Rgb = Rgb::new(255, 0, 0);
+    const BLUE: Rgb = Rgb::new(0, 80, 255);
+
+    fn light(leds: usize) -> (ShiftLight, MockStrip) {
+        let strip = MockStrip::new(leds);
+        (
+            ShiftLight::new(ShiftLightSettings::default(), Box::new(strip.clone())),
+            strip,
+        )
+    }
+
+    fn lit(frame: &[Rgb]) -> usize {
+        frame.iter().filter(|led| !led.is_off()).count()
+    }
+
+    #[test]
+    fn progressive_bar() {
+        let (mut light, strip) = light(8);
+        let now = Instant::now();
+
+        light.update(Some(4000.0), now, false).unwrap();
+        assert_eq!(lit(&strip.last().unwrap()), 0);
+
+        // Halfway through the window
+        light.update(Some(5500.0), now, false).unwrap();
+        assert_eq!(strip.last().unwrap(), [vec![GREEN; 4], vec![Rgb::OFF; 4]].concat());
+
+        light.update(Some(6500.0), now, false).unwrap();
+        assert_eq!(
+            strip.last().unwrap(),
+            [vec![GREEN; 4], vec![AMBER; 2], vec![RED; 2]].concat()
+        );
+    }
+
+    #[test]
+    fn flashes_at_redline() {
+        let (mut light, strip) = light(8);
+        let start = Instant::now();
+
+        light.update(Some(7100.0), start, false).unwrap();
+        assert_eq!(strip.last().unwrap(), vec![BLUE; 8]);
+
+        // 8 Hz: off for the second half of each 125 ms period
+        light
+            .update(Some(7100.0), start + Duration::from_millis(70), false)
+            .unwrap();
+        assert_eq!(lit(&strip.last().unwrap()), 0);
+        light
+            .update(Some(7100.0), start + Duration::from_millis(130), false)
+            .unwrap();
+        assert_eq!(strip.last().unwrap(), vec![BLUE; 8]);
+
+        // Back under the redline shows the bar again
+        light
+            .update(Some(6600.0), start + Duration::from_millis(140), false)
+            .unwrap();
+        assert_eq!(lit(&strip.last().unwrap()), 8);
+        assert_eq!(strip.last().unwrap()[0], GREEN);
+    }
+
+    #[test]
+    fn dims_at_night_and_only_writes_changes() {
+        let (mut light, strip) = light(8);
+        let now = Instant::now();
+
+        light.update(Some(6500.0), now, true).unwrap();
+        light.update(Some(6500.0), now, true).unwrap();
+        assert_eq!(strip.frames.lock().unwrap().len(), 1);
+        assert_eq!(strip.last().unwrap()[0], GREEN.dim(40));
+
+        // No RPM source turns everything off
+        light.update(None, now, true).unwrap();
+        assert_eq!(lit(&strip.last().unwrap()), 0);
+    }
+
+    #[test]
+    fn single_led_lights_at_shift_point() {
+        let (mut light, strip) = light(1);
+        let now = Instant::now();
+
+        light.update(Some(6400.0), now, false).unwrap();
+        assert_eq!(lit(&strip.last().unwrap()), 0);
+        light.update(Some(6500.0), now, false).unwrap();
+        assert_eq!(lit(&strip.last().unwrap()), 1);
+    }
+}
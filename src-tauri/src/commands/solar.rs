@@ -0,0 +1,96 @@
+// Sun position from the NOAA solar calculator equations, so day/night
+// switching works without a network connection.
+
+const UNIX_EPOCH_JULIAN_DAY: f64 = 2440587.5;
+const J2000_JULIAN_DAY: f64 = 2451545.0;
+
+fn sin_deg(deg: f64) -> f64 {
+    deg.to_radians().sin()
+}
+
+fn cos_deg(deg: f64) -> f64 {
+    deg.to_radians().cos()
+}
+
+/// Solar elevation in degrees above the horizon (negative below it), without
+/// atmospheric refraction, for a unix timestamp and a position in degrees
+/// (north and east positive).
+pub fn solar_elevation(unix_secs: f64, latitude: f64, longitude: f64) -> f64 {
+    let julian_day = unix_secs / 86400.0 + UNIX_EPOCH_JULIAN_DAY;
+    let t = (julian_day - J2000_JULIAN_DAY) / 36525.0;
+
+    let mean_longitude = (280.46646 + t * (36000.76983 + t * 0.0003032)).rem_euclid(360.0);
+    let mean_anomaly = 357.52911 + t * (35999.05029 - 0.0001537 * t);
+    let eccentricity = 0.016708634 - t * (0.000042037 + 0.0000001267 * t);
+
+    let center = sin_deg(mean_anomaly) * (1.914602 - t * (0.004817 + 0.000014 * t))
+        + sin_deg(2.0 * mean_anomaly) * (0.019993 - 0.000101 * t)
+        + sin_deg(3.0 * mean_anomaly) * 0.000289;
+    let omega = 125.04 - 1934.136 * t;
+    let apparent_longitude = mean_longitude + center - 0.00569 - 0.00478 * sin_deg(omega);
+
+    let mean_obliquity = 23.0 + (26.0 + (21.448 - t * (46.815 + t * (0.00059 - t * 0.001813))) / 60.0) / 60.0;
+    let obliquity = mean_obliquity + 0.00256 * cos_deg(omega);
+    let declination = (sin_deg(obliquity) * sin_deg(apparent_longitude)).asin().to_degrees();
+
+    // Equation of time, in minutes
+    let y = (obliquity / 2.0).to_radians().tan().powi(2);
+    let equation_of_time = 4.0
+        * (y * sin_deg(2.0 * mean_longitude) - 2.0 * eccentricity * sin_deg(mean_anomaly)
+            + 4.0 * eccentricity * y * sin_deg(mean_anomaly) * cos_deg(2.0 * mean_longitude)
+            - 0.5 * y * y * sin_deg(4.0 * mean_longitude)
+            - 1.25 * eccentricity * eccentricity * sin_deg(2.0 * mean_anomaly))
+        .to_degrees();
+
+    let utc_minutes = unix_secs.rem_euclid(86400.0) / 60.0;
+    let true_solar_time = (utc_minutes + equation_of_time + 4.0 * longitude).rem_euclid(1440.0);
+    let hour_angle = true_solar_time / 4.0 - 180.0;
+
+    let cos_zenith = sin_deg(latitude) * sin_deg(declination)
+        + cos_deg(latitude) * cos_deg(declination) * cos_deg(hour_angle);
+    90.0 - cos_zenith.clamp(-1.0, 1.0).acos().to_degrees()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-06-20 12:00:00 UTC, the day of the June solstice
+    const SOLSTICE_NOON: f64 = 1718884800.0;
+    // 2024-03-20 12:00:00 UTC, the day of the March equinox
+    const EQUINOX_NOON: f64 = 1710936000.0;
+
+    #[test]
+    fn solstice_noon_and_midnight() {
+        // 90 - 40 + 23.44 at solar noon, mirrored below the horizon at midnight
+        let noon = solar_elevation(SOLSTICE_NOON, 40.0, 0.0);
+        assert!((noon - 73.4).abs() < 0.5, "noon elevation {}", noon);
+
+        let midnight = solar_elevation(SOLSTICE_NOON - 43200.0, 40.0, 0.0);
+        assert!((midnight + 26.6).abs() < 0.5, "midnight elevation {}", midnight);
+    }
+
+    #[test]
+    fn equinox_sun_is_overhead_at_equator() {
+        let elevation = solar_elevation(EQUINOX_NOON, 0.0, 0.0);
+        assert!(elevation > 87.0, "elevation {}", elevation);
+    }
+
+    #[test]
+    fn longitude_shifts_local_noon() {
+        // Noon UTC is early morning 90 degrees west, and evening 90 degrees east
+        let west = solar_elevation(EQUINOX_NOON, 0.0, -90.0);
+        let east = solar_elevation(EQUINOX_NOON, 0.0, 90.0);
+        assert!(west.abs() < 3.0, "west elevation {}", west);
+        assert!(east.abs() < 3.0, "east elevation {}", east);
+
+        let morning = solar_elevation(EQUINOX_NOON + 3.0 * 3600.0, 0.0, -90.0);
+        assert!(morning > 40.0 && morning < 50.0, "morning elevation {}", morning);
+    }
+
+    #[test]
+    fn polar_night() {
+        let elevation = solar_elevation(SOLSTICE_NOON, -80.0, 0.0);
+        assert!(elevation < 0.0, "elevation {}", elevation);
+    }
+}
@@ -0,0 +1,254 @@
+use once_cell::sync::Lazy;
+use rodio::{Decoder, Sink, Source};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock};
+
+use super::{audio, dsp, settings};
+
+const SETTINGS_NAME: &str = "soundboard";
+const AUDIO_EXTENSIONS: [&str; 4] = ["mp3", "wav", "ogg", "flac"];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SoundboardSettings {
+    /// Linear gain per category (1.0 = unchanged)
+    pub category_gain: HashMap<String, f32>,
+    /// Drop the Bluetooth stream volume while a clip is playing
+    pub duck_bluetooth: bool,
+    /// Bluetooth volume (0-100) to duck down to
+    pub duck_volume: u8,
+}
+
+impl Default for SoundboardSettings {
+    fn default() -> Self {
+        Self {
+            category_gain: HashMap::new(),
+            duck_bluetooth: true,
+            duck_volume: 30,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SoundboardCategory {
+    pub name: String,
+    pub clips: Vec<String>,
+    pub gain: f32,
+}
+
+pub struct Soundboard {
+    categories: BTreeMap<String, Vec<String>>,
+    root: PathBuf,
+    settings: SoundboardSettings,
+    sinks: Vec<Sink>,
+}
+
+impl Soundboard {
+    pub fn new() -> Self {
+        Self {
+            categories: BTreeMap::new(),
+            root: PathBuf::new(),
+            settings: settings::load(SETTINGS_NAME),
+            sinks: Vec::new(),
+        }
+    }
+
+    /// Scan `<root>/<category>/*` for playable clips
+    pub fn scan(&mut self, root: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut categories = BTreeMap::new();
+
+        for entry in std::fs::read_dir(root)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+
+            let mut clips: Vec<String> = std::fs::read_dir(entry.path())?
+                .filter_map(|clip| clip.ok())
+                .map(|clip| clip.path())
+                .filter(|path| {
+                    path.extension()
+                        .and_then(|ext| ext.to_str())
+                        .map(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+                        .unwrap_or(false)
+                })
+                .filter_map(|path| path.file_name().map(|name| name.to_string_lossy().to_string()))
+                .collect();
+            clips.sort();
+
+            if !clips.is_empty() {
+                categories.insert(entry.file_name().to_string_lossy().to_string(), clips);
+            }
+        }
+
+        self.root = root.to_path_buf();
+        self.categories = categories;
+        Ok(())
+    }
+
+    pub fn categories(&self) -> Vec<SoundboardCategory> {
+        self.categories
+            .iter()
+            .map(|(name, clips)| SoundboardCategory {
+                name: name.clone(),
+                clips: clips.clone(),
+                gain: self.gain(name),
+            })
+            .collect()
+    }
+
+    fn gain(&self, category: &str) -> f32 {
+        self.settings.category_gain.get(category).copied().unwrap_or(1.0)
+    }
+
+    /// Start a clip on its own sink so it mixes with anything already playing
+    pub fn play(&mut self, category: &str, clip: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // Only play clips found by the scan so the frontend can't hand us arbitrary paths
+        let known = self
+            .categories
+            .get(category)
+            .map(|clips| clips.iter().any(|c| c == clip))
+            .unwrap_or(false);
+        if !known {
+            return Err(format!("Unknown soundboard clip {}/{}", category, clip).into());
+        }
+
+        let file = File::open(self.root.join(category).join(clip))?;
+        let source = Decoder::new(BufReader::new(file))?;
+
+        let handle = audio::output_handle()?;
+        let sink = Sink::try_new(&handle)?;
+        // Gain goes in ahead of the DSP chain so its limiter still catches a loud category
+        let gain = self.gain(category);
+        sink.append(dsp::DspSource::new(source.convert_samples().amplify(gain)));
+
+        self.sinks.retain(|sink| !sink.empty());
+        self.sinks.push(sink);
+        Ok(())
+    }
+
+    pub fn stop_all(&mut self) {
+        for sink in self.sinks.drain(..) {
+            sink.stop();
+        }
+    }
+
+    pub fn is_playing(&mut self) -> bool {
+        self.sinks.retain(|sink| !sink.empty());
+        !self.sinks.is_empty()
+    }
+}
+
+#[cfg(target_os = "linux")]
+async fn duck_bluetooth(level: u8) -> Option<(String, u8)> {
+    use super::{bluetooth, media_player};
+
+    let address = bluetooth::connected_device_address().await?;
+    let volume = media_player::get_volume(&address).await.ok().flatten()?;
+    if volume > level {
+        media_player::set_volume(&address, level).await.ok()?;
+    }
+    Some((address, volume))
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn duck_bluetooth(_level: u8) -> Option<(String, u8)> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+async fn restore_bluetooth(address: &str, volume: u8) {
+    if let Err(e) = super::media_player::set_volume(address, volume).await {
+        println!("Failed to restore Bluetooth volume: {}", e);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn restore_bluetooth(_address: &str, _volume: u8) {}
+
+// Device address and volume to restore once the last clip finishes. Kept apart
+// from the soundboard so clips can start and stop while the phone is answering.
+static DUCKED: Mutex<Option<(String, u8)>> = Mutex::const_new(None);
+
+// Global soundboard instance
+static SOUNDBOARD: Lazy<Arc<RwLock<Soundboard>>> =
+    Lazy::new(|| Arc::new(RwLock::new(Soundboard::new())));
+
+/// Scan the bundled soundboard clips. Called once from the app setup hook.
+pub fn init(root: &Path) {
+    let mut soundboard = SOUNDBOARD.blocking_write();
+    match soundboard.scan(root) {
+        Ok(_) => println!("Loaded {} soundboard categories from {}", soundboard.categories.len(), root.display()),
+        Err(e) => println!("Failed to scan soundboard clips in {}: {}", root.display(), e),
+    }
+
+    // Write every category's gain back out so there's something to edit
+    let Soundboard { categories, settings: soundboard_settings, .. } = &mut *soundboard;
+    for name in categories.keys() {
+        soundboard_settings.category_gain.entry(name.clone()).or_insert(1.0);
+    }
+    if let Err(e) = settings::save(SETTINGS_NAME, soundboard_settings) {
+        println!("Failed to save soundboard settings: {}", e);
+    }
+}
+
+/// Wait for every clip to finish, then put the Bluetooth volume back
+async fn watch_ducking() {
+    loop {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Held until restored so a clip starting now can't duck in between
+        let mut ducked = DUCKED.lock().await;
+        if SOUNDBOARD.write().await.is_playing() {
+            continue;
+        }
+
+        if let Some((address, volume)) = ducked.take() {
+            restore_bluetooth(&address, volume).await;
+        }
+        break;
+    }
+}
+
+#[tauri::command]
+pub async fn list_soundboard() -> Result<Vec<SoundboardCategory>, String> {
+    let soundboard = SOUNDBOARD.read().await;
+    Ok(soundboard.categories())
+}
+
+#[tauri::command]
+pub async fn play_soundboard_clip(category: String, clip: String) -> Result<(), String> {
+    let duck_volume = {
+        let mut soundboard = SOUNDBOARD.write().await;
+        soundboard.play(&category, &clip).map_err(|e| e.to_string())?;
+        let config = &soundboard.settings;
+        config.duck_bluetooth.then_some(config.duck_volume)
+    };
+
+    if let Some(level) = duck_volume {
+        // Keep holding this lock so overlapping clips can't duck twice and
+        // remember the already-ducked volume as the one to restore
+        let mut ducked = DUCKED.lock().await;
+        if ducked.is_none() {
+            *ducked = duck_bluetooth(level).await;
+            if ducked.is_some() {
+                tauri::async_runtime::spawn(watch_ducking());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn stop_all_clips() -> Result<(), String> {
+    let mut soundboard = SOUNDBOARD.write().await;
+    soundboard.stop_all();
+    Ok(())
+}
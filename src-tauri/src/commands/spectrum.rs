@@ -0,0 +1,411 @@
+use once_cell::sync::Lazy;
+use rodio::cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use rodio::cpal::{self, SampleFormat};
+use rodio::{Decoder, Source};
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+
+use super::settings;
+
+pub const BAND_COUNT: usize = 32;
+pub const SPECTRUM_EVENT: &str = "audio://spectrum";
+
+const SETTINGS_NAME: &str = "spectrum";
+const FFT_SIZE: usize = 2048;
+const MIN_FREQ: f32 = 20.0;
+const MAX_FREQ: f32 = 16_000.0;
+// Anything quieter than this shows as an empty bar
+const FLOOR_DB: f32 = -70.0;
+// ~30 Hz, plenty for the visualizer and cheap for the webview
+const EMIT_INTERVAL: Duration = Duration::from_millis(33);
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SpectrumSource {
+    /// Tap a capture device, e.g. a PipeWire monitor or `hw:Loopback,1`.
+    /// Without a name the first monitor/loopback device is used.
+    Capture { device: Option<String> },
+    /// Decode a file in real time instead, for testing without a playback stream
+    File { path: PathBuf },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SpectrumSettings {
+    pub source: SpectrumSource,
+}
+
+impl Default for SpectrumSettings {
+    fn default() -> Self {
+        Self {
+            source: SpectrumSource::Capture { device: None },
+        }
+    }
+}
+
+/// Windowed FFT over the most recent samples, folded into log-spaced bands
+pub struct SpectrumAnalyzer {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    window_gain: f32,
+    bands: Vec<(usize, usize)>,
+    buffer: Vec<Complex<f32>>,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new(sample_rate: u32) -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(FFT_SIZE);
+
+        // Hann window
+        let window: Vec<f32> = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FFT_SIZE as f32).cos())
+            .collect();
+        let window_gain = window.iter().sum::<f32>();
+
+        Self {
+            fft,
+            window,
+            window_gain,
+            bands: band_bins(sample_rate),
+            buffer: vec![Complex::default(); FFT_SIZE],
+        }
+    }
+
+    /// Band levels (0.0-1.0) for the last `FFT_SIZE` mono samples.
+    /// Shorter input is zero-padded at the front.
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        let samples = &samples[samples.len().saturating_sub(FFT_SIZE)..];
+        let offset = FFT_SIZE - samples.len();
+
+        for (i, value) in self.buffer.iter_mut().enumerate() {
+            let sample = if i < offset { 0.0 } else { samples[i - offset] };
+            *value = Complex::new(sample * self.window[i], 0.0);
+        }
+        self.fft.process(&mut self.buffer);
+
+        self.bands
+            .iter()
+            .map(|&(start, end)| {
+                // Peak amplitude in the band, scaled so a full-scale sine reads 1.0
+                let peak = self.buffer[start..end]
+                    .iter()
+                    .map(|bin| bin.norm() * 2.0 / self.window_gain)
+                    .fold(0.0f32, f32::max);
+                let db = 20.0 * peak.max(1e-9).log10();
+                ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0)
+            })
+            .collect()
+    }
+}
+
+/// FFT bin range for each log-spaced band. Every band gets at least one bin
+/// so the low end doesn't show up as dead bars.
+fn band_bins(sample_rate: u32) -> Vec<(usize, usize)> {
+    let nyquist = sample_rate as f32 / 2.0;
+    let max_freq = MAX_FREQ.min(nyquist);
+    let bin_width = sample_rate as f32 / FFT_SIZE as f32;
+    let max_bin = FFT_SIZE / 2;
+
+    (0..BAND_COUNT)
+        .map(|band| {
+            let low = MIN_FREQ * (max_freq / MIN_FREQ).powf(band as f32 / BAND_COUNT as f32);
+            let high = MIN_FREQ * (max_freq / MIN_FREQ).powf((band + 1) as f32 / BAND_COUNT as f32);
+
+            let start = ((low / bin_width).floor() as usize).clamp(1, max_bin - 1);
+            let end = ((high / bin_width).ceil() as usize).clamp(start + 1, max_bin);
+            (start, end)
+        })
+        .collect()
+}
+
+fn downmix(data: &[f32], channels: usize) -> Vec<f32> {
+    data.chunks(channels.max(1))
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect()
+}
+
+fn find_capture_device(host: &cpal::Host, name: Option<&str>) -> Result<cpal::Device, String> {
+    let mut devices = host.input_devices().map_err(|e| e.to_string())?;
+
+    let device = match name {
+        Some(name) => devices.find(|device| device.name().map(|n| n.contains(name)).unwrap_or(false)),
+        None => devices
+            .find(|device| {
+                device
+                    .name()
+                    .map(|n| n.contains("monitor") || n.contains("Loopback"))
+                    .unwrap_or(false)
+            })
+            .or_else(|| host.default_input_device()),
+    };
+
+    device.ok_or_else(|| format!("No capture device found for spectrum analyzer ({:?})", name))
+}
+
+fn open_capture_stream(
+    name: Option<&str>,
+    tx: SyncSender<Vec<f32>>,
+    running: Arc<AtomicBool>,
+) -> Result<(cpal::Stream, u32), String> {
+    let host = cpal::default_host();
+    let device = find_capture_device(&host, name)?;
+    let config = device.default_input_config().map_err(|e| e.to_string())?;
+
+    let channels = config.channels() as usize;
+    let sample_rate = config.sample_rate().0;
+    // An unplugged device never comes back on this stream, so give up on it
+    let err_fn = move |e| {
+        println!("Spectrum capture error: {}", e);
+        if matches!(e, cpal::StreamError::DeviceNotAvailable) {
+            running.store(false, Ordering::Relaxed);
+        }
+    };
+
+    // try_send so a stalled analyzer never blocks the audio callback
+    let stream = match config.sample_format() {
+        SampleFormat::F32 => device.build_input_stream(
+            &config.into(),
+            move |data: &[f32], _: &cpal::InputCallbackInfo| {
+                let _ = tx.try_send(downmix(data, channels));
+            },
+            err_fn,
+            None,
+        ),
+        SampleFormat::I16 => device.build_input_stream(
+            &config.into(),
+            move |data: &[i16], _: &cpal::InputCallbackInfo| {
+                let data: Vec<f32> = data.iter().map(|s| *s as f32 / i16::MAX as f32).collect();
+                let _ = tx.try_send(downmix(&data, channels));
+            },
+            err_fn,
+            None,
+        ),
+        format => return Err(format!("Unsupported capture sample format {:?}", format)),
+    }
+    .map_err(|e| e.to_string())?;
+
+    stream.play().map_err(|e| e.to_string())?;
+    println!("Spectrum analyzer capturing from {}", device.name().unwrap_or_default());
+    Ok((stream, sample_rate))
+}
+
+fn start_capture(name: Option<String>, tx: SyncSender<Vec<f32>>, running: Arc<AtomicBool>) -> Result<u32, String> {
+    let (rate_tx, rate_rx) = mpsc::channel();
+
+    // cpal streams aren't Send, so the stream lives and dies on this thread
+    std::thread::spawn(move || {
+        let stream = match open_capture_stream(name.as_deref(), tx, running.clone()) {
+            Ok((stream, sample_rate)) => {
+                let _ = rate_tx.send(Ok(sample_rate));
+                stream
+            }
+            Err(e) => {
+                let _ = rate_tx.send(Err(e));
+                return;
+            }
+        };
+
+        while running.load(Ordering::Relaxed) {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        drop(stream);
+    });
+
+    rate_rx.recv().map_err(|e| e.to_string())?
+}
+
+fn start_file(path: PathBuf, tx: SyncSender<Vec<f32>>, running: Arc<AtomicBool>) -> Result<u32, String> {
+    let file = File::open(&path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let source = Decoder::new(BufReader::new(file)).map_err(|e| e.to_string())?;
+
+    let sample_rate = source.sample_rate();
+    let channels = source.channels() as usize;
+
+    std::thread::spawn(move || {
+        let samples: Vec<f32> = source.convert_samples().collect();
+        // Feed 10ms at a time so the file plays out in real time, looping forever
+        let chunk_len = (sample_rate as usize / 100 * channels).max(1);
+
+        for chunk in samples.chunks(chunk_len).cycle() {
+            if !running.load(Ordering::Relaxed) || tx.send(downmix(chunk, channels)).is_err() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    });
+
+    Ok(sample_rate)
+}
+
+fn run_analyzer(app: AppHandle, rx: Receiver<Vec<f32>>, sample_rate: u32) {
+    let mut analyzer = SpectrumAnalyzer::new(sample_rate);
+    let mut history: VecDeque<f32> = VecDeque::with_capacity(FFT_SIZE * 2);
+    let mut last_emit = Instant::now();
+
+    // Ends once the source thread hangs up
+    while let Ok(chunk) = rx.recv() {
+        history.extend(chunk);
+        let excess = history.len().saturating_sub(FFT_SIZE);
+        history.drain(..excess);
+
+        if last_emit.elapsed() >= EMIT_INTERVAL {
+            last_emit = Instant::now();
+            let bands = analyzer.process(history.make_contiguous());
+            if let Ok(mut latest) = LATEST.lock() {
+                *latest = Some((bands.clone(), last_emit));
+            }
+            if let Err(e) = app.emit(SPECTRUM_EVENT, bands) {
+                println!("Failed to emit spectrum: {}", e);
+            }
+        }
+    }
+}
+
+// Latest bands and when they were worked out, for the lights that follow the music
+static LATEST: Mutex<Option<(Vec<f32>, Instant)>> = Mutex::new(None);
+
+/// Most recent bands, None unless the analyzer produced them within `max_age`
+pub fn latest_bands(max_age: Duration) -> Option<Vec<f32>> {
+    let latest = LATEST.lock().ok()?;
+    let (bands, at) = latest.as_ref()?;
+    (at.elapsed() <= max_age).then(|| bands.clone())
+}
+
+// Stop flag for the running analyzer, if any
+static RUNNING: Lazy<Mutex<Option<Arc<AtomicBool>>>> = Lazy::new(|| Mutex::new(None));
+
+/// Clears `RUNNING` when the analyzer ends for any reason, so a source that
+/// failed doesn't keep the next start from opening a new one
+struct RunningGuard(Arc<AtomicBool>);
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Relaxed);
+        if let Ok(mut running) = RUNNING.lock() {
+            if running.as_ref().is_some_and(|flag| Arc::ptr_eq(flag, &self.0)) {
+                *running = None;
+            }
+        }
+    }
+}
+
+#[tauri::command]
+pub fn start_spectrum_analyzer(app: AppHandle) -> Result<(), String> {
+    let mut running = RUNNING.lock().map_err(|e| e.to_string())?;
+    if running.is_some() {
+        return Ok(());
+    }
+
+    let spectrum_settings: SpectrumSettings = settings::load(SETTINGS_NAME);
+    let flag = Arc::new(AtomicBool::new(true));
+    let (tx, rx) = mpsc::sync_channel(64);
+
+    let sample_rate = match spectrum_settings.source {
+        SpectrumSource::Capture { device } => start_capture(device, tx, flag.clone())?,
+        SpectrumSource::File { path } => start_file(path, tx, flag.clone())?,
+    };
+    let guard = RunningGuard(flag.clone());
+    std::thread::spawn(move || {
+        let _guard = guard;
+        run_analyzer(app, rx, sample_rate)
+    });
+
+    *running = Some(flag);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn stop_spectrum_analyzer() -> Result<(), String> {
+    let mut running = RUNNING.lock().map_err(|e| e.to_string())?;
+    if let Some(flag) = running.take() {
+        flag.store(false, Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f32, sample_rate: u32, amplitude: f32) -> Vec<f32> {
+        (0..FFT_SIZE)
+            .map(|i| amplitude * (2.0 * std::f32::consts::PI * freq * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    fn loudest_band(bands: &[f32]) -> usize {
+        bands
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, _)| i)
+            .unwrap()
+    }
+
+    #[test]
+    fn bands_are_log_spaced_and_ordered() {
+        let bands = band_bins(48_000);
+        assert_eq!(bands.len(), BAND_COUNT);
+        for (start, end) in &bands {
+            assert!(start < end);
+        }
+        for pair in bands.windows(2) {
+            assert!(pair[0].0 <= pair[1].0);
+        }
+    }
+
+    #[test]
+    fn silence_is_empty() {
+        let mut analyzer = SpectrumAnalyzer::new(48_000);
+        let bands = analyzer.process(&[0.0; FFT_SIZE]);
+        assert!(bands.iter().all(|&level| level == 0.0));
+    }
+
+    #[test]
+    fn sine_lands_in_matching_band() {
+        let sample_rate = 44_100;
+        let bin_width = sample_rate as f32 / FFT_SIZE as f32;
+        let mut analyzer = SpectrumAnalyzer::new(sample_rate);
+
+        for freq in [100.0, 1_000.0, 8_000.0] {
+            let bands = analyzer.process(&sine(freq, sample_rate, 0.5));
+            let (start, end) = band_bins(sample_rate)[loudest_band(&bands)];
+            let bin = (freq / bin_width).round() as usize;
+            assert!(start <= bin && bin < end, "{} Hz landed in bins {}..{}", freq, start, end);
+        }
+    }
+
+    #[test]
+    fn louder_input_reads_higher() {
+        let mut analyzer = SpectrumAnalyzer::new(48_000);
+        let quiet = analyzer.process(&sine(1_000.0, 48_000, 0.01));
+        let loud = analyzer.process(&sine(1_000.0, 48_000, 0.9));
+        let band = loudest_band(&loud);
+        assert!(loud[band] > quiet[band]);
+        assert!(loud[band] <= 1.0);
+    }
+
+    #[test]
+    fn ended_run_clears_only_its_own_flag() {
+        let current = Arc::new(AtomicBool::new(true));
+        *RUNNING.lock().unwrap() = Some(current.clone());
+
+        // A stale run ending doesn't stop the one that replaced it
+        drop(RunningGuard(Arc::new(AtomicBool::new(true))));
+        assert!(RUNNING.lock().unwrap().is_some());
+
+        drop(RunningGuard(current.clone()));
+        assert!(RUNNING.lock().unwrap().is_none());
+        assert!(!current.load(Ordering::Relaxed));
+    }
+}
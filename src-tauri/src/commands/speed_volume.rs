@@ -0,0 +1,207 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::{audio, settings, vehicle};
+
+const SETTINGS_NAME: &str = "speed_volume";
+const UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+// Smaller boost changes aren't worth another amixer call
+const MIN_BOOST_STEP_DB: f32 = 0.25;
+
+/// Speed-compensated volume (GALA) curve
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SpeedVolumeSettings {
+    pub enabled: bool,
+    /// No compensation below this speed (km/h)
+    pub start_speed: f32,
+    /// Gain added per km/h above `start_speed`
+    pub db_per_kmh: f32,
+    pub max_boost_db: f32,
+    /// Speed has to move this far (km/h) before the boost is recalculated
+    pub hysteresis_kmh: f32,
+    /// Time constant for smoothing the speed input
+    pub smoothing_secs: f32,
+}
+
+impl Default for SpeedVolumeSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            start_speed: 30.0,
+            db_per_kmh: 0.08,
+            max_boost_db: 10.0,
+            hysteresis_kmh: 4.0,
+            smoothing_secs: 3.0,
+        }
+    }
+}
+
+impl SpeedVolumeSettings {
+    fn boost_for(&self, speed: f32) -> f32 {
+        ((speed - self.start_speed) * self.db_per_kmh).clamp(0.0, self.max_boost_db)
+    }
+}
+
+#[derive(Default)]
+pub struct SpeedCompensator {
+    smoothed_speed: Option<f32>,
+    applied_speed: f32,
+    boost_db: f32,
+}
+
+impl SpeedCompensator {
+    /// Feed the latest speed. Returns the new boost when it should be applied.
+    pub fn update(&mut self, config: &SpeedVolumeSettings, speed: Option<f32>, dt: Duration) -> Option<f32> {
+        if !config.enabled {
+            // Back to the user's volume, and start afresh once enabled again
+            let boosted = self.boost_db != 0.0;
+            *self = Self::default();
+            return boosted.then_some(0.0);
+        }
+
+        // Hold the current boost through GPS dropouts
+        let speed = speed?;
+
+        let smoothed = match self.smoothed_speed {
+            Some(previous) => {
+                let alpha = 1.0 - (-dt.as_secs_f32() / config.smoothing_secs.max(0.01)).exp();
+                previous + (speed - previous) * alpha
+            }
+            None => speed,
+        };
+        self.smoothed_speed = Some(smoothed);
+
+        if (smoothed - self.applied_speed).abs() < config.hysteresis_kmh {
+            return None;
+        }
+        self.applied_speed = smoothed;
+        self.set_boost(config.boost_for(smoothed))
+    }
+
+    fn set_boost(&mut self, boost_db: f32) -> Option<f32> {
+        if (boost_db - self.boost_db).abs() < MIN_BOOST_STEP_DB && !(boost_db == 0.0 && self.boost_db != 0.0) {
+            return None;
+        }
+        self.boost_db = boost_db;
+        Some(boost_db)
+    }
+}
+
+static SETTINGS: Lazy<Mutex<SpeedVolumeSettings>> = Lazy::new(|| Mutex::new(settings::load(SETTINGS_NAME)));
+
+/// Start tracking vehicle speed and adjusting the system volume
+pub fn start() {
+    tauri::async_runtime::spawn(async {
+        let mut compensator = SpeedCompensator::default();
+        let mut interval = tokio::time::interval(UPDATE_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let config = match SETTINGS.lock() {
+                Ok(config) => config.clone(),
+                Err(_) => continue,
+            };
+
+            if let Some(boost_db) = compensator.update(&config, vehicle::speed_kmh(), UPDATE_INTERVAL) {
+                if let Err(e) = audio::set_speed_boost(boost_db).await {
+                    println!("Failed to apply speed volume boost: {}", e);
+                }
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_speed_volume_settings() -> Result<SpeedVolumeSettings, String> {
+    let config = SETTINGS.lock().map_err(|e| e.to_string())?;
+    Ok(config.clone())
+}
+
+#[tauri::command]
+pub fn set_speed_volume_settings(config: SpeedVolumeSettings) -> Result<(), String> {
+    settings::save(SETTINGS_NAME, &config)?;
+    *SETTINGS.lock().map_err(|e| e.to_string())? = config;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK: Duration = Duration::from_millis(250);
+
+    fn enabled() -> SpeedVolumeSettings {
+        SpeedVolumeSettings {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn boost_curve_is_clamped() {
+        let config = enabled();
+        assert_eq!(config.boost_for(0.0), 0.0);
+        assert_eq!(config.boost_for(30.0), 0.0);
+        assert!((config.boost_for(80.0) - 4.0).abs() < 1e-4);
+        // 0.08 dB a km/h reaches the 10 dB limit at 155 km/h
+        assert_eq!(config.boost_for(155.0), 10.0);
+        assert_eq!(config.boost_for(250.0), 10.0);
+    }
+
+    #[test]
+    fn speed_is_smoothed() {
+        let config = enabled();
+        let mut compensator = SpeedCompensator::default();
+
+        // The first reading is taken as is
+        assert!((compensator.update(&config, Some(80.0), TICK).unwrap() - 4.0).abs() < 1e-4);
+
+        // A sudden jump to 150 only comes through over the smoothing time
+        let first = compensator.update(&config, Some(150.0), TICK).unwrap();
+        assert!(first > 4.0 && first < 5.0);
+        let mut boost = first;
+        for _ in 0..40 {
+            if let Some(new) = compensator.update(&config, Some(150.0), TICK) {
+                boost = new;
+            }
+        }
+        assert!((boost - 9.6).abs() < 0.5);
+    }
+
+    #[test]
+    fn small_changes_are_held() {
+        let config = enabled();
+        let mut compensator = SpeedCompensator::default();
+        compensator.update(&config, Some(100.0), TICK);
+
+        // Wandering within the hysteresis band doesn't touch the volume
+        for speed in [102.0, 98.0, 103.0, 97.0, 101.0] {
+            assert_eq!(compensator.update(&config, Some(speed), TICK), None);
+        }
+        // Nor does losing the GPS fix
+        assert_eq!(compensator.update(&config, None, TICK), None);
+    }
+
+    #[test]
+    fn disabling_drops_the_boost() {
+        let mut config = enabled();
+        let mut compensator = SpeedCompensator::default();
+        assert_eq!(compensator.update(&config, Some(200.0), TICK), Some(10.0));
+        // Already at the limit, going faster changes nothing
+        for _ in 0..20 {
+            assert_eq!(compensator.update(&config, Some(240.0), TICK), None);
+        }
+
+        config.enabled = false;
+        assert_eq!(compensator.update(&config, Some(240.0), TICK), Some(0.0));
+        assert_eq!(compensator.update(&config, Some(240.0), TICK), None);
+
+        // Turning it back on boosts from the first reading, not from where it was left
+        config.enabled = true;
+        assert_eq!(compensator.update(&config, Some(210.0), TICK), Some(10.0));
+    }
+}
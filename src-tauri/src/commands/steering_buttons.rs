@@ -0,0 +1,410 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+use tokio::sync::oneshot;
+
+use super::adc::{self, Adc, AdcSettings};
+use super::physical_controls::{self, ButtonDecoder, ButtonEvent, ControlAction, ControlEvent};
+use super::settings;
+
+const SETTINGS_NAME: &str = "steering_buttons";
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+const DISABLED_INTERVAL: Duration = Duration::from_millis(250);
+const LEARN_TIMEOUT: Duration = Duration::from_secs(15);
+// Readings have to stay this close together for this many polls to be learned
+const LEARN_SPREAD_VOLTS: f32 = 0.03;
+const LEARN_SAMPLES: usize = 20;
+
+/// One button on the ladder and the voltage it pulls the line to
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LadderButton {
+    pub name: String,
+    pub volts: f32,
+    pub press: ControlAction,
+    pub long_press: Option<ControlAction>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct SteeringButtonsSettings {
+    pub enabled: bool,
+    pub adc: AdcSettings,
+    /// ADC channel the ladder is wired to
+    pub channel: u8,
+    /// Line voltage with nothing pressed, pulled up to the ADC reference
+    pub idle_volts: f32,
+    /// How far a reading can be from a button's voltage and still count as that button
+    pub tolerance_volts: f32,
+    /// Filled in with the learn command
+    pub buttons: Vec<LadderButton>,
+    pub debounce_ms: u64,
+    pub long_press_ms: u64,
+    pub poll_ms: u64,
+}
+
+impl Default for SteeringButtonsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            adc: AdcSettings::default(),
+            channel: 0,
+            idle_volts: 3.3,
+            tolerance_volts: 0.1,
+            buttons: vec![],
+            debounce_ms: 30,
+            long_press_ms: 600,
+            poll_ms: 10,
+        }
+    }
+}
+
+impl SteeringButtonsSettings {
+    fn is_idle(&self, volts: f32) -> bool {
+        (volts - self.idle_volts).abs() <= self.tolerance_volts
+    }
+
+    /// Index of the button whose window `volts` falls in, None when idle or between windows
+    pub fn classify(&self, volts: f32) -> Option<usize> {
+        if self.is_idle(volts) {
+            return None;
+        }
+
+        self.buttons
+            .iter()
+            .map(|button| (button.volts - volts).abs())
+            .enumerate()
+            .filter(|&(_, distance)| distance <= self.tolerance_volts)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(index, _)| index)
+    }
+}
+
+/// Turns ladder voltages into button presses
+pub struct Ladder {
+    config: SteeringButtonsSettings,
+    decoders: Vec<ButtonDecoder>,
+}
+
+impl Ladder {
+    pub fn new(config: SteeringButtonsSettings) -> Self {
+        let debounce = Duration::from_millis(config.debounce_ms);
+        let long_press = Duration::from_millis(config.long_press_ms);
+        let decoders = config
+            .buttons
+            .iter()
+            .map(|button| ButtonDecoder::new(debounce, button.long_press.map(|_| long_press)))
+            .collect();
+        Self { config, decoders }
+    }
+
+    pub fn poll(&mut self, volts: f32, now: Instant) -> Vec<ControlEvent> {
+        let pressed = self.config.classify(volts);
+        let mut events = vec![];
+
+        // Each button is debounced on its own, so passing through a neighbour's
+        // window on the way to another level doesn't register
+        for (index, (button, decoder)) in self.config.buttons.iter().zip(self.decoders.iter_mut()).enumerate() {
+            let action = match decoder.update(pressed == Some(index), now) {
+                Some(ButtonEvent::Press) => Some(button.press),
+                Some(ButtonEvent::LongPress) => button.long_press,
+                None => None,
+            };
+            if let Some(action) = action {
+                events.push(ControlEvent {
+                    action,
+                    steps: 1,
+                    source: button.name.clone(),
+                });
+            }
+        }
+        events
+    }
+}
+
+/// Waits for a held button and averages its voltage
+#[derive(Default)]
+pub struct Learner {
+    samples: Vec<f32>,
+}
+
+impl Learner {
+    pub fn sample(&mut self, config: &SteeringButtonsSettings, volts: f32) -> Option<f32> {
+        if config.is_idle(volts) {
+            self.samples.clear();
+            return None;
+        }
+        // Still settling, start again from here
+        if self
+            .samples
+            .first()
+            .is_some_and(|first| (volts - first).abs() > LEARN_SPREAD_VOLTS)
+        {
+            self.samples.clear();
+        }
+
+        self.samples.push(volts);
+        if self.samples.len() < LEARN_SAMPLES {
+            return None;
+        }
+        let mean = self.samples.iter().sum::<f32>() / self.samples.len() as f32;
+        self.samples.clear();
+        Some(mean)
+    }
+
+    pub fn reset(&mut self) {
+        self.samples.clear();
+    }
+}
+
+static SETTINGS: Lazy<Mutex<SteeringButtonsSettings>> = Lazy::new(|| Mutex::new(settings::load(SETTINGS_NAME)));
+// Set while the learn command is waiting for a press
+static LEARN: Mutex<Option<oneshot::Sender<f32>>> = Mutex::new(None);
+
+fn learning() -> bool {
+    LEARN.lock().map(|learn| learn.is_some()).unwrap_or(false)
+}
+
+fn open(config: &SteeringButtonsSettings) -> Result<Box<dyn Adc>, Box<dyn Error + Send + Sync>> {
+    if config.channel >= config.adc.model.channels() {
+        return Err(format!("{:?} has no channel {}", config.adc.model, config.channel).into());
+    }
+    adc::open_adc(&config.adc)
+}
+
+/// Start reading the ladder. Called once from the app setup hook.
+pub fn start(app: AppHandle) {
+    std::thread::spawn(move || {
+        let mut adc: Option<(AdcSettings, Box<dyn Adc>)> = None;
+        let mut ladder: Option<Ladder> = None;
+        let mut learner = Learner::default();
+
+        loop {
+            let config = match SETTINGS.lock() {
+                Ok(config) => config.clone(),
+                Err(_) => return,
+            };
+            let learning = learning();
+
+            if !config.enabled && !learning {
+                adc = None;
+                std::thread::sleep(DISABLED_INTERVAL);
+                continue;
+            }
+
+            if adc.as_ref().map(|(opened, _)| opened != &config.adc).unwrap_or(true) {
+                match open(&config) {
+                    Ok(opened) => adc = Some((config.adc.clone(), opened)),
+                    Err(e) => {
+                        println!("Failed to open steering button ADC: {}", e);
+                        adc = None;
+                        std::thread::sleep(RETRY_DELAY);
+                        continue;
+                    }
+                }
+            }
+            let Some((_, reader)) = adc.as_mut() else { continue };
+
+            let volts = match reader.read_volts(config.channel) {
+                Ok(volts) => volts,
+                Err(e) => {
+                    println!("Failed to read steering button ADC: {}", e);
+                    adc = None;
+                    std::thread::sleep(RETRY_DELAY);
+                    continue;
+                }
+            };
+
+            if learning {
+                if let Some(volts) = learner.sample(&config, volts) {
+                    if let Some(learn) = LEARN.lock().ok().and_then(|mut learn| learn.take()) {
+                        let _ = learn.send(volts);
+                    }
+                }
+            } else {
+                learner.reset();
+                if ladder.as_ref().map(|ladder| ladder.config != config).unwrap_or(true) {
+                    ladder = Some(Ladder::new(config.clone()));
+                }
+                if let Some(ladder) = ladder.as_mut() {
+                    for event in ladder.poll(volts, Instant::now()) {
+                        physical_controls::dispatch(&app, event);
+                    }
+                }
+            }
+
+            std::thread::sleep(Duration::from_millis(config.poll_ms));
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_steering_buttons_settings() -> Result<SteeringButtonsSettings, String> {
+    let config = SETTINGS.lock().map_err(|e| e.to_string())?;
+    Ok(config.clone())
+}
+
+#[tauri::command]
+pub fn set_steering_buttons_settings(config: SteeringButtonsSettings) -> Result<(), String> {
+    settings::save(SETTINGS_NAME, &config)?;
+    *SETTINGS.lock().map_err(|e| e.to_string())? = config;
+    Ok(())
+}
+
+/// Wait for a button to be held and record its voltage under `name`,
+/// replacing any button already learned with that name
+#[tauri::command]
+pub async fn learn_steering_button(
+    name: String,
+    press: ControlAction,
+    long_press: Option<ControlAction>,
+) -> Result<LadderButton, String> {
+    let (sender, receiver) = oneshot::channel();
+    *LEARN.lock().map_err(|e| e.to_string())? = Some(sender);
+
+    let volts = match tokio::time::timeout(LEARN_TIMEOUT, receiver).await {
+        Ok(Ok(volts)) => volts,
+        _ => {
+            LEARN.lock().map_err(|e| e.to_string())?.take();
+            return Err("No button press detected".to_string());
+        }
+    };
+
+    let mut config = SETTINGS.lock().map_err(|e| e.to_string())?.clone();
+    if let Some(other) = config
+        .buttons
+        .iter()
+        .find(|button| button.name != name && (button.volts - volts).abs() <= config.tolerance_volts * 2.0)
+    {
+        return Err(format!(
+            "{:.2} V is too close to {} at {:.2} V",
+            volts, other.name, other.volts
+        ));
+    }
+
+    let button = LadderButton {
+        name,
+        volts,
+        press,
+        long_press,
+    };
+    match config.buttons.iter_mut().find(|existing| existing.name == button.name) {
+        Some(existing) => *existing = button.clone(),
+        None => config.buttons.push(button.clone()),
+    }
+
+    settings::save(SETTINGS_NAME, &config)?;
+    *SETTINGS.lock().map_err(|e| e.to_string())? = config;
+    println!("Learned steering button {} at {:.2} V", button.name, button.volts);
+    Ok(button)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(start: Instant, ms: u64) -> Instant {
+        start + Duration::from_millis(ms)
+    }
+
+    fn button(name: &str, volts: f32, press: ControlAction) -> LadderButton {
+        LadderButton {
+            name: name.to_string(),
+            volts,
+            press,
+            long_press: None,
+        }
+    }
+
+    // Typical four-button ladder with a 3.3 V pull-up
+    fn ladder_settings() -> SteeringButtonsSettings {
+        SteeringButtonsSettings {
+            buttons: vec![
+                button("vol+", 0.0, ControlAction::VolumeUp),
+                button("vol-", 0.8, ControlAction::VolumeDown),
+                button("next", 1.6, ControlAction::NextTrack),
+                LadderButton {
+                    long_press: Some(ControlAction::Home),
+                    ..button("mode", 2.4, ControlAction::PlayPause)
+                },
+            ],
+            ..SteeringButtonsSettings::default()
+        }
+    }
+
+    #[test]
+    fn classifies_voltage_windows() {
+        let config = ladder_settings();
+
+        assert_eq!(config.classify(3.3), None);
+        assert_eq!(config.classify(3.25), None);
+        assert_eq!(config.classify(0.05), Some(0));
+        assert_eq!(config.classify(0.75), Some(1));
+        assert_eq!(config.classify(1.68), Some(2));
+        assert_eq!(config.classify(2.45), Some(3));
+        // Between windows
+        assert_eq!(config.classify(1.2), None);
+    }
+
+    #[test]
+    fn presses_map_to_actions() {
+        let mut ladder = Ladder::new(ladder_settings());
+        let start = Instant::now();
+
+        ladder.poll(3.3, at(start, 0));
+        ladder.poll(3.3, at(start, 30));
+        assert!(ladder.poll(1.6, at(start, 100)).is_empty());
+        let events = ladder.poll(1.61, at(start, 130));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].action, ControlAction::NextTrack);
+        assert_eq!(events[0].source, "next");
+
+        // Held, no repeat
+        assert!(ladder.poll(1.6, at(start, 500)).is_empty());
+    }
+
+    #[test]
+    fn passing_through_other_windows_is_ignored() {
+        let mut ladder = Ladder::new(ladder_settings());
+        let start = Instant::now();
+        ladder.poll(3.3, at(start, 0));
+        ladder.poll(3.3, at(start, 30));
+
+        // The line falls through "mode" and "next" on its way down to "vol-"
+        let mut actions = vec![];
+        for (ms, volts) in [(100, 2.4), (110, 1.6), (120, 0.8), (150, 0.8), (200, 3.3), (240, 3.3)] {
+            actions.extend(ladder.poll(volts, at(start, ms)).into_iter().map(|event| event.action));
+        }
+        assert_eq!(actions, vec![ControlAction::VolumeDown]);
+    }
+
+    #[test]
+    fn long_press_on_ladder_button() {
+        let mut ladder = Ladder::new(ladder_settings());
+        let start = Instant::now();
+        ladder.poll(3.3, at(start, 0));
+        ladder.poll(3.3, at(start, 30));
+
+        ladder.poll(2.4, at(start, 100));
+        assert!(ladder.poll(2.4, at(start, 130)).is_empty());
+        let events = ladder.poll(2.4, at(start, 730));
+        assert_eq!(events[0].action, ControlAction::Home);
+    }
+
+    #[test]
+    fn learner_waits_for_steady_reading() {
+        let config = SteeringButtonsSettings::default();
+        let mut learner = Learner::default();
+
+        assert_eq!(learner.sample(&config, 3.3), None);
+        // Line still falling
+        assert_eq!(learner.sample(&config, 2.0), None);
+        for _ in 0..LEARN_SAMPLES - 1 {
+            assert_eq!(learner.sample(&config, 1.21), None);
+        }
+        let learned = learner.sample(&config, 1.19).unwrap();
+        assert!((learned - 1.209).abs() < 0.001);
+    }
+}
@@ -0,0 +1,263 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+
+use super::gpio::{self, DigitalInput, Edge, Pull};
+use super::telemetry::{self, Telemetry};
+use super::{pins, settings};
+
+const SETTINGS_NAME: &str = "tach";
+const PUBLISH_INTERVAL: Duration = Duration::from_millis(50);
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Tach signal after conditioning (optocoupler or comparator) down to 3.3 V
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct TachSettings {
+    pub enabled: bool,
+    /// BCM pin number
+    pub pin: u8,
+    pub pull: Pull,
+    /// Edge counted as one pulse
+    pub edge: Edge,
+    /// Two for the 13B, one pulse per rotor per revolution
+    pub pulses_per_rev: f32,
+    /// Edges closer together than this engine speed allows are treated as noise
+    pub max_rpm: f32,
+    /// Pulse intervals averaged per reading
+    pub average_pulses: usize,
+    /// Report 0 RPM when no pulse arrives for this long
+    pub timeout_ms: u64,
+}
+
+impl Default for TachSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            pin: 24,
+            pull: Pull::Up,
+            edge: Edge::Falling,
+            pulses_per_rev: 2.0,
+            max_rpm: 10_000.0,
+            average_pulses: 4,
+            timeout_ms: 500,
+        }
+    }
+}
+
+/// Times pulse edges and turns the intervals into RPM
+pub struct PulseTimer {
+    pulses_per_rev: f32,
+    min_interval: Duration,
+    timeout: Duration,
+    average_pulses: usize,
+    last_edge: Option<Instant>,
+    intervals: VecDeque<Duration>,
+}
+
+impl PulseTimer {
+    pub fn new(config: &TachSettings) -> Self {
+        let pulses_per_rev = config.pulses_per_rev.max(0.1);
+        // Shortest real interval is at max_rpm, allow a little over it
+        let min_interval = Duration::from_secs_f32(60.0 / (config.max_rpm.max(1.0) * 1.2 * pulses_per_rev));
+
+        PulseTimer {
+            pulses_per_rev,
+            min_interval,
+            timeout: Duration::from_millis(config.timeout_ms),
+            average_pulses: config.average_pulses.max(1),
+            last_edge: None,
+            intervals: VecDeque::new(),
+        }
+    }
+
+    pub fn pulse(&mut self, now: Instant) {
+        let Some(last) = self.last_edge else {
+            self.last_edge = Some(now);
+            return;
+        };
+
+        let interval = now.saturating_duration_since(last);
+        if interval < self.min_interval {
+            // Ringing on the coil signal, wait for the next real edge
+            return;
+        }
+
+        self.last_edge = Some(now);
+        if interval > self.timeout {
+            // First pulse after the engine stopped, there's no speed to measure yet
+            self.intervals.clear();
+            return;
+        }
+
+        self.intervals.push_back(interval);
+        while self.intervals.len() > self.average_pulses {
+            self.intervals.pop_front();
+        }
+    }
+
+    pub fn rpm(&self, now: Instant) -> f32 {
+        let Some(last) = self.last_edge else {
+            return 0.0;
+        };
+        let since_last = now.saturating_duration_since(last);
+        if since_last > self.timeout || self.intervals.is_empty() {
+            return 0.0;
+        }
+
+        let average = self.intervals.iter().sum::<Duration>() / self.intervals.len() as u32;
+        // A pulse that's overdue means the engine is slowing, so don't hold the old speed
+        let interval = average.max(since_last);
+        60.0 / (interval.as_secs_f32() * self.pulses_per_rev)
+    }
+}
+
+static SETTINGS: Lazy<Mutex<TachSettings>> = Lazy::new(|| Mutex::new(settings::load(SETTINGS_NAME)));
+static RPM: Mutex<Option<f32>> = Mutex::new(None);
+
+/// Current engine speed, None while the tach input isn't running
+pub fn rpm() -> Option<f32> {
+    RPM.lock().ok().and_then(|rpm| *rpm)
+}
+
+fn set_rpm(rpm: Option<f32>) {
+    if let Ok(mut latest) = RPM.lock() {
+        *latest = rpm;
+    }
+}
+
+fn pin_claim(config: &TachSettings) -> [(u8, &'static str); 1] {
+    [(config.pin, "tachometer")]
+}
+
+/// Time edges on the tach pin from the GPIO interrupt thread. Edges stop being timed when the pin is dropped.
+fn watch_edges(
+    config: &TachSettings,
+    timer: Arc<Mutex<PulseTimer>>,
+) -> Result<Box<dyn DigitalInput>, Box<dyn Error + Send + Sync>> {
+    pins::check("tachometer", &pin_claim(config))?;
+    let on_edge = Box::new(move || {
+        let now = Instant::now();
+        if let Ok(mut timer) = timer.lock() {
+            timer.pulse(now);
+        }
+    });
+    gpio::provider().watch_edges(config.pin, config.pull, config.edge, on_edge)
+}
+
+/// Start timing the tach signal. Called once from the app setup hook.
+pub fn start(app: AppHandle) {
+    std::thread::spawn(move || loop {
+        let config = match SETTINGS.lock() {
+            Ok(config) => config.clone(),
+            Err(_) => return,
+        };
+        if !config.enabled {
+            set_rpm(None);
+            std::thread::sleep(RETRY_DELAY);
+            continue;
+        }
+
+        let timer = Arc::new(Mutex::new(PulseTimer::new(&config)));
+        let _pin = match watch_edges(&config, timer.clone()) {
+            Ok(pin) => pin,
+            Err(e) => {
+                println!("Failed to watch tach on GPIO{}: {}", config.pin, e);
+                std::thread::sleep(RETRY_DELAY);
+                continue;
+            }
+        };
+
+        // Keep the pin, and with it the interrupt, until the settings change
+        while SETTINGS.lock().map(|current| *current == config).unwrap_or(false) {
+            let rpm = match timer.lock() {
+                Ok(timer) => timer.rpm(Instant::now()),
+                Err(_) => return,
+            };
+            set_rpm(Some(rpm));
+            telemetry::publish(&app, Telemetry::Tach { rpm });
+            std::thread::sleep(PUBLISH_INTERVAL);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_tach_rpm() -> Result<Option<f32>, String> {
+    Ok(rpm())
+}
+
+#[tauri::command]
+pub fn get_tach_settings() -> Result<TachSettings, String> {
+    let config = SETTINGS.lock().map_err(|e| e.to_string())?;
+    Ok(config.clone())
+}
+
+#[tauri::command]
+pub fn set_tach_settings(config: TachSettings) -> Result<(), String> {
+    if config.enabled {
+        pins::check("tachometer", &pin_claim(&config))?;
+    }
+    settings::save(SETTINGS_NAME, &config)?;
+    *SETTINGS.lock().map_err(|e| e.to_string())? = config;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timer() -> PulseTimer {
+        PulseTimer::new(&TachSettings::default())
+    }
+
+    #[test]
+    fn two_pulses_per_rev() {
+        let mut timer = timer();
+        let start = Instant::now();
+        // 3000 RPM is 50 rev/s, 100 pulses/s
+        for i in 0..5 {
+            timer.pulse(start + Duration::from_millis(10 * i));
+        }
+        let rpm = timer.rpm(start + Duration::from_millis(45));
+        assert!((rpm - 3000.0).abs() < 1.0, "{}", rpm);
+    }
+
+    #[test]
+    fn rejects_glitches() {
+        let mut timer = timer();
+        let start = Instant::now();
+        for i in 0..5 {
+            let edge = start + Duration::from_millis(10 * i);
+            timer.pulse(edge);
+            // Ringing 200 us after each edge, far past 10000 RPM
+            timer.pulse(edge + Duration::from_micros(200));
+        }
+        let rpm = timer.rpm(start + Duration::from_millis(45));
+        assert!((rpm - 3000.0).abs() < 1.0, "{}", rpm);
+    }
+
+    #[test]
+    fn drops_to_zero_after_timeout() {
+        let mut timer = timer();
+        let start = Instant::now();
+        assert_eq!(timer.rpm(start), 0.0);
+
+        timer.pulse(start);
+        timer.pulse(start + Duration::from_millis(10));
+        assert!(timer.rpm(start + Duration::from_millis(15)) > 0.0);
+
+        // Slowing down while the next pulse is overdue
+        let slowing = timer.rpm(start + Duration::from_millis(40));
+        assert!((slowing - 1000.0).abs() < 1.0, "{}", slowing);
+
+        assert_eq!(timer.rpm(start + Duration::from_millis(600)), 0.0);
+
+        // Restarting doesn't average in the time spent stopped
+        timer.pulse(start + Duration::from_secs(2));
+        assert_eq!(timer.rpm(start + Duration::from_secs(2)), 0.0);
+    }
+}
@@ -0,0 +1,98 @@
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+
+use super::alarms;
+use super::analog_sensors::SensorReading;
+use super::power_fc::EngineData;
+use super::wideband::AfrReading;
+
+const TELEMETRY_EVENT: &str = "engine://data";
+// A channel counts as gone if its source goes quiet for this long
+const CHANNEL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Everything published on the telemetry channel, tagged with where it came from
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum Telemetry {
+    PowerFc(EngineData),
+    Wideband(AfrReading),
+    /// One reading per configured analog sender, polled together
+    Sensors {
+        readings: Vec<SensorReading>,
+    },
+    /// Engine speed timed from the coil signal
+    Tach {
+        rpm: f32,
+    },
+}
+
+impl Telemetry {
+    /// Numeric values by channel name, `<source>.<field>`, e.g. `power_fc.water_temp_c` or `sensors.oil_pressure`
+    pub fn channels(&self) -> Vec<(String, f32)> {
+        match self {
+            Telemetry::PowerFc(data) => numeric_fields("power_fc", data),
+            Telemetry::Wideband(reading) => numeric_fields("wideband", reading),
+            Telemetry::Sensors { readings } => readings
+                .iter()
+                .filter_map(|reading| Some((format!("sensors.{}", reading.name), reading.value?)))
+                .collect(),
+            Telemetry::Tach { rpm } => vec![("tach.rpm".to_string(), *rpm)],
+        }
+    }
+}
+
+fn numeric_fields<T: Serialize>(source: &str, value: &T) -> Vec<(String, f32)> {
+    let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(value) else {
+        return vec![];
+    };
+    fields
+        .iter()
+        .filter_map(|(name, value)| Some((format!("{}.{}", source, name), value.as_f64()? as f32)))
+        .collect()
+}
+
+// Latest value of every channel, for the outputs that follow the data stream
+static LATEST: Lazy<Mutex<HashMap<String, (f32, Instant)>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Latest value of `channel`, None once its source has gone quiet
+pub fn channel(channel: &str, now: Instant) -> Option<f32> {
+    let latest = LATEST.lock().ok()?;
+    let (value, at) = latest.get(channel).copied()?;
+    (now.saturating_duration_since(at) <= CHANNEL_TIMEOUT).then_some(value)
+}
+
+/// Engine speed from `channel`, e.g. `tach.rpm`, or from whichever RPM channel updated last
+pub fn rpm(channel: Option<&str>, now: Instant) -> Option<f32> {
+    if let Some(name) = channel {
+        return self::channel(name, now);
+    }
+    let latest = LATEST.lock().ok()?;
+    let (value, at) = latest
+        .iter()
+        .filter(|(name, _)| name.ends_with(".rpm"))
+        .map(|(_, latest)| *latest)
+        .max_by_key(|(_, at)| *at)?;
+    (now.saturating_duration_since(at) <= CHANNEL_TIMEOUT).then_some(value)
+}
+
+fn record(channels: &[(String, f32)]) {
+    let now = Instant::now();
+    if let Ok(mut latest) = LATEST.lock() {
+        for (name, value) in channels {
+            latest.insert(name.clone(), (*value, now));
+        }
+    }
+}
+
+pub fn publish(app: &AppHandle, telemetry: Telemetry) {
+    let channels = telemetry.channels();
+    record(&channels);
+    alarms::observe(app, &channels);
+    if let Err(e) = app.emit(TELEMETRY_EVENT, telemetry) {
+        println!("Failed to emit telemetry: {}", e);
+    }
+}
@@ -0,0 +1,424 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter};
+
+use super::{settings, telemetry, vehicle};
+
+const SETTINGS_NAME: &str = "trip";
+// Counters live in their own file so saving them doesn't rewrite the settings
+const TRIPS_NAME: &str = "trips";
+const UPDATE_EVENT: &str = "trip://update";
+const TICK: Duration = Duration::from_millis(250);
+const EMIT_INTERVAL: Duration = Duration::from_secs(1);
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+// Longer gaps, e.g. a suspend, aren't counted
+const MAX_STEP: Duration = Duration::from_secs(2);
+// GPS speed wanders a little while parked, so slower than this counts as
+// stopped for distance and moving time
+const MOVING_KMH: f32 = 2.0;
+const RUNNING_RPM: f32 = 300.0;
+const KM_PER_MILE: f64 = 1.609344;
+const LITRES_PER_GALLON: f64 = 3.785411784;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct TripSettings {
+    /// Telemetry channel to take speed from, e.g. `power_fc.speed_kmh` for the VSS, None for the vehicle speed (GPS)
+    pub speed_channel: Option<String>,
+    /// None for whichever RPM channel updated last
+    pub rpm_channel: Option<String>,
+    pub primary_duty_channel: String,
+    pub secondary_duty_channel: Option<String>,
+    /// Flow of one injector held open, cc/min
+    pub primary_cc_min: f32,
+    pub secondary_cc_min: f32,
+    /// Injectors per stage, one per rotor
+    pub injectors_per_stage: u8,
+}
+
+impl Default for TripSettings {
+    fn default() -> Self {
+        Self {
+            speed_channel: None,
+            rpm_channel: None,
+            primary_duty_channel: "power_fc.injector_duty".to_string(),
+            secondary_duty_channel: Some("power_fc.secondary_injector_duty".to_string()),
+            // Stock S5 turbo injectors
+            primary_cc_min: 550.0,
+            secondary_cc_min: 850.0,
+            injectors_per_stage: 2,
+        }
+    }
+}
+
+impl TripSettings {
+    /// Litres per hour for injector duties in percent
+    fn fuel_lph(&self, primary_duty: f32, secondary_duty: Option<f32>) -> f32 {
+        let cc_min =
+            primary_duty / 100.0 * self.primary_cc_min + secondary_duty.unwrap_or(0.0) / 100.0 * self.secondary_cc_min;
+        cc_min * self.injectors_per_stage as f32 * 60.0 / 1000.0
+    }
+
+    fn sample(&self, now: Instant) -> Sample {
+        let speed_kmh = match &self.speed_channel {
+            Some(channel) => telemetry::channel(channel, now),
+            None => vehicle::speed_kmh(),
+        };
+        let fuel_lph = telemetry::channel(&self.primary_duty_channel, now).map(|primary| {
+            let secondary = self
+                .secondary_duty_channel
+                .as_deref()
+                .and_then(|channel| telemetry::channel(channel, now));
+            self.fuel_lph(primary, secondary)
+        });
+        Sample {
+            speed_kmh,
+            fuel_lph,
+            engine_running: telemetry::rpm(self.rpm_channel.as_deref(), now).is_some_and(|rpm| rpm >= RUNNING_RPM),
+        }
+    }
+}
+
+/// What the car was doing over one step
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub speed_kmh: Option<f32>,
+    pub fuel_lph: Option<f32>,
+    pub engine_running: bool,
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct TripCounters {
+    /// Unix seconds of the last reset
+    pub started_at: u64,
+    pub distance_km: f64,
+    /// Time with the head unit on
+    pub elapsed_secs: f64,
+    pub moving_secs: f64,
+    pub max_speed_kmh: f32,
+    /// Only counted while the ECU is reporting
+    pub fuel_litres: f64,
+    pub engine_secs: f64,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct TripSummary {
+    #[serde(flatten)]
+    pub counters: TripCounters,
+    /// Over moving time
+    pub average_speed_kmh: Option<f32>,
+    pub litres_per_100km: Option<f32>,
+    /// US gallons
+    pub mpg: Option<f32>,
+    pub engine_hours: f64,
+}
+
+impl TripCounters {
+    fn new() -> Self {
+        TripCounters {
+            started_at: unix_secs(),
+            ..Default::default()
+        }
+    }
+
+    fn add(&mut self, sample: &Sample, secs: f64) {
+        self.elapsed_secs += secs;
+        if let Some(speed) = sample.speed_kmh {
+            self.max_speed_kmh = self.max_speed_kmh.max(speed);
+            if speed >= MOVING_KMH {
+                self.distance_km += speed as f64 * secs / 3600.0;
+                self.moving_secs += secs;
+            }
+        }
+        if let Some(lph) = sample.fuel_lph {
+            self.fuel_litres += lph as f64 * secs / 3600.0;
+        }
+        if sample.engine_running {
+            self.engine_secs += secs;
+        }
+    }
+
+    pub fn summary(&self) -> TripSummary {
+        let average_speed_kmh = (self.moving_secs > 0.0).then(|| (self.distance_km / self.moving_secs * 3600.0) as f32);
+        // Too short a distance gives silly numbers
+        let economy = self.distance_km >= 0.1 && self.fuel_litres > 0.0;
+        TripSummary {
+            counters: self.clone(),
+            average_speed_kmh,
+            litres_per_100km: economy.then(|| (self.fuel_litres / self.distance_km * 100.0) as f32),
+            mpg: economy.then(|| ((self.distance_km / KM_PER_MILE) / (self.fuel_litres / LITRES_PER_GALLON)) as f32),
+            engine_hours: self.engine_secs / 3600.0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TripId {
+    A,
+    B,
+}
+
+/// Two resettable trips plus the lifetime totals
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct Trips {
+    pub a: TripCounters,
+    pub b: TripCounters,
+    /// Never reset, only corrected with `set_odometer`
+    pub lifetime: TripCounters,
+}
+
+impl Default for Trips {
+    fn default() -> Self {
+        Self {
+            a: TripCounters::new(),
+            b: TripCounters::new(),
+            lifetime: TripCounters::new(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct TripUpdate {
+    pub a: TripSummary,
+    pub b: TripSummary,
+    pub lifetime: TripSummary,
+}
+
+impl Trips {
+    fn add(&mut self, sample: &Sample, secs: f64) {
+        for counters in [&mut self.a, &mut self.b, &mut self.lifetime] {
+            counters.add(sample, secs);
+        }
+    }
+
+    fn reset(&mut self, trip: TripId) {
+        match trip {
+            TripId::A => self.a = TripCounters::new(),
+            TripId::B => self.b = TripCounters::new(),
+        }
+    }
+
+    pub fn update(&self) -> TripUpdate {
+        TripUpdate {
+            a: self.a.summary(),
+            b: self.b.summary(),
+            lifetime: self.lifetime.summary(),
+        }
+    }
+}
+
+static SETTINGS: Lazy<Mutex<TripSettings>> = Lazy::new(|| Mutex::new(settings::load(SETTINGS_NAME)));
+static TRIPS: Lazy<Mutex<Trips>> = Lazy::new(|| Mutex::new(settings::load(TRIPS_NAME)));
+
+/// Lifetime totals, for the service intervals
+pub fn lifetime() -> TripCounters {
+    TRIPS.lock().map(|trips| trips.lifetime.clone()).unwrap_or_default()
+}
+
+/// Write the counters out. Done every minute and before a shutdown.
+pub fn save() {
+    let trips = match TRIPS.lock() {
+        Ok(trips) => trips.clone(),
+        Err(_) => return,
+    };
+    if let Err(e) = settings::save(TRIPS_NAME, &trips) {
+        println!("Failed to save trips: {}", e);
+    }
+}
+
+fn emit_update(app: &AppHandle) {
+    let update = match TRIPS.lock() {
+        Ok(trips) => trips.update(),
+        Err(_) => return,
+    };
+    if let Err(e) = app.emit(UPDATE_EVENT, update) {
+        println!("Failed to emit trip update: {}", e);
+    }
+}
+
+/// Start counting. Called once from the app setup hook.
+pub fn start(app: AppHandle) {
+    std::thread::spawn(move || {
+        let mut last = Instant::now();
+        let mut last_emit = last;
+        let mut last_save = last;
+
+        loop {
+            std::thread::sleep(TICK);
+
+            let config = match SETTINGS.lock() {
+                Ok(config) => config.clone(),
+                Err(_) => return,
+            };
+            let now = Instant::now();
+            let step = now.duration_since(last);
+            last = now;
+
+            if step <= MAX_STEP {
+                let sample = config.sample(now);
+                match TRIPS.lock() {
+                    Ok(mut trips) => trips.add(&sample, step.as_secs_f64()),
+                    Err(_) => return,
+                }
+            }
+
+            if now.duration_since(last_emit) >= EMIT_INTERVAL {
+                emit_update(&app);
+                last_emit = now;
+            }
+            if now.duration_since(last_save) >= SAVE_INTERVAL {
+                save();
+                last_save = now;
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_trips() -> Result<TripUpdate, String> {
+    let trips = TRIPS.lock().map_err(|e| e.to_string())?;
+    Ok(trips.update())
+}
+
+#[tauri::command]
+pub fn reset_trip(app: AppHandle, trip: TripId) -> Result<(), String> {
+    TRIPS.lock().map_err(|e| e.to_string())?.reset(trip);
+    save();
+    emit_update(&app);
+    Ok(())
+}
+
+/// Line the lifetime distance up with the car's odometer
+#[tauri::command]
+pub fn set_odometer(app: AppHandle, km: f64) -> Result<(), String> {
+    if !km.is_finite() || km < 0.0 {
+        return Err("Odometer can't be negative".to_string());
+    }
+    TRIPS.lock().map_err(|e| e.to_string())?.lifetime.distance_km = km;
+    save();
+    emit_update(&app);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_trip_settings() -> Result<TripSettings, String> {
+    let config = SETTINGS.lock().map_err(|e| e.to_string())?;
+    Ok(config.clone())
+}
+
+#[tauri::command]
+pub fn set_trip_settings(config: TripSettings) -> Result<(), String> {
+    settings::save(SETTINGS_NAME, &config)?;
+    *SETTINGS.lock().map_err(|e| e.to_string())? = config;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn driving(speed_kmh: f32, fuel_lph: f32) -> Sample {
+        Sample {
+            speed_kmh: Some(speed_kmh),
+            fuel_lph: Some(fuel_lph),
+            engine_running: true,
+        }
+    }
+
+    #[test]
+    fn integrates_distance_and_time() {
+        let mut trip = TripCounters::default();
+
+        // A minute at 60 km/h, then a minute idling at the lights
+        for _ in 0..60 {
+            trip.add(&driving(60.0, 6.0), 1.0);
+        }
+        for _ in 0..60 {
+            trip.add(&driving(0.0, 1.2), 1.0);
+        }
+
+        let summary = trip.summary();
+        assert!((trip.distance_km - 1.0).abs() < 1e-9);
+        assert_eq!(trip.elapsed_secs, 120.0);
+        assert_eq!(trip.moving_secs, 60.0);
+        assert_eq!(trip.max_speed_kmh, 60.0);
+        assert_eq!(summary.average_speed_kmh, Some(60.0));
+        assert!((summary.engine_hours - 120.0 / 3600.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn parked_gps_drift_adds_no_distance() {
+        let mut trip = TripCounters::default();
+
+        // An hour parked with the fix wandering between 0 and 1.5 km/h
+        for i in 0..3600 {
+            trip.add(&driving((i % 4) as f32 * 0.5, 0.0), 1.0);
+        }
+        assert_eq!(trip.distance_km, 0.0);
+        assert_eq!(trip.moving_secs, 0.0);
+        assert_eq!(trip.summary().average_speed_kmh, None);
+    }
+
+    #[test]
+    fn fuel_economy() {
+        let mut trip = TripCounters::default();
+        // 100 km/h burning 12 L/h is 12 L/100km
+        for _ in 0..36 {
+            trip.add(&driving(100.0, 12.0), 100.0);
+        }
+
+        let summary = trip.summary();
+        assert!((summary.litres_per_100km.unwrap() - 12.0).abs() < 1e-3);
+        assert!((summary.mpg.unwrap() - 19.6).abs() < 0.05);
+    }
+
+    #[test]
+    fn no_economy_without_fuel_or_distance() {
+        let mut trip = TripCounters::default();
+        let sample = Sample {
+            speed_kmh: Some(50.0),
+            fuel_lph: None,
+            engine_running: false,
+        };
+        trip.add(&sample, 60.0);
+        assert_eq!(trip.summary().litres_per_100km, None);
+        assert_eq!(trip.engine_secs, 0.0);
+
+        let mut parked = TripCounters::default();
+        parked.add(&driving(0.0, 1.0), 60.0);
+        assert_eq!(parked.summary().mpg, None);
+        assert_eq!(parked.summary().average_speed_kmh, None);
+    }
+
+    #[test]
+    fn fuel_flow_from_duty() {
+        let config = TripSettings::default();
+        // Two 550s at 10% is 110 cc/min
+        assert!((config.fuel_lph(10.0, None) - 6.6).abs() < 1e-3);
+        // Plus two 850s at 5%, 85 cc/min
+        assert!((config.fuel_lph(10.0, Some(5.0)) - 11.7).abs() < 1e-3);
+    }
+
+    #[test]
+    fn reset_keeps_lifetime() {
+        let mut trips = Trips::default();
+        trips.add(&driving(60.0, 6.0), 60.0);
+        trips.reset(TripId::A);
+
+        assert_eq!(trips.a.distance_km, 0.0);
+        assert!(trips.b.distance_km > 0.0);
+        assert!(trips.lifetime.distance_km > 0.0);
+    }
+}
@@ -0,0 +1,138 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::watch;
+
+use super::settings;
+
+const SETTINGS_NAME: &str = "vehicle";
+// Drop the speed if gpsd goes quiet for this long
+const GPS_TIMEOUT: Duration = Duration::from_secs(5);
+const GPS_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SpeedSource {
+    /// Read TPV reports from gpsd
+    Gpsd,
+    /// Only take speeds from `set_test_vehicle_speed`
+    Manual,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct VehicleSettings {
+    pub speed_source: SpeedSource,
+    pub gpsd_address: String,
+}
+
+impl Default for VehicleSettings {
+    fn default() -> Self {
+        Self {
+            speed_source: SpeedSource::Gpsd,
+            gpsd_address: "127.0.0.1:2947".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+// Latest vehicle speed in km/h, None while there's no fix
+static SPEED: Lazy<watch::Sender<Option<f32>>> = Lazy::new(|| watch::channel(None).0);
+// Last GPS position, kept through dropouts since it's only used for rough location
+static POSITION: Lazy<watch::Sender<Option<Position>>> = Lazy::new(|| watch::channel(None).0);
+
+pub fn speed_kmh() -> Option<f32> {
+    *SPEED.borrow()
+}
+
+pub fn publish_speed(speed: Option<f32>) {
+    SPEED.send_replace(speed);
+}
+
+pub fn position() -> Option<Position> {
+    *POSITION.borrow()
+}
+
+/// Pull the position out of a gpsd TPV report
+fn parse_tpv_position(line: &str) -> Option<Position> {
+    let report: serde_json::Value = serde_json::from_str(line).ok()?;
+    if report.get("class")?.as_str()? != "TPV" {
+        return None;
+    }
+    Some(Position {
+        latitude: report.get("lat")?.as_f64()?,
+        longitude: report.get("lon")?.as_f64()?,
+    })
+}
+
+/// Pull the speed (m/s) out of a gpsd TPV report
+fn parse_tpv_speed(line: &str) -> Option<f32> {
+    let report: serde_json::Value = serde_json::from_str(line).ok()?;
+    if report.get("class")?.as_str()? != "TPV" {
+        return None;
+    }
+    report.get("speed")?.as_f64().map(|speed| speed as f32)
+}
+
+async fn read_gpsd(address: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut stream = TcpStream::connect(address).await?;
+    stream.write_all(b"?WATCH={\"enable\":true,\"json\":true}\n").await?;
+
+    let mut lines = BufReader::new(stream).lines();
+    loop {
+        match tokio::time::timeout(GPS_TIMEOUT, lines.next_line()).await {
+            Ok(Ok(Some(line))) => {
+                if let Some(speed) = parse_tpv_speed(&line) {
+                    publish_speed(Some(speed * 3.6));
+                }
+                if let Some(position) = parse_tpv_position(&line) {
+                    POSITION.send_replace(Some(position));
+                }
+            }
+            Ok(Ok(None)) => return Err("gpsd closed the connection".into()),
+            Ok(Err(e)) => return Err(e.into()),
+            // No reports for a while, the last speed is stale
+            Err(_) => publish_speed(None),
+        }
+    }
+}
+
+/// Start feeding the vehicle speed from the configured source
+pub fn start() {
+    let vehicle_settings: VehicleSettings = settings::load(SETTINGS_NAME);
+    if vehicle_settings.speed_source != SpeedSource::Gpsd {
+        return;
+    }
+
+    tauri::async_runtime::spawn(async move {
+        loop {
+            if let Err(e) = read_gpsd(&vehicle_settings.gpsd_address).await {
+                println!("gpsd speed source error: {}", e);
+            }
+            publish_speed(None);
+            tokio::time::sleep(GPS_RETRY_DELAY).await;
+        }
+    });
+}
+
+/// Test feed for driving speed-dependent features without a GPS fix
+#[tauri::command]
+pub fn set_test_vehicle_speed(speed: Option<f32>) -> Result<(), String> {
+    if speed.map(|s| s < 0.0).unwrap_or(false) {
+        return Err("Speed can't be negative".to_string());
+    }
+    publish_speed(speed);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_vehicle_speed() -> Result<Option<f32>, String> {
+    Ok(speed_kmh())
+}
@@ -0,0 +1,455 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::io::Read;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::AppHandle;
+
+use super::settings;
+use super::telemetry::{self, Telemetry};
+
+const SETTINGS_NAME: &str = "wideband";
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+// Both controllers stream several times a second, so this much silence means it's gone
+const MAX_SILENT_READS: u32 = 3;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WidebandProtocol {
+    /// Innovate LC-1/LC-2 and other MTS devices
+    InnovateMts,
+    /// AEM UEGO ASCII serial output
+    AemAscii,
+}
+
+impl WidebandProtocol {
+    fn baud_rate(self) -> u32 {
+        match self {
+            WidebandProtocol::InnovateMts => 19200,
+            WidebandProtocol::AemAscii => 9600,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Fuel {
+    Gasoline,
+    E85,
+    Ethanol,
+    Methanol,
+    Lpg,
+}
+
+impl Fuel {
+    /// Stoichiometric air/fuel ratio
+    pub fn stoich_afr(self) -> f32 {
+        match self {
+            Fuel::Gasoline => 14.7,
+            Fuel::E85 => 9.765,
+            Fuel::Ethanol => 9.0,
+            Fuel::Methanol => 6.4,
+            Fuel::Lpg => 15.5,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct WidebandSettings {
+    pub enabled: bool,
+    pub protocol: WidebandProtocol,
+    pub port: String,
+    /// What AFR is shown against, lambda doesn't depend on it
+    pub fuel: Fuel,
+}
+
+impl Default for WidebandSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            protocol: WidebandProtocol::InnovateMts,
+            port: "/dev/ttyUSB1".to_string(),
+            fuel: Fuel::Gasoline,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum SensorStatus {
+    Ok,
+    /// Heater still bringing the sensor up to temperature
+    WarmingUp {
+        percent: Option<f32>,
+    },
+    /// Free air or heater calibration in progress
+    Calibrating,
+    NeedsCalibration,
+    /// Sensor out of the exhaust, reading oxygen in free air
+    FreeAir {
+        oxygen_percent: f32,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// One decoded report from a controller
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sample {
+    Lambda(f32),
+    Status(SensorStatus),
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct AfrReading {
+    /// None unless the sensor is reading
+    pub lambda: Option<f32>,
+    pub afr: Option<f32>,
+    pub fuel: Fuel,
+    pub status: SensorStatus,
+}
+
+impl AfrReading {
+    pub fn new(sample: Sample, fuel: Fuel) -> Self {
+        match sample {
+            Sample::Lambda(lambda) => AfrReading {
+                lambda: Some(lambda),
+                afr: Some(lambda * fuel.stoich_afr()),
+                fuel,
+                status: SensorStatus::Ok,
+            },
+            Sample::Status(status) => AfrReading {
+                lambda: None,
+                afr: None,
+                fuel,
+                status,
+            },
+        }
+    }
+}
+
+/// Decodes a wideband controller's serial stream
+pub trait AfrSource: Send {
+    /// Feed bytes as they arrive, returning any complete samples
+    fn feed(&mut self, bytes: &[u8]) -> Vec<Sample>;
+}
+
+/// Innovate MTS packets: a header word giving the packet length, then one
+/// or more sub-packets. Only the first lambda sub-packet is used.
+#[derive(Default)]
+pub struct InnovateMts {
+    buffer: Vec<u8>,
+}
+
+impl InnovateMts {
+    fn is_header(high: u8, low: u8) -> bool {
+        high & 0xA2 == 0xA2 && low & 0x80 != 0
+    }
+
+    fn is_lambda(high: u8) -> bool {
+        high & 0xE2 == 0x42
+    }
+
+    fn decode_lambda(words: &[u8]) -> Sample {
+        let function = (words[0] >> 2) & 0x07;
+        let value = ((words[2] & 0x3F) as u16) << 7 | (words[3] & 0x7F) as u16;
+
+        match function {
+            0 => Sample::Lambda((value as f32 + 500.0) / 1000.0),
+            1 => Sample::Status(SensorStatus::FreeAir {
+                oxygen_percent: value as f32 / 10.0,
+            }),
+            2 | 5 => Sample::Status(SensorStatus::Calibrating),
+            3 => Sample::Status(SensorStatus::NeedsCalibration),
+            4 => Sample::Status(SensorStatus::WarmingUp {
+                percent: Some(value as f32 / 10.0),
+            }),
+            6 => Sample::Status(SensorStatus::Error {
+                message: format!("Controller error {}", value),
+            }),
+            _ => Sample::Status(SensorStatus::Error {
+                message: format!("Unknown function {}", function),
+            }),
+        }
+    }
+}
+
+impl AfrSource for InnovateMts {
+    fn feed(&mut self, bytes: &[u8]) -> Vec<Sample> {
+        self.buffer.extend_from_slice(bytes);
+        let mut samples = vec![];
+
+        loop {
+            // Only header bytes have the top bit set, so resync on the next one
+            let start = self
+                .buffer
+                .windows(2)
+                .position(|pair| Self::is_header(pair[0], pair[1]));
+            let Some(start) = start else {
+                let keep = self.buffer.len().min(1);
+                self.buffer.drain(..self.buffer.len() - keep);
+                break;
+            };
+            self.buffer.drain(..start);
+
+            let words = ((self.buffer[0] & 0x01) << 7 | self.buffer[1] & 0x7F) as usize;
+            let packet_len = 2 + words * 2;
+            if self.buffer.len() < packet_len {
+                break;
+            }
+
+            let packet: Vec<u8> = self.buffer.drain(..packet_len).skip(2).collect();
+            // Lambda sub-packets are two words, anything else (aux inputs) is one
+            let mut offset = 0;
+            while offset + 2 <= packet.len() {
+                if Self::is_lambda(packet[offset]) && offset + 4 <= packet.len() {
+                    samples.push(Self::decode_lambda(&packet[offset..offset + 4]));
+                    break;
+                }
+                offset += 2;
+            }
+        }
+        samples
+    }
+}
+
+/// AEM UEGO serial output: one gasoline AFR per line, e.g. "14.7\r\n"
+#[derive(Default)]
+pub struct AemAscii {
+    line: Vec<u8>,
+}
+
+impl AemAscii {
+    const MAX_LINE: usize = 32;
+    const GASOLINE_STOICH: f32 = 14.7;
+
+    fn decode_line(line: &str) -> Option<Sample> {
+        let line = line.trim();
+        if line.is_empty() {
+            return None;
+        }
+        if let Ok(afr) = line.parse::<f32>() {
+            return Some(Sample::Lambda(afr / Self::GASOLINE_STOICH));
+        }
+
+        Some(Sample::Status(if line.to_ascii_uppercase().contains("WARM") {
+            SensorStatus::WarmingUp { percent: None }
+        } else {
+            SensorStatus::Error {
+                message: line.to_string(),
+            }
+        }))
+    }
+}
+
+impl AfrSource for AemAscii {
+    fn feed(&mut self, bytes: &[u8]) -> Vec<Sample> {
+        let mut samples = vec![];
+        for &byte in bytes {
+            if byte == b'\n' || byte == b'\r' {
+                if let Some(sample) = Self::decode_line(&String::from_utf8_lossy(&self.line)) {
+                    samples.push(sample);
+                }
+                self.line.clear();
+            } else if self.line.len() < Self::MAX_LINE {
+                self.line.push(byte);
+            } else {
+                // Not a line based stream, wrong baud rate or protocol
+                self.line.clear();
+            }
+        }
+        samples
+    }
+}
+
+fn open_source(protocol: WidebandProtocol) -> Box<dyn AfrSource> {
+    match protocol {
+        WidebandProtocol::InnovateMts => Box::new(InnovateMts::default()),
+        WidebandProtocol::AemAscii => Box::new(AemAscii::default()),
+    }
+}
+
+static SETTINGS: Lazy<Mutex<WidebandSettings>> = Lazy::new(|| Mutex::new(settings::load(SETTINGS_NAME)));
+static LATEST: Mutex<Option<AfrReading>> = Mutex::new(None);
+
+/// Latest reading, None while no controller is talking
+pub fn afr_reading() -> Option<AfrReading> {
+    LATEST.lock().ok().and_then(|latest| latest.clone())
+}
+
+fn set_latest(reading: Option<AfrReading>) {
+    if let Ok(mut latest) = LATEST.lock() {
+        *latest = reading;
+    }
+}
+
+fn read_stream(app: &AppHandle, config: &WidebandSettings) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut port = serialport::new(&config.port, config.protocol.baud_rate())
+        .timeout(READ_TIMEOUT)
+        .open()?;
+    let mut source = open_source(config.protocol);
+    let mut buffer = [0u8; 256];
+    let mut silent_reads = 0;
+
+    loop {
+        let read = match port.read(&mut buffer) {
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => 0,
+            Err(e) => return Err(e.into()),
+        };
+
+        // Only quiet reads count toward giving up; a long packet can take
+        // several reads to assemble
+        if read == 0 {
+            silent_reads += 1;
+            if silent_reads >= MAX_SILENT_READS {
+                return Err("No data from controller".into());
+            }
+            continue;
+        }
+        silent_reads = 0;
+
+        for sample in source.feed(&buffer[..read]) {
+            let reading = AfrReading::new(sample, config.fuel);
+            set_latest(Some(reading.clone()));
+            telemetry::publish(app, Telemetry::Wideband(reading));
+        }
+    }
+}
+
+/// Start reading the wideband controller. Called once from the app setup hook.
+pub fn start(app: AppHandle) {
+    let config = match SETTINGS.lock() {
+        Ok(config) => config.clone(),
+        Err(_) => return,
+    };
+    if !config.enabled {
+        return;
+    }
+
+    std::thread::spawn(move || loop {
+        if let Err(e) = read_stream(&app, &config) {
+            println!("Wideband on {} failed: {}", config.port, e);
+        }
+        set_latest(None);
+        std::thread::sleep(RETRY_DELAY);
+    });
+}
+
+#[tauri::command]
+pub fn get_afr_reading() -> Result<Option<AfrReading>, String> {
+    Ok(afr_reading())
+}
+
+#[tauri::command]
+pub fn get_wideband_settings() -> Result<WidebandSettings, String> {
+    let config = SETTINGS.lock().map_err(|e| e.to_string())?;
+    Ok(config.clone())
+}
+
+/// The port is opened at startup, changes apply after a restart
+#[tauri::command]
+pub fn set_wideband_settings(config: WidebandSettings) -> Result<(), String> {
+    settings::save(SETTINGS_NAME, &config)?;
+    *SETTINGS.lock().map_err(|e| e.to_string())? = config;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LC-1 at power on: warming up, then stoich, then a rich pull. Header
+    // B2 82 is a two word packet, the AFR multiplier is 147 (gasoline).
+    const LC1_WARMUP: [u8; 6] = [0xB2, 0x82, 0x53, 0x13, 0x03, 0x10];
+    const LC1_STOICH: [u8; 6] = [0xB2, 0x82, 0x43, 0x13, 0x03, 0x74];
+    const LC1_RICH: [u8; 6] = [0xB2, 0x82, 0x43, 0x13, 0x02, 0x2C];
+    // Heater circuit fault, error code 3
+    const LC1_ERROR: [u8; 6] = [0xB2, 0x82, 0x5B, 0x13, 0x00, 0x03];
+
+    fn lambda(sample: &Sample) -> f32 {
+        match sample {
+            Sample::Lambda(lambda) => *lambda,
+            other => panic!("Expected lambda, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn innovate_warmup_then_lambda() {
+        let mut source = InnovateMts::default();
+        let stream = [&LC1_WARMUP[..], &LC1_STOICH, &LC1_RICH].concat();
+
+        let samples = source.feed(&stream);
+        assert_eq!(samples.len(), 3);
+        assert_eq!(
+            samples[0],
+            Sample::Status(SensorStatus::WarmingUp { percent: Some(40.0) })
+        );
+        assert!((lambda(&samples[1]) - 1.0).abs() < 0.001);
+        assert!((lambda(&samples[2]) - 0.8).abs() < 0.001);
+    }
+
+    #[test]
+    fn innovate_error_frame() {
+        let mut source = InnovateMts::default();
+        assert_eq!(
+            source.feed(&LC1_ERROR),
+            vec![Sample::Status(SensorStatus::Error {
+                message: "Controller error 3".to_string()
+            })]
+        );
+    }
+
+    #[test]
+    fn innovate_resyncs_mid_stream() {
+        let mut source = InnovateMts::default();
+
+        // Port opened partway through a packet, and the next one split across reads
+        assert!(source.feed(&LC1_STOICH[3..]).is_empty());
+        assert!(source.feed(&LC1_RICH[..4]).is_empty());
+        let samples = source.feed(&LC1_RICH[4..]);
+        assert_eq!(samples.len(), 1);
+        assert!((lambda(&samples[0]) - 0.8).abs() < 0.001);
+    }
+
+    #[test]
+    fn innovate_skips_aux_words() {
+        let mut source = InnovateMts::default();
+        // Three words: an aux input from an LMA-3, then the LC-1
+        let stream = [0xB2, 0x83, 0x01, 0x23, 0x43, 0x13, 0x03, 0x74];
+
+        let samples = source.feed(&stream);
+        assert!((lambda(&samples[0]) - 1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn aem_lines() {
+        let mut source = AemAscii::default();
+
+        let samples = source.feed(b"WARM\r\n14.7\r\n11.");
+        assert_eq!(samples[0], Sample::Status(SensorStatus::WarmingUp { percent: None }));
+        assert!((lambda(&samples[1]) - 1.0).abs() < 0.001);
+
+        let samples = source.feed(b"76\r\nE-03\r\n");
+        assert!((lambda(&samples[0]) - 0.8).abs() < 0.001);
+        assert_eq!(
+            samples[1],
+            Sample::Status(SensorStatus::Error {
+                message: "E-03".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn afr_follows_fuel() {
+        let reading = AfrReading::new(Sample::Lambda(0.8), Fuel::E85);
+        assert!((reading.afr.unwrap() - 7.812).abs() < 0.001);
+
+        let reading = AfrReading::new(Sample::Status(SensorStatus::NeedsCalibration), Fuel::Gasoline);
+        assert_eq!(reading.afr, None);
+    }
+}
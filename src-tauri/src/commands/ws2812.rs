@@ -0,0 +1,262 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+#[cfg(target_os = "linux")]
+use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
+
+use super::gpio::DigitalOutput;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const OFF: Rgb = Rgb { r: 0, g: 0, b: 0 };
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Rgb { r, g, b }
+    }
+
+    /// Scale by `brightness` out of 255
+    pub fn dim(self, brightness: u8) -> Self {
+        let scale = |channel: u8| (channel as u16 * brightness as u16 / 255) as u8;
+        Rgb::new(scale(self.r), scale(self.g), scale(self.b))
+    }
+
+    pub fn is_off(self) -> bool {
+        self == Rgb::OFF
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LedType {
+    /// WS2812B and SK6812 RGB, GRB order
+    #[default]
+    Ws2812,
+    /// SK6812 RGBW, GRBW order. Whatever all three colours share goes to the white LED.
+    Sk6812Rgbw,
+}
+
+impl LedType {
+    /// SPI bytes to send a frame to `leds` of these, three per colour channel
+    pub fn frame_bytes(self, leds: usize) -> usize {
+        let channels = match self {
+            LedType::Ws2812 => 3,
+            LedType::Sk6812Rgbw => 4,
+        };
+        leds * channels * 3 + RESET_BYTES
+    }
+}
+
+/// A row of LEDs, index 0 nearest the controller
+pub trait LedStrip: Send {
+    fn len(&self) -> usize;
+
+    fn write(&mut self, colors: &[Rgb]) -> Result<(), Box<dyn Error + Send + Sync>>;
+}
+
+// Each data bit is sent as three SPI bits at 2.4 MHz, 1.25 us per bit
+const SPI_CLOCK_HZ: u32 = 2_400_000;
+const ONE: u8 = 0b110;
+const ZERO: u8 = 0b100;
+// Low for over 80 us latches the frame, long enough for SK6812s too
+const RESET_BYTES: usize = 30;
+// spidev's default buffer, writes longer than `spidev.bufsiz` are refused.
+// deploy/setup-pi.sh raises it so long strips fit in one transfer.
+const SPIDEV_BUFSIZ: usize = 4096;
+#[cfg(target_os = "linux")]
+const SPIDEV_BUFSIZ_PATH: &str = "/sys/module/spidev/parameters/bufsiz";
+
+/// SPI bytes for a frame, in the order `led_type` expects
+pub fn encode(colors: &[Rgb], led_type: LedType) -> Vec<u8> {
+    let mut out = Vec::with_capacity(led_type.frame_bytes(colors.len()));
+    for color in colors {
+        let channels = match led_type {
+            LedType::Ws2812 => vec![color.g, color.r, color.b],
+            LedType::Sk6812Rgbw => {
+                let white = color.r.min(color.g).min(color.b);
+                vec![color.g - white, color.r - white, color.b - white, white]
+            }
+        };
+        for byte in channels {
+            // 8 bits become 24, sent most significant first
+            let bits = (0..8).rev().fold(0u32, |bits, i| {
+                bits << 3 | if byte >> i & 1 == 1 { ONE } else { ZERO } as u32
+            });
+            out.extend_from_slice(&bits.to_be_bytes()[1..]);
+        }
+    }
+    out.extend(std::iter::repeat_n(0, RESET_BYTES));
+    out
+}
+
+/// WS2812 strip with its data line on an SPI MOSI pin.
+///
+/// Only MOSI is used, so the strip needs a bus of its own: GPIO10 on SPI0 or
+/// GPIO20 on SPI1. Each frame goes out as one transfer, as a gap between two
+/// writes can be long enough to latch half a frame.
+#[cfg(target_os = "linux")]
+pub struct SpiStrip {
+    spi: Spi,
+    len: usize,
+    led_type: LedType,
+}
+
+#[cfg(target_os = "linux")]
+impl SpiStrip {
+    pub fn new(bus: u8, len: usize, led_type: LedType) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let bus = match bus {
+            0 => Bus::Spi0,
+            1 => Bus::Spi1,
+            2 => Bus::Spi2,
+            3 => Bus::Spi3,
+            4 => Bus::Spi4,
+            5 => Bus::Spi5,
+            6 => Bus::Spi6,
+            _ => return Err(format!("No SPI bus {}", bus).into()),
+        };
+        let bufsiz = std::fs::read_to_string(SPIDEV_BUFSIZ_PATH)
+            .ok()
+            .and_then(|bufsiz| bufsiz.trim().parse().ok())
+            .unwrap_or(SPIDEV_BUFSIZ);
+        let frame = led_type.frame_bytes(len);
+        if frame > bufsiz {
+            return Err(format!(
+                "{} LEDs need a {} byte SPI transfer but spidev.bufsiz is {}, raise it in cmdline.txt",
+                len, frame, bufsiz
+            )
+            .into());
+        }
+        Ok(SpiStrip {
+            spi: Spi::new(bus, SlaveSelect::Ss0, SPI_CLOCK_HZ, Mode::Mode0)?,
+            len,
+            led_type,
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl LedStrip for SpiStrip {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn write(&mut self, colors: &[Rgb]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let frame = encode(&colors[..colors.len().min(self.len)], self.led_type);
+        self.spi.write(&frame)?;
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn open_strip(bus: u8, len: usize, led_type: LedType) -> Result<Box<dyn LedStrip>, Box<dyn Error + Send + Sync>> {
+    Ok(Box::new(SpiStrip::new(bus, len, led_type)?))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn open_strip(
+    _bus: u8,
+    _len: usize,
+    _led_type: LedType,
+) -> Result<Box<dyn LedStrip>, Box<dyn Error + Send + Sync>> {
+    Err("LED strips are only supported on Linux".into())
+}
+
+/// A single plain LED on a GPIO pin, lit while its one pixel isn't black
+pub struct GpioLed {
+    output: Box<dyn DigitalOutput>,
+    active_low: bool,
+}
+
+impl GpioLed {
+    pub fn new(output: Box<dyn DigitalOutput>, active_low: bool) -> Self {
+        GpioLed { output, active_low }
+    }
+}
+
+impl LedStrip for GpioLed {
+    fn len(&self) -> usize {
+        1
+    }
+
+    fn write(&mut self, colors: &[Rgb]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let lit = colors.first().is_some_and(|color| !color.is_off());
+        self.output.set_level(lit != self.active_low);
+        Ok(())
+    }
+}
+
+/// Records every frame written, for testing effects without hardware
+#[cfg(test)]
+#[derive(Clone)]
+pub struct MockStrip {
+    pub len: usize,
+    pub frames: std::sync::Arc<std::sync::Mutex<Vec<Vec<Rgb>>>>,
+}
+
+#[cfg(test)]
+impl MockStrip {
+    pub fn new(len: usize) -> Self {
+        MockStrip {
+            len,
+            frames: Default::default(),
+        }
+    }
+
+    pub fn last(&self) -> Option<Vec<Rgb>> {
+        self.frames.lock().unwrap().last().cloned()
+    }
+}
+
+#[cfg(test)]
+impl LedStrip for MockStrip {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn write(&mut self, colors: &[Rgb]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.frames.lock().unwrap().push(colors.to_vec());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_grb_bits() {
+        let frame = encode(&[Rgb::new(0x00, 0xFF, 0x80)], LedType::Ws2812);
+        assert_eq!(frame.len(), 9 + RESET_BYTES);
+        assert_eq!(frame.len(), LedType::Ws2812.frame_bytes(1));
+
+        // Green first: eight 110s
+        assert_eq!(&frame[0..3], &[0b1101_1011, 0b0110_1101, 0b1011_0110]);
+        // Red: eight 100s
+        assert_eq!(&frame[3..6], &[0b1001_0010, 0b0100_1001, 0b0010_0100]);
+        // Blue 0x80: one 110 then seven 100s
+        assert_eq!(&frame[6..9], &[0b1101_0010, 0b0100_1001, 0b0010_0100]);
+        assert!(frame[9..].iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn rgbw_moves_shared_part_to_white() {
+        let frame = encode(&[Rgb::new(0xFF, 0xFF, 0x80)], LedType::Sk6812Rgbw);
+        assert_eq!(frame.len(), 12 + RESET_BYTES);
+        assert_eq!(frame.len(), LedType::Sk6812Rgbw.frame_bytes(1));
+        // G and R keep 0x7F, B nothing, W takes 0x80
+        assert_eq!(&frame[0..3], &encode(&[Rgb::new(0, 0x7F, 0)], LedType::Ws2812)[0..3]);
+        assert_eq!(&frame[6..9], &encode(&[Rgb::OFF], LedType::Ws2812)[0..3]);
+        assert_eq!(&frame[9..12], &[0b1101_0010, 0b0100_1001, 0b0010_0100]);
+    }
+
+    #[test]
+    fn dims() {
+        assert_eq!(Rgb::new(255, 128, 10).dim(128), Rgb::new(128, 64, 5));
+        assert_eq!(Rgb::new(255, 255, 255).dim(0), Rgb::OFF);
+    }
+}
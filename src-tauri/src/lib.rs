@@ -1,12 +1,132 @@
 mod commands;
 
+use tauri::Manager;
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .register_asynchronous_uri_scheme_protocol("camera", |_ctx, request, responder| {
+            commands::camera::handle_frame_request(request, responder)
+        })
+        .setup(|app| {
+            commands::display::init(app.handle());
+            commands::auto_brightness::start(app.handle().clone());
+            commands::light_sensor::start(app.handle().clone());
+            commands::illumination::start(app.handle().clone());
+            commands::display_power::start(app.handle().clone());
+            commands::power::start(app.handle().clone());
+            commands::physical_controls::start(app.handle().clone());
+            commands::steering_buttons::start(app.handle().clone());
+            commands::reverse::start(app.handle().clone());
+            commands::power_fc::start(app.handle().clone());
+            commands::wideband::start(app.handle().clone());
+            commands::analog_sensors::start(app.handle().clone());
+            commands::tach::start(app.handle().clone());
+            commands::alarms::start(app.handle().clone());
+            commands::shift_light::start();
+            commands::lighting::start(app.handle().clone());
+            commands::headlights::start(app.handle().clone());
+            commands::trip::start(app.handle().clone());
+            commands::maintenance::start(app.handle().clone());
+            let soundboard_dir = app
+                .path()
+                .resolve("assets/audio/soundboard", tauri::path::BaseDirectory::Resource)?;
+            commands::soundboard::init(&soundboard_dir);
+            commands::vehicle::start();
+            commands::speed_volume::start();
+            commands::audio_route::start();
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             commands::audio::set_system_volume,
+            commands::dsp::get_audio_settings,
+            commands::dsp::set_audio_settings,
+            // Audio routing commands
+            commands::audio_route::list_audio_sources,
+            commands::audio_route::select_audio_source,
+            commands::audio_route::get_audio_route,
             commands::display::set_brightness,
+            commands::display::get_brightness,
+            commands::display::get_backlight_info,
+            commands::auto_brightness::get_auto_brightness_settings,
+            commands::auto_brightness::set_auto_brightness_settings,
+            commands::auto_brightness::get_night_mode,
+            commands::light_sensor::get_light_sensor_settings,
+            commands::light_sensor::set_light_sensor_settings,
+            commands::light_sensor::get_ambient_lux,
+            commands::illumination::get_illumination_settings,
+            commands::illumination::set_illumination_settings,
+            commands::illumination::get_headlights_on,
+            commands::gpio::set_fake_gpio_level,
+            // Display power commands
+            commands::display_power::report_user_activity,
+            commands::display_power::get_display_power_state,
+            commands::display_power::get_display_power_settings,
+            commands::display_power::set_display_power_settings,
+            // Power management commands
+            commands::power::get_power_state,
+            commands::power::get_power_settings,
+            commands::power::set_power_settings,
+            // Physical controls commands
+            commands::physical_controls::get_physical_controls_settings,
+            commands::physical_controls::set_physical_controls_settings,
+            commands::steering_buttons::get_steering_buttons_settings,
+            commands::steering_buttons::set_steering_buttons_settings,
+            commands::steering_buttons::learn_steering_button,
+            // Reverse camera commands
+            commands::reverse::get_reverse,
+            commands::reverse::get_reverse_settings,
+            commands::reverse::set_reverse_settings,
+            commands::camera::get_camera_settings,
+            commands::camera::set_camera_settings,
+            commands::camera::start_camera_preview,
+            commands::camera::stop_camera_preview,
+            // Engine telemetry commands
+            commands::power_fc::get_engine_data,
+            commands::power_fc::get_power_fc_settings,
+            commands::power_fc::set_power_fc_settings,
+            commands::wideband::get_afr_reading,
+            commands::wideband::get_wideband_settings,
+            commands::wideband::set_wideband_settings,
+            commands::analog_sensors::get_sensor_readings,
+            commands::analog_sensors::get_analog_sensors_config,
+            commands::tach::get_tach_rpm,
+            commands::tach::get_tach_settings,
+            commands::tach::set_tach_settings,
+            // Alarm commands
+            commands::alarms::get_active_alarms,
+            commands::alarms::get_alarm_log,
+            commands::alarms::clear_alarm_log,
+            commands::shift_light::get_shift_light_settings,
+            commands::shift_light::set_shift_light_settings,
+            // Lighting commands
+            commands::lighting::get_lighting_settings,
+            commands::lighting::set_lighting_settings,
+            commands::lighting::set_lighting_scene,
+            commands::lighting::set_zone_color,
+            commands::lighting::list_effects,
+            // Headlight commands
+            commands::headlights::get_headlight_status,
+            commands::headlights::headlight_command,
+            commands::headlights::get_headlight_settings,
+            commands::headlights::set_headlight_settings,
+            // Trip computer commands
+            commands::trip::get_trips,
+            commands::trip::reset_trip,
+            commands::trip::set_odometer,
+            commands::trip::get_trip_settings,
+            commands::trip::set_trip_settings,
+            // Maintenance commands
+            commands::maintenance::get_maintenance_settings,
+            commands::maintenance::set_maintenance_settings,
+            commands::maintenance::get_maintenance_status,
+            commands::maintenance::get_due_maintenance,
+            commands::maintenance::log_service,
+            commands::maintenance::get_service_history,
+            commands::maintenance::delete_service_record,
+            commands::maintenance::export_service_history,
+            commands::maintenance::import_service_history,
             commands::bluetooth::initialize_bluetooth,
             commands::bluetooth::start_bluetooth_discovery,
             commands::bluetooth::stop_bluetooth_discovery,
@@ -26,7 +146,20 @@ pub fn run() {
             commands::bluetooth::stop_bluetooth_media,
             commands::bluetooth::get_bluetooth_media_info,
             commands::bluetooth::set_bluetooth_volume,
-            commands::bluetooth::get_bluetooth_volume
+            commands::bluetooth::get_bluetooth_volume,
+            // Soundboard commands
+            commands::soundboard::list_soundboard,
+            commands::soundboard::play_soundboard_clip,
+            commands::soundboard::stop_all_clips,
+            // Visualizer commands
+            commands::spectrum::start_spectrum_analyzer,
+            commands::spectrum::stop_spectrum_analyzer,
+            // Speed-compensated volume commands
+            commands::speed_volume::get_speed_volume_settings,
+            commands::speed_volume::set_speed_volume_settings,
+            // Vehicle commands
+            commands::vehicle::get_vehicle_speed,
+            commands::vehicle::set_test_vehicle_speed
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
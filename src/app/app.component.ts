@@ -1,41 +1,57 @@
-import { Component } from '@angular/core';
+import { Component, HostBinding } from '@angular/core';
 import { CommonModule } from '@angular/common';
 import { RouterOutlet, Router } from '@angular/router';
 import { invoke } from "@tauri-apps/api/core";
 import { getCurrentWindow } from '@tauri-apps/api/window';
+import { listen } from '@tauri-apps/api/event';
+import { BluetoothService } from './services/bluetooth.service';
+import { AlarmOverlayComponent } from './components/alarm-overlay/alarm-overlay.component';
+
+interface ControlEvent {
+  action: string;
+  steps: number;
+  source: string;
+}
 
 @Component({
   selector: 'app-root',
   standalone: true,
-  imports: [CommonModule, RouterOutlet],
+  imports: [CommonModule, RouterOutlet, AlarmOverlayComponent],
   templateUrl: './app.component.html',
   styleUrl: './app.component.css'
 })
 export class AppComponent {
-  private inactivityTimer: any;
-  private readonly INACTIVITY_TIMEOUT = 120000; // 2 minutes in milliseconds
+  private lastActivityReport = 0;
+  private readonly ACTIVITY_REPORT_INTERVAL = 1000; // Idle timeouts are handled by the backend
+  private readonly SCREENS = ['/home', '/audio-settings', '/bluetooth-settings'];
+  private readonly VOLUME_STEP = 2;
+  private history: string[] = [];
+  private beforeReverse: string | null = null;
+  private reversing = false;
+
+  @HostBinding('class.night-mode') nightMode = false;
 
   log = "hello";
-  constructor(private router: Router) {
+  constructor(private router: Router, private bluetooth: BluetoothService) {
     getCurrentWindow().setCursorVisible(false);
     document.body.style.cursor = 'none';
     
-    // Reset inactivity timer on any user interaction
-    const resetTimer = () => {
-      if (this.router.url === '/home') {
-        clearTimeout(this.inactivityTimer);
-        this.inactivityTimer = setTimeout(() => {
-          this.router.navigate(['/screensaver']);
-        }, this.INACTIVITY_TIMEOUT);
+    // Let the backend know the screen is being used
+    const reportActivity = () => {
+      const now = Date.now();
+      if (now - this.lastActivityReport >= this.ACTIVITY_REPORT_INTERVAL) {
+        this.lastActivityReport = now;
+        invoke('report_user_activity');
       }
     };
 
     // Add event listeners for user interaction
     document.addEventListener('mousemove', () => {
       document.body.style.cursor = 'none';
-      resetTimer();
+      reportActivity();
     });
     document.addEventListener('mousedown', (e) => {
+      reportActivity();
       const target = e.target as HTMLElement;
       if (target.tagName === 'INPUT' || 
           target.tagName === 'BUTTON' || 
@@ -44,13 +60,103 @@ export class AppComponent {
         return;
       }
       e.preventDefault();
-      resetTimer();
     });
-    document.addEventListener('keydown', resetTimer);
-    document.addEventListener('touchstart', resetTimer);
+    document.addEventListener('keydown', reportActivity);
+    document.addEventListener('touchstart', reportActivity);
+
+    // Follow the display power state machine
+    listen<string>('display://power-state', event => this.onPowerState(event.payload));
+
+    // Switch palettes when the sun goes down
+    invoke<boolean>('get_night_mode').then(night => this.setNightMode(night));
+    listen<boolean>('theme://night-mode', event => this.setNightMode(event.payload));
+
+    // Rear camera while in reverse
+    invoke<boolean>('get_reverse').then(reverse => this.onReverse(reverse));
+    listen<boolean>('vehicle://reverse', event => this.onReverse(event.payload));
+
+    // Knob and buttons on the dash
+    listen<ControlEvent>('input://action', event => this.onControl(event.payload));
+  }
+
+  private onPowerState(state: string) {
+    if (state === 'screensaver' && this.router.url === '/home') {
+      this.router.navigate(['/screensaver']);
+    } else if (state === 'active' && this.router.url === '/screensaver') {
+      this.router.navigate(['/home']);
+    }
+  }
+
+  private async onReverse(reverse: boolean) {
+    this.reversing = reverse;
+    if (reverse && this.router.url !== '/camera') {
+      // Read on every shift so a change in settings applies straight away
+      const settings = await invoke<{ show_camera: boolean }>('get_reverse_settings')
+        .catch(() => ({ show_camera: true }));
+      // Out of reverse again while the settings were loading
+      if (!settings.show_camera || !this.reversing) {
+        return;
+      }
+      // Reversing wakes the display, so don't go back to the screensaver
+      this.beforeReverse = this.router.url === '/screensaver' ? '/home' : this.router.url;
+      this.router.navigate(['/camera']);
+    } else if (!reverse && this.beforeReverse !== null) {
+      this.router.navigateByUrl(this.beforeReverse);
+      this.beforeReverse = null;
+    }
+  }
+
+  private onControl(event: ControlEvent) {
+    switch (event.action) {
+      case 'volume_up':
+      case 'volume_down': {
+        const delta = this.VOLUME_STEP * event.steps * (event.action === 'volume_up' ? 1 : -1);
+        this.bluetooth.setVolume(Math.min(100, Math.max(0, this.bluetooth.volume + delta)));
+        break;
+      }
+      case 'next_track':
+        this.bluetooth.next();
+        break;
+      case 'previous_track':
+        this.bluetooth.previous();
+        break;
+      case 'play_pause':
+        this.bluetooth.togglePlayPause();
+        break;
+      case 'next_screen':
+        this.cycleScreen(event.steps);
+        break;
+      case 'previous_screen':
+        this.cycleScreen(-event.steps);
+        break;
+      case 'home':
+        this.navigate('/home');
+        break;
+      case 'back': {
+        const previous = this.history.pop();
+        if (previous) {
+          this.router.navigateByUrl(previous);
+        }
+        break;
+      }
+    }
+  }
+
+  private cycleScreen(offset: number) {
+    const index = Math.max(0, this.SCREENS.indexOf(this.router.url));
+    const count = this.SCREENS.length;
+    this.navigate(this.SCREENS[(((index + offset) % count) + count) % count]);
+  }
+
+  private navigate(url: string) {
+    if (url !== this.router.url) {
+      this.history.push(this.router.url);
+      this.router.navigateByUrl(url);
+    }
+  }
 
-    // Initialize timer
-    resetTimer();
+  private setNightMode(night: boolean) {
+    this.nightMode = night;
   }
 
   playSound() {
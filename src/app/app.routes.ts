@@ -4,6 +4,7 @@ import { HomeComponent } from './pages/home/home.component';
 import { AudioSettingsComponent } from './pages/audio-settings/audio-settings.component';
 import { ScreensaverComponent } from './pages/screensaver/screensaver.component';
 import { BluetoothSettingsComponent } from './pages/bluetooth-settings/bluetooth-settings.component';
+import { CameraComponent } from './pages/camera/camera.component';
 
 export const routes: Routes = [
   { path: '', component: IntroComponent },
@@ -11,4 +12,5 @@ export const routes: Routes = [
   { path: 'audio-settings', component: AudioSettingsComponent },
   { path: 'bluetooth-settings', component: BluetoothSettingsComponent },
   { path: 'screensaver', component: ScreensaverComponent },
+  { path: 'camera', component: CameraComponent },
 ];
\ No newline at end of file
@@ -0,0 +1,59 @@
+import { Component, OnInit, OnDestroy } from '@angular/core';
+import { CommonModule } from '@angular/common';
+import { invoke } from '@tauri-apps/api/core';
+import { listen, UnlistenFn } from '@tauri-apps/api/event';
+
+export interface Alarm {
+  name: string;
+  message: string;
+  severity: 'info' | 'warning' | 'critical';
+  raised_at: number;
+  // Raised again soon after it last chimed, so it stays dismissed
+  repeat: boolean;
+}
+
+@Component({
+  selector: 'app-alarm-overlay',
+  standalone: true,
+  imports: [CommonModule],
+  templateUrl: './alarm-overlay.component.html',
+  styleUrl: './alarm-overlay.component.css'
+})
+export class AlarmOverlayComponent implements OnInit, OnDestroy {
+  public alarms: Alarm[] = [];
+
+  // Dismissed on screen, shown again if the alarm clears and comes back after the chime cooldown
+  private dismissed = new Set<string>();
+  private unlisteners: UnlistenFn[] = [];
+
+  async ngOnInit() {
+    invoke<Alarm[]>('get_active_alarms')
+      .then(alarms => this.alarms = alarms)
+      .catch(error => console.error('Failed to get active alarms:', error));
+
+    this.unlisteners.push(await listen<Alarm>('alarm://raised', event => {
+      if (!event.payload.repeat) {
+        this.dismissed.delete(event.payload.name);
+      }
+      this.alarms = [...this.alarms.filter(alarm => alarm.name !== event.payload.name), event.payload];
+    }));
+    this.unlisteners.push(await listen<Alarm>('alarm://cleared', event => {
+      this.alarms = this.alarms.filter(alarm => alarm.name !== event.payload.name);
+    }));
+  }
+
+  ngOnDestroy() {
+    this.unlisteners.forEach(unlisten => unlisten());
+  }
+
+  get visible(): Alarm[] {
+    const rank = { critical: 0, warning: 1, info: 2 };
+    return this.alarms
+      .filter(alarm => !this.dismissed.has(alarm.name))
+      .sort((a, b) => rank[a.severity] - rank[b.severity]);
+  }
+
+  dismiss(alarm: Alarm) {
+    this.dismissed.add(alarm.name);
+  }
+}
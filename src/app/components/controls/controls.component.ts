@@ -19,6 +19,11 @@ export class ControlsComponent implements OnInit, OnDestroy {
   constructor(private bluetooth: BluetoothService) {}
 
   ngOnInit() {
+    // Start from the level the backlight was restored to
+    invoke<number>('get_brightness')
+      .then(level => this._brightness = level)
+      .catch(error => console.error('Failed to get brightness:', error));
+
     // Subscribe to bluetooth volume changes
     this.volumeSubscription = this.bluetooth.volume$.subscribe(vol => {
       this._volume = vol;
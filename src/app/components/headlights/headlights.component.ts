@@ -0,0 +1,76 @@
+import { Component, OnInit, OnDestroy } from '@angular/core';
+import { CommonModule } from '@angular/common';
+import { invoke } from '@tauri-apps/api/core';
+import { listen, UnlistenFn } from '@tauri-apps/api/event';
+
+export type HeadlightPosition = 'up' | 'down' | 'moving' | 'partial' | 'unknown';
+
+export interface HeadlightStatus {
+  left: HeadlightPosition;
+  right: HeadlightPosition;
+  fault: string | null;
+}
+
+export type HeadlightCommand =
+  | { command: 'up' }
+  | { command: 'down' }
+  | { command: 'wink'; side: 'left' | 'right' }
+  | { command: 'sleepy_eyes' };
+
+@Component({
+  selector: 'app-headlights',
+  standalone: true,
+  imports: [CommonModule],
+  templateUrl: './headlights.component.html',
+  styleUrl: './headlights.component.css'
+})
+export class HeadlightsComponent implements OnInit, OnDestroy {
+  // Null while relay control is turned off
+  public status: HeadlightStatus | null = null;
+  public error: string | null = null;
+
+  private unlisten: UnlistenFn | null = null;
+  private errorTimer: any;
+
+  async ngOnInit() {
+    invoke<HeadlightStatus | null>('get_headlight_status')
+      .then(status => this.status = status)
+      .catch(error => console.error('Failed to get headlight status:', error));
+
+    this.unlisten = await listen<HeadlightStatus | null>('headlights://status', event => {
+      this.status = event.payload;
+    });
+  }
+
+  ngOnDestroy() {
+    this.unlisten?.();
+    clearTimeout(this.errorTimer);
+  }
+
+  async send(command: HeadlightCommand) {
+    try {
+      await invoke('headlight_command', { command });
+    } catch (error) {
+      // Interlock refusals, e.g. moving too fast
+      this.error = String(error);
+      clearTimeout(this.errorTimer);
+      this.errorTimer = setTimeout(() => this.error = null, 3000);
+    }
+  }
+
+  get raised(): boolean {
+    return this.status?.left === 'up' && this.status?.right === 'up';
+  }
+
+  toggle() {
+    this.send({ command: this.raised ? 'down' : 'up' });
+  }
+
+  wink(side: 'left' | 'right') {
+    this.send({ command: 'wink', side });
+  }
+
+  sleepyEyes() {
+    this.send({ command: 'sleepy_eyes' });
+  }
+}
@@ -0,0 +1,72 @@
+import { Component, OnInit, OnDestroy } from '@angular/core';
+import { CommonModule } from '@angular/common';
+import { invoke } from '@tauri-apps/api/core';
+import { listen, UnlistenFn } from '@tauri-apps/api/event';
+
+export interface ServiceItem {
+  id: string;
+  name: string;
+  interval_km: number | null;
+  interval_days: number | null;
+  interval_hours: number | null;
+}
+
+export interface ItemStatus {
+  item: ServiceItem;
+  state: 'ok' | 'due_soon' | 'overdue' | 'unlogged';
+  remaining_km: number | null;
+  remaining_days: number | null;
+  remaining_hours: number | null;
+}
+
+@Component({
+  selector: 'app-maintenance-reminders',
+  standalone: true,
+  imports: [CommonModule],
+  templateUrl: './maintenance-reminders.component.html',
+  styleUrl: './maintenance-reminders.component.css'
+})
+export class MaintenanceRemindersComponent implements OnInit, OnDestroy {
+  public due: ItemStatus[] = [];
+
+  private unlisten: UnlistenFn | null = null;
+
+  async ngOnInit() {
+    invoke<ItemStatus[]>('get_due_maintenance')
+      .then(due => this.due = due)
+      .catch(error => console.error('Failed to get due maintenance:', error));
+
+    this.unlisten = await listen<ItemStatus[]>('maintenance://due', event => {
+      this.due = event.payload;
+    });
+  }
+
+  ngOnDestroy() {
+    this.unlisten?.();
+  }
+
+  // The interval closest to running out, e.g. "120 KM OVER" or "3 DAYS LEFT".
+  // Compared by how much of each interval is left, as km, days and hours don't compare.
+  remaining(status: ItemStatus): string {
+    const { item } = status;
+    const left = [
+      { value: status.remaining_km, interval: item.interval_km, unit: 'KM' },
+      { value: status.remaining_days, interval: item.interval_days, unit: 'DAYS' },
+      { value: status.remaining_hours, interval: item.interval_hours, unit: 'HRS' },
+    ].filter(left => left.value !== null && left.interval) as { value: number; interval: number; unit: string }[];
+    if (left.length === 0) {
+      return '';
+    }
+    const soonest = left.reduce((a, b) => a.value / a.interval < b.value / b.interval ? a : b);
+    const amount = Math.abs(Math.round(soonest.value));
+    return `${amount} ${soonest.unit} ${soonest.value < 0 ? 'OVER' : 'LEFT'}`;
+  }
+
+  async done(status: ItemStatus) {
+    try {
+      await invoke('log_service', { item: status.item.id, notes: '', odometerKm: null, engineHours: null, date: null });
+    } catch (error) {
+      console.error('Failed to log service:', error);
+    }
+  }
+}
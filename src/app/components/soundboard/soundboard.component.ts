@@ -1,5 +1,12 @@
-import { Component } from '@angular/core';
+import { Component, OnInit } from '@angular/core';
 import { CommonModule } from '@angular/common';
+import { invoke } from '@tauri-apps/api/core';
+
+interface SoundboardCategory {
+  name: string;
+  clips: string[];
+  gain: number;
+}
 
 @Component({
   selector: 'app-soundboard',
@@ -8,57 +15,27 @@ import { CommonModule } from '@angular/common';
   templateUrl: './soundboard.component.html',
   styleUrl: './soundboard.component.css'
 })
-export class SoundboardComponent {
+export class SoundboardComponent implements OnInit {
   // Track last 2 sounds played in each category
-  private lastPlayedSounds: { [key: string]: string[] } = {
-    music: [],
-    anime: [],
-    nostalgia: [],
-    do_not_push: []
-  };
-
-  private audio: HTMLAudioElement | null = null;
+  private lastPlayedSounds: { [key: string]: string[] } = {};
 
-  // Define available sound categories and their sounds
-  readonly soundCategories = {
-    music: [
-      'pedro-song.mp3',
-      'deja-vu_1qHyNkn.mp3',
-      'anime_song.mp3',
-      'speed.mp3',
-      'bye-bye-deadpool.mp3',
-      'celebration.mp3'
-    ],
-    anime: [
-      'tole-tole-kawaii.mp3',
-      'gmi2-salvaje.mp3',
-      'ara-ara-sayonara-mp3cut.mp3',
-      'anime-yamete01.mp3',
-      'amazed-onniich.mp3',
-      'anime_song.mp3',
-      'tuturu_1.mp3',
-      'anime-ahh.mp3',
-      'anime-wow-sound-effect.mp3'
-    ],
-    nostalgia: [
-      'windows-shutdown_lWRhnkD.mp3',
-      'ps2_start_up.mp3',
-      'windows-7-startup.mp3'
-    ],
-    do_not_push: [
-      'daddyy-chill.mp3',
-      'guest-1337-forsaken.mp3',
-      'ew-dude-wtf.mp3',
-      'metal-slug-mission-complete.mp3',
-      'danger-siren-alarm_BfknMds.mp3'
-    ]
-  };
+  // Available sound categories and their clips, scanned by the backend
+  soundCategories: { [key: string]: string[] } = {};
 
-  playSound(sound_category: string) {
-    if (this.audio) {
-      this.audio.pause();
+  async ngOnInit() {
+    try {
+      const categories = await invoke<SoundboardCategory[]>('list_soundboard');
+      categories.forEach(category => {
+        this.soundCategories[category.name] = category.clips;
+        this.lastPlayedSounds[category.name] = [];
+      });
+    } catch (error) {
+      console.error('Failed to load soundboard:', error);
     }
-    const sounds = this.soundCategories[sound_category as keyof typeof this.soundCategories];
+  }
+
+  async playSound(sound_category: string) {
+    const sounds = this.soundCategories[sound_category];
     if (sounds && sounds.length > 0) {
       // Filter out the last 2 played sounds
       const availableSounds = sounds.filter(
@@ -76,8 +53,11 @@ export class SoundboardComponent {
         ...this.lastPlayedSounds[sound_category].slice(0, 1)
       ];
 
-      this.audio = new Audio(`assets/audio/soundboard/${sound_category}/${randomSound}`);
-      this.audio.play();
+      try {
+        await invoke('play_soundboard_clip', { category: sound_category, clip: randomSound });
+      } catch (error) {
+        console.error('Failed to play sound:', error);
+      }
     }
   }
-} 
\ No newline at end of file
+}
@@ -3,6 +3,7 @@ import { CommonModule } from '@angular/common';
 import { FormsModule } from '@angular/forms';
 import { Router } from '@angular/router';
 import { invoke } from '@tauri-apps/api/core';
+import { listen, UnlistenFn } from '@tauri-apps/api/event';
 
 interface EQBand {
   name: string;
@@ -10,6 +11,21 @@ interface EQBand {
   value: number; // -12 to +12 dB
 }
 
+interface SpeedVolumeSettings {
+  enabled: boolean;
+  start_speed: number;
+  db_per_kmh: number;
+  max_boost_db: number;
+  hysteresis_kmh: number;
+  smoothing_secs: number;
+}
+
+interface AudioSettings {
+  eq: { bands: number[]; preset: string };
+  loudness: { enabled: boolean; reference_phon: number; strength: number };
+  limiter: { enabled: boolean; ceiling_db: number; lookahead_ms: number; release_ms: number };
+}
+
 interface EQPreset {
   name: string;
   icon: string;
@@ -29,6 +45,12 @@ export class AudioSettingsComponent implements OnInit, OnDestroy {
   isMuted = false;
   private previousVolume = 50;
 
+  // Backend DSP settings (EQ, loudness, limiter)
+  private audioSettings: AudioSettings | null = null;
+
+  // Speed-compensated volume
+  speedVolume: SpeedVolumeSettings | null = null;
+
   // Equalizer bands
   eqBands: EQBand[] = [
     { name: 'SUB', frequency: '32Hz', value: 0 },
@@ -62,6 +84,8 @@ export class AudioSettingsComponent implements OnInit, OnDestroy {
   
   // Animation for visualizer
   private animationFrame: number | null = null;
+  private unlistenSpectrum: UnlistenFn | null = null;
+  private spectrumLevels: number[] = new Array(32).fill(0);
   visualizerBars: number[] = new Array(32).fill(0);
 
   constructor(private router: Router) {}
@@ -75,11 +99,32 @@ export class AudioSettingsComponent implements OnInit, OnDestroy {
     if (this.animationFrame) {
       cancelAnimationFrame(this.animationFrame);
     }
+    if (this.unlistenSpectrum) {
+      this.unlistenSpectrum();
+    }
+    invoke('stop_spectrum_analyzer').catch(error => {
+      console.error('Failed to stop spectrum analyzer:', error);
+    });
   }
 
   async loadSettings() {
-    // In a real implementation, load saved settings from storage
-    // For now, use defaults
+    try {
+      this.audioSettings = await invoke<AudioSettings>('get_audio_settings');
+      this.audioSettings.eq.bands.forEach((value, index) => {
+        if (this.eqBands[index]) {
+          this.eqBands[index].value = value;
+        }
+      });
+      this.activePreset = this.audioSettings.eq.preset;
+    } catch (error) {
+      console.error('Failed to load audio settings:', error);
+    }
+
+    try {
+      this.speedVolume = await invoke<SpeedVolumeSettings>('get_speed_volume_settings');
+    } catch (error) {
+      console.error('Failed to load speed volume settings:', error);
+    }
   }
 
   async setVolume(volume: number) {
@@ -105,10 +150,22 @@ export class AudioSettingsComponent implements OnInit, OnDestroy {
     this.setVolume(this.systemVolume);
   }
 
+  async toggleSpeedVolume() {
+    if (!this.speedVolume) return;
+    const config = { ...this.speedVolume, enabled: !this.speedVolume.enabled };
+
+    try {
+      await invoke('set_speed_volume_settings', { config });
+      this.speedVolume = config;
+    } catch (error) {
+      console.error('Failed to update speed volume:', error);
+    }
+  }
+
   setEQBand(index: number, value: number) {
     this.eqBands[index].value = value;
     this.activePreset = 'CUSTOM';
-    // In a real implementation, send to audio backend
+    this.saveAudioSettings();
   }
 
   applyPreset(preset: EQPreset) {
@@ -118,6 +175,21 @@ export class AudioSettingsComponent implements OnInit, OnDestroy {
         this.eqBands[index].value = value;
       }
     });
+    this.saveAudioSettings();
+  }
+
+  private async saveAudioSettings() {
+    if (!this.audioSettings) return;
+    this.audioSettings.eq = {
+      bands: this.eqBands.map(band => band.value),
+      preset: this.activePreset
+    };
+
+    try {
+      await invoke('set_audio_settings', { config: this.audioSettings });
+    } catch (error) {
+      console.error('Failed to save audio settings:', error);
+    }
   }
 
   setBalance(value: number) {
@@ -162,16 +234,25 @@ export class AudioSettingsComponent implements OnInit, OnDestroy {
     return '#ff8800';
   }
 
-  private startVisualizer() {
+  private async startVisualizer() {
     const animate = () => {
-      // Generate random visualizer bars for aesthetic effect
+      // Ease the bars toward the latest band levels from the backend
       for (let i = 0; i < this.visualizerBars.length; i++) {
-        const target = Math.random() * 80 + 10;
+        const target = this.spectrumLevels[i] * 100;
         this.visualizerBars[i] += (target - this.visualizerBars[i]) * 0.3;
       }
       this.animationFrame = requestAnimationFrame(animate);
     };
     animate();
+
+    try {
+      this.unlistenSpectrum = await listen<number[]>('audio://spectrum', event => {
+        this.spectrumLevels = event.payload;
+      });
+      await invoke('start_spectrum_analyzer');
+    } catch (error) {
+      console.error('Failed to start spectrum analyzer:', error);
+    }
   }
 
   goBack() {
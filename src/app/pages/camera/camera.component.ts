@@ -0,0 +1,54 @@
+import { Component, OnInit, OnDestroy } from '@angular/core';
+import { CommonModule } from '@angular/common';
+import { convertFileSrc } from '@tauri-apps/api/core';
+
+@Component({
+  selector: 'app-camera',
+  standalone: true,
+  imports: [CommonModule],
+  templateUrl: './camera.component.html',
+  styleUrl: './camera.component.css'
+})
+export class CameraComponent implements OnInit, OnDestroy {
+  public frameSrc: string = '';
+  public noSignal: boolean = false;
+
+  // Each request waits for the next frame from the backend
+  private readonly frameUrl = convertFileSrc('frame', 'camera');
+  private running = false;
+
+  ngOnInit() {
+    this.running = true;
+    this.fetchFrames();
+  }
+
+  ngOnDestroy() {
+    this.running = false;
+    if (this.frameSrc) {
+      URL.revokeObjectURL(this.frameSrc);
+    }
+  }
+
+  private async fetchFrames() {
+    while (this.running) {
+      try {
+        const response = await fetch(this.frameUrl);
+        if (response.status !== 200) {
+          this.noSignal = true;
+          continue;
+        }
+
+        const frame = URL.createObjectURL(await response.blob());
+        if (this.frameSrc) {
+          URL.revokeObjectURL(this.frameSrc);
+        }
+        this.frameSrc = frame;
+        this.noSignal = false;
+      } catch (error) {
+        console.error('Failed to fetch camera frame:', error);
+        this.noSignal = true;
+        await new Promise(resolve => setTimeout(resolve, 1000));
+      }
+    }
+  }
+}
@@ -4,6 +4,8 @@ import { CommonModule } from '@angular/common';
 import { FormsModule } from '@angular/forms';
 import { ControlsComponent } from '../../components/controls/controls.component';
 import { SoundboardComponent } from '../../components/soundboard/soundboard.component';
+import { HeadlightsComponent } from '../../components/headlights/headlights.component';
+import { MaintenanceRemindersComponent } from '../../components/maintenance-reminders/maintenance-reminders.component';
 import { CrtSceneService } from '../../services/crt-scene.service';
 import { BluetoothService, MediaPlayerInfo, BluetoothDevice } from '../../services/bluetooth.service';
 import * as THREE from 'three';
@@ -11,7 +13,7 @@ import * as THREE from 'three';
 @Component({
   selector: 'app-home',
   standalone: true,
-  imports: [CommonModule, FormsModule, ControlsComponent, SoundboardComponent],
+  imports: [CommonModule, FormsModule, ControlsComponent, SoundboardComponent, HeadlightsComponent, MaintenanceRemindersComponent],
   templateUrl: './home.component.html',
   styleUrl: './home.component.css'
 })